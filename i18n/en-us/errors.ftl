@@ -1,9 +1,20 @@
+language-name = English (US)
 error-internal-server-error = Internal Server Error
-error-web-unsupported-aturi = The AT-URI is not supported.
+error-web-unsupported-aturi = { $tried ->
+        [0] The AT-URI is not supported.
+       *[other] The AT-URI is not supported by the servers tried: { $servers }{ $more ->
+            [0] {""}
+           *[other] , and { $more } more
+        }.
+    }
 error-web-invalid-aturi = The AT-URI is not valid.
+error-web-invalid-destination = The AT-URI resolved to an address that isn't secure.
+error-web-invalid-url = The URL is not valid.
+error-web-unsupported-url = The URL doesn't match any known link template.
 error-i18n-not-translated = This message not been translated
 
 # These aren't exposed to users.
 error-i18n-invalid-language = The language code is not supported.
+error-i18n-language-not-found = The language translation files were not found.
 error-i18n-resource-failed = Processing the translation resource failed.
 error-i18n-bundle-load = Processing the translation resource failed.
\ No newline at end of file
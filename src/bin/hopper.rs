@@ -1,15 +1,23 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use hopper::{
-    cache::{new_resolve_aturi_cache, new_resolve_webhostmeta_cache, ResolveWebHostMetaResult},
+    cache::Caches,
+    config::Listen,
+    fetch::{http_client_builder, HostLimitedFetcher, HttpFetcher},
     http::{
         context::{AppEngine, I18nContext, WebContext},
-        server::build_router,
+        server::{build_router, serve_unix, wait_for_shutdown},
     },
     i18n::Locales,
-    webhostmeta::WebHostMeta,
+    logging::fmt_layer,
 };
-use std::{env, str::FromStr, time::Duration};
-use tokio::net::TcpListener;
+use std::{
+    env,
+    net::SocketAddr,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::net::{TcpListener, UnixListener};
 use tokio::signal;
 use tokio_util::{sync::CancellationToken, task::TaskTracker};
 use tracing_subscriber::prelude::*;
@@ -27,14 +35,30 @@ use hopper::http::templates::reload_env;
 #[cfg(feature = "reload")]
 use hopper::i18n::reload::populate_locale;
 
+/// Owner and group may connect to the unix socket, so a reverse proxy only
+/// needs to share the group of the hopper process.
+const UNIX_SOCKET_MODE: u32 = 0o660;
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::registry()
+    #[cfg(feature = "otel")]
+    let tracer_provider = hopper::telemetry::tracer_provider()?;
+
+    let registry = tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
             std::env::var("RUST_LOG").unwrap_or_else(|_| "hopper=debug,info".into()),
         ))
-        .with(tracing_subscriber::fmt::layer().pretty())
-        .init();
+        .with(fmt_layer(
+            env::var("HOPPER_LOG_FORMAT")
+                .unwrap_or_default()
+                .try_into()?,
+            std::io::stdout,
+        ));
+
+    #[cfg(feature = "otel")]
+    let registry = registry.with(tracer_provider.as_ref().map(hopper::telemetry::layer));
+
+    registry.init();
 
     let version = hopper::config::version()?;
 
@@ -46,27 +70,18 @@ async fn main() -> Result<()> {
     });
 
     let config = hopper::config::Config::new()?;
+    config.validate()?;
 
-    let mut client_builder = reqwest::Client::builder();
-    for ca_certificate in config.certificate_bundles.as_ref() {
-        tracing::info!("Loading CA certificate: {:?}", ca_certificate);
-        let cert = std::fs::read(ca_certificate)?;
-        let cert = reqwest::Certificate::from_pem(&cert)?;
-        client_builder = client_builder.add_root_certificate(cert);
-    }
-
-    client_builder = client_builder.user_agent(config.user_agent.clone());
-    client_builder = client_builder.read_timeout(Duration::from_secs(1));
-    client_builder = client_builder.connect_timeout(Duration::from_secs(1));
-    client_builder = client_builder.timeout(Duration::from_secs(3));
-    let http_client = client_builder.build()?;
+    let http_client: Arc<dyn HttpFetcher> = Arc::new(HostLimitedFetcher::new(
+        http_client_builder(&config)?.build()?,
+        *config.max_fetches_per_host.as_ref(),
+        *config.max_fetches.as_ref(),
+    ));
 
-    let supported_languages = vec![LanguageIdentifier::from_str("en-us")?];
+    let supported_languages = config.languages.as_ref().clone();
     tracing::info!("Supported languages: {:?}", supported_languages);
 
-    let mut locales = Locales::new(supported_languages.clone());
-
-    populate_locale(&supported_languages, &mut locales)?;
+    let locales = load_locales(&supported_languages)?;
 
     #[cfg(feature = "embed")]
     let jinja = embed_env::build_env(config.external_base.clone(), config.version.clone());
@@ -74,59 +89,33 @@ async fn main() -> Result<()> {
     #[cfg(feature = "reload")]
     let jinja = reload_env::build_env(&config.external_base, &config.version);
 
-    let resolve_webfinger_cache = new_resolve_webhostmeta_cache();
-
-    resolve_webfinger_cache
-        .insert(
-            "bsky.app".to_string(),
-            ResolveWebHostMetaResult::Found(WebHostMeta::new(vec![
-                hopper::webhostmeta::Link::new("https://bsky.app/profile/{identity}", None),
-                hopper::webhostmeta::Link::new(
-                    "https://bsky.app/profile/{identity}/post/{rkey}",
-                    Some("app.bsky.feed.post"),
-                ),
-            ])),
-        )
-        .await;
-
-    resolve_webfinger_cache
-        .insert(
-            "frontpage.fyi".to_string(),
-            ResolveWebHostMetaResult::Found(WebHostMeta::new(vec![
-                hopper::webhostmeta::Link::new(
-                    "https://frontpage.fyi/post/{identity}/{rkey}",
-                    Some("fyi.unravel.frontpage.post"),
-                ),
-            ])),
-        )
-        .await;
-
-        resolve_webfinger_cache
-        .insert(
-            "whtwnd.com".to_string(),
-            ResolveWebHostMetaResult::Found(WebHostMeta::new(vec![
-                hopper::webhostmeta::Link::new(
-                    "https://whtwnd.com/{identity}/{rkey}",
-                    Some("com.whtwnd.blog.entry"),
-                ),
-            ])),
-        )
-        .await;
-
-    let resolve_aturi_cache = new_resolve_aturi_cache();
+    let tracker = TaskTracker::new();
+
+    let mut caches = Caches::from_config(&config).await?;
+    if let Some(soft_ttl) = *config.host_meta_soft_ttl.as_ref() {
+        caches = caches.stale_while_revalidate(
+            soft_ttl,
+            Arc::clone(&http_client),
+            config.resolver_base.as_ref(),
+            config.host_meta_path.as_ref(),
+            tracker.clone(),
+        );
+    }
 
     let web_context = WebContext::new(
-        config.external_base.as_str(),
+        &config,
         AppEngine::from(jinja),
-        &http_client,
-        resolve_webfinger_cache,
-        resolve_aturi_cache,
+        Arc::clone(&http_client),
+        caches.clone(),
         I18nContext::new(supported_languages, locales),
     );
 
+    web_context.seed_webhostmeta_cache().await;
+
+    hopper::prewarm::prewarm(http_client.as_ref(), &caches, &config).await;
+
     let app = build_router(web_context.clone());
 
-    let tracker = TaskTracker::new();
     let token = CancellationToken::new();
 
     {
@@ -159,23 +148,78 @@ async fn main() -> Result<()> {
     }
 
     {
-        let inner_config = config.clone();
-        let http_port = *inner_config.http_port.as_ref();
+        let web_context = web_context.clone();
+        let supported_languages = config.languages.as_ref().clone();
         let inner_token = token.clone();
-        tracker.spawn(async move {
-            let listener = TcpListener::bind(&format!("0.0.0.0:{}", http_port))
-                .await
-                .unwrap();
 
-            let shutdown_token = inner_token.clone();
-            let result = axum::serve(listener, app)
-                .with_graceful_shutdown(async move {
-                    tokio::select! {
-                        () = shutdown_token.cancelled() => { }
+        let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())
+            .expect("failed to install signal handler");
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    () = inner_token.cancelled() => break,
+                    _ = hangup.recv() => {},
+                }
+
+                tracing::info!("Reloading locales and seeds");
+                let reloaded = load_locales(&supported_languages).and_then(|locales| {
+                    hopper::config::Config::new().map(|config| (locales, config.seeds))
+                });
+                match reloaded {
+                    Ok((locales, seeds)) => web_context.reload(locales, &seeds).await,
+                    Err(err) => tracing::error!(
+                        "reload failed, keeping the loaded locales and seeds: {:?}",
+                        err
+                    ),
+                }
+            }
+        });
+    }
+
+    let listener = match &config.listen {
+        Listen::Tcp(address) => {
+            tracing::info!("Listening on tcp://{}", address);
+            Listener::Tcp(
+                TcpListener::bind(address)
+                    .await
+                    .with_context(|| format!("binding tcp://{} failed", address))?,
+            )
+        }
+        Listen::Unix(path) => {
+            tracing::info!("Listening on unix://{}", path.display());
+            Listener::Unix(bind_unix(path)?, path.clone())
+        }
+    };
+
+    {
+        let inner_token = token.clone();
+        tracker.spawn(async move {
+            let result = match listener {
+                Listener::Tcp(listener) => {
+                    let shutdown_token = inner_token.clone();
+                    axum::serve(
+                        listener,
+                        app.into_make_service_with_connect_info::<SocketAddr>(),
+                    )
+                    .with_graceful_shutdown(async move {
+                        tokio::select! {
+                            () = shutdown_token.cancelled() => { }
+                        }
+                        tracing::info!("axum graceful shutdown complete");
+                    })
+                    .await
+                    .map_err(anyhow::Error::from)
+                }
+                Listener::Unix(listener, path) => {
+                    let result = serve_unix(listener, app, inner_token.clone()).await;
+                    if let Err(err) = std::fs::remove_file(&path) {
+                        tracing::warn!("removing unix socket failed: {}", err);
                     }
-                    tracing::info!("axum graceful shutdown complete");
-                })
-                .await;
+                    tracing::info!("unix socket graceful shutdown complete");
+                    result
+                }
+            };
             if let Err(err) = result {
                 tracing::error!("axum task failed: {}", err);
             }
@@ -184,7 +228,38 @@ async fn main() -> Result<()> {
         });
     }
 
-    tracker.wait().await;
+    token.cancelled().await;
+    wait_for_shutdown(&tracker, *config.shutdown_timeout.as_ref()).await;
+
+    #[cfg(feature = "otel")]
+    if let Some(tracer_provider) = tracer_provider {
+        tracer_provider.shutdown()?;
+    }
 
     Ok(())
 }
+
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, PathBuf),
+}
+
+/// Binds the unix socket at `path`, replacing a socket left behind by an
+/// earlier run. Anything else at `path` is left alone and fails the bind.
+fn bind_unix(path: &Path) -> Result<UnixListener> {
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        std::fs::remove_file(path)
+            .with_context(|| format!("removing stale unix socket {} failed", path.display()))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("binding unix://{} failed", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(UNIX_SOCKET_MODE))
+        .with_context(|| format!("setting permissions of {} failed", path.display()))?;
+    Ok(listener)
+}
+
+fn load_locales(supported_languages: &Vec<LanguageIdentifier>) -> Result<Locales> {
+    let mut locales = Locales::new(supported_languages.clone(), supported_languages[0].clone());
+    populate_locale(supported_languages, &mut locales)?;
+    Ok(locales)
+}
@@ -1,18 +1,98 @@
 use anyhow::{anyhow, Result};
-use moka::{future::Cache, Expiry};
+use async_trait::async_trait;
+use futures_util::{stream::FuturesUnordered, FutureExt, StreamExt};
+use moka::{future::Cache, notification::RemovalCause, Expiry};
+use rand::Rng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
+    collections::HashMap,
+    future::Future,
     hash::Hasher,
-    time::{Duration, Instant},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
 };
+use tokio_util::task::TaskTracker;
+
+use tracing::{field::Empty, Instrument, Span};
 
 use crate::{
+    config::{CacheBackend, Config},
+    did::{self, DidDocument},
+    fetch::HttpFetcher,
     model::AtUri,
-    webhostmeta::{query, WebHostMeta},
+    resolve::{Resolution, ERROR_INVALID_DESTINATION},
+    webhostmeta::{query, NoMatch, WebHostMeta},
 };
 
-struct ResolveWebHostMetaExpiry;
+/// The share of a negative TTL that it is randomly lengthened or shortened
+/// by, so failures cached together don't all expire at the same time.
+pub const DEFAULT_NEGATIVE_JITTER: f64 = 0.1;
+
+const NEGATIVE_TTL: Duration = Duration::from_secs(60 * 10);
+
+/// How long a fetched host-meta document is kept in a shared store, which
+/// outlives restarts and has no capacity to evict it.
+const SHARED_FETCHED_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// The default capacity of both resolution caches. AT-URI entries count
+/// one each, and host-meta entries one per started [`WEIGHT_UNIT`].
+pub const DEFAULT_CACHE_CAPACITY: u64 = 1024 * 20;
+
+/// The estimated size, in bytes, that a host-meta entry weighs one unit per.
+const WEIGHT_UNIT: usize = 1024;
+
+/// Weighs a host-meta entry by its estimated size, so a document with many
+/// links takes more of the capacity than a small one.
+fn webhostmeta_weight(key: &str, value: &ResolveWebHostMetaResult) -> u32 {
+    let size = key.len()
+        + match value {
+            ResolveWebHostMetaResult::Found(webhostmeta)
+            | ResolveWebHostMetaResult::Fetched(webhostmeta, _) => webhostmeta.estimated_size(),
+            ResolveWebHostMetaResult::NotFound(err) => err.len(),
+        };
+    size.div_ceil(WEIGHT_UNIT).try_into().unwrap_or(u32::MAX)
+}
 
-struct ResolveAtUriExpiry;
+/// Entries removed from the host-meta cache because it was full.
+pub static WEBHOSTMETA_EVICTIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Entries removed from the AT-URI cache because it was full.
+pub static ATURI_EVICTIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns a listener that counts evictions in `evictions` and logs them, to
+/// help size the caches. Only entries evicted for capacity are counted, not
+/// expirations, explicit removals or replacements.
+fn eviction_listener<V>(
+    cache: &'static str,
+    evictions: &'static AtomicU64,
+) -> impl Fn(Arc<String>, V, RemovalCause) + Send + Sync + 'static {
+    move |key, _value, cause| {
+        if cause != RemovalCause::Size {
+            return;
+        }
+        evictions.fetch_add(1, Ordering::Relaxed);
+        tracing::debug!(cache, key = key.as_str(), ?cause, "cache entry evicted");
+    }
+}
+
+/// Returns `ttl` scaled by a random factor within `1 ± jitter`.
+fn jittered(ttl: Duration, jitter: f64) -> Duration {
+    if jitter <= 0.0 {
+        return ttl;
+    }
+    ttl.mul_f64(1.0 + rand::thread_rng().gen_range(-jitter..=jitter))
+}
+
+struct ResolveWebHostMetaExpiry {
+    negative_jitter: f64,
+}
+
+struct ResolveAtUriExpiry {
+    negative_jitter: f64,
+}
 
 impl Expiry<String, ResolveWebHostMetaResult> for ResolveWebHostMetaExpiry {
     fn expire_after_create(
@@ -21,10 +101,7 @@ impl Expiry<String, ResolveWebHostMetaResult> for ResolveWebHostMetaExpiry {
         value: &ResolveWebHostMetaResult,
         _current_time: Instant,
     ) -> Option<Duration> {
-        match value {
-            ResolveWebHostMetaResult::Found(_) => None,
-            ResolveWebHostMetaResult::NotFound(_) => Some(Duration::from_secs(60 * 10)),
-        }
+        value.ttl(self.negative_jitter)
     }
 }
 
@@ -35,56 +112,398 @@ impl Expiry<String, ResolveAtUriResult> for ResolveAtUriExpiry {
         value: &ResolveAtUriResult,
         _current_time: Instant,
     ) -> Option<Duration> {
-        match value {
-            ResolveAtUriResult::Found(_) => Some(Duration::from_secs(60 * 30)),
-            ResolveAtUriResult::NotFound(_) => Some(Duration::from_secs(60 * 10)),
-        }
+        value.ttl(self.negative_jitter)
     }
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub enum ResolveWebHostMetaResult {
-    Found(WebHostMeta),
+    /// A seeded document, which is never refreshed.
+    Found(Arc<WebHostMeta>),
+
+    /// A document fetched from the server at the given time.
+    Fetched(Arc<WebHostMeta>, SystemTime),
+
     NotFound(String),
 }
 
-#[derive(Clone, PartialEq, Eq)]
+impl ResolveWebHostMetaResult {
+    /// Returns true when the document was fetched more than `soft_ttl` ago.
+    fn is_stale(&self, soft_ttl: Duration) -> bool {
+        match self {
+            ResolveWebHostMetaResult::Fetched(_, fetched_at) => {
+                fetched_at.elapsed().is_ok_and(|elapsed| elapsed > soft_ttl)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub enum ResolveAtUriResult {
     Found(String),
     NotFound(String),
 }
 
+/// A value stored in a resolution cache, which decides how long it is kept.
+pub trait CachedValue: Clone + Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Returns how long the value is kept, or `None` to keep it until it is
+    /// evicted. Negative results are varied by `negative_jitter`.
+    fn ttl(&self, negative_jitter: f64) -> Option<Duration>;
+
+    /// Returns how long the value is kept in a shared store such as Redis.
+    /// Nothing evicts values from a shared store, so values kept until
+    /// evicted in memory may need to expire there.
+    fn shared_ttl(&self, negative_jitter: f64) -> Option<Duration> {
+        self.ttl(negative_jitter)
+    }
+}
+
+impl CachedValue for ResolveWebHostMetaResult {
+    fn ttl(&self, negative_jitter: f64) -> Option<Duration> {
+        match self {
+            ResolveWebHostMetaResult::Found(_) | ResolveWebHostMetaResult::Fetched(_, _) => None,
+            ResolveWebHostMetaResult::NotFound(_) => Some(jittered(NEGATIVE_TTL, negative_jitter)),
+        }
+    }
+
+    /// Seeded documents are written again on every start and reload, so only
+    /// fetched ones need to expire.
+    fn shared_ttl(&self, negative_jitter: f64) -> Option<Duration> {
+        match self {
+            ResolveWebHostMetaResult::Fetched(_, _) => Some(SHARED_FETCHED_TTL),
+            _ => self.ttl(negative_jitter),
+        }
+    }
+}
+
+impl CachedValue for ResolveAtUriResult {
+    fn ttl(&self, negative_jitter: f64) -> Option<Duration> {
+        match self {
+            ResolveAtUriResult::Found(_) => Some(Duration::from_secs(60 * 30)),
+            ResolveAtUriResult::NotFound(_) => Some(jittered(NEGATIVE_TTL, negative_jitter)),
+        }
+    }
+}
+
+/// The operations resolution needs from a cache, so that the in-process
+/// moka caches and a shared Redis can be used interchangeably.
+#[async_trait]
+pub trait CacheStore<V>: Send + Sync {
+    async fn get(&self, key: &str) -> Option<V>;
+
+    async fn insert(&self, key: String, value: V);
+
+    async fn invalidate(&self, key: &str);
+}
+
+#[async_trait]
+impl<V: CachedValue> CacheStore<V> for Cache<String, V> {
+    async fn get(&self, key: &str) -> Option<V> {
+        Cache::get(self, key).await
+    }
+
+    async fn insert(&self, key: String, value: V) {
+        Cache::insert(self, key, value).await
+    }
+
+    async fn invalidate(&self, key: &str) {
+        Cache::invalidate(self, key).await
+    }
+}
+
+/// Serves fetched host-meta documents older than `soft_ttl` from the wrapped
+/// store while refetching them in the background, so updated templates are
+/// picked up without delaying requests. At most one refresh runs per host, and
+/// a failed refresh keeps the stale document and isn't retried for another
+/// `soft_ttl`, so a failing server isn't refetched on every hit.
+pub struct StaleWhileRevalidate {
+    inner: Arc<dyn CacheStore<ResolveWebHostMetaResult>>,
+    soft_ttl: Duration,
+    http_client: Arc<dyn HttpFetcher>,
+    resolver_base: String,
+    host_meta_path: String,
+    tracker: TaskTracker,
+    refreshing: Arc<Mutex<HashMap<String, Refresh>>>,
+}
+
+/// The state of a host's background refresh.
+enum Refresh {
+    Running,
+    FailedAt(Instant),
+}
+
+impl StaleWhileRevalidate {
+    pub fn new(
+        inner: Arc<dyn CacheStore<ResolveWebHostMetaResult>>,
+        soft_ttl: Duration,
+        http_client: Arc<dyn HttpFetcher>,
+        resolver_base: &str,
+        host_meta_path: &str,
+        tracker: TaskTracker,
+    ) -> Self {
+        Self {
+            inner,
+            soft_ttl,
+            http_client,
+            resolver_base: resolver_base.to_string(),
+            host_meta_path: host_meta_path.to_string(),
+            tracker,
+            refreshing: Default::default(),
+        }
+    }
+
+    fn schedule_refresh(&self, hostname: &str) {
+        {
+            let mut refreshing = self.refreshing.lock().expect("refreshing lock poisoned");
+            match refreshing.get(hostname) {
+                Some(Refresh::Running) => return,
+                Some(Refresh::FailedAt(failed_at)) if failed_at.elapsed() < self.soft_ttl => return,
+                _ => {}
+            }
+            refreshing.insert(hostname.to_string(), Refresh::Running);
+        }
+
+        let inner = Arc::clone(&self.inner);
+        let http_client = Arc::clone(&self.http_client);
+        let resolver_base = self.resolver_base.clone();
+        let host_meta_path = self.host_meta_path.clone();
+        let refreshing = Arc::clone(&self.refreshing);
+        let hostname = hostname.to_string();
+        self.tracker.spawn(async move {
+            let refreshed = match query(
+                http_client.as_ref(),
+                &resolver_base,
+                &host_meta_path,
+                &hostname,
+            )
+            .await
+            {
+                Ok(webhostmeta) => {
+                    tracing::debug!(hostname, "refreshed stale host-meta");
+                    let value =
+                        ResolveWebHostMetaResult::Fetched(Arc::new(webhostmeta), SystemTime::now());
+                    inner.insert(hostname.clone(), value).await;
+                    true
+                }
+                Err(err) => {
+                    tracing::debug!(hostname, error = ?err, "host-meta refresh failed");
+                    false
+                }
+            };
+            let mut refreshing = refreshing.lock().expect("refreshing lock poisoned");
+            if refreshed {
+                refreshing.remove(&hostname);
+            } else {
+                refreshing.insert(hostname, Refresh::FailedAt(Instant::now()));
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl CacheStore<ResolveWebHostMetaResult> for StaleWhileRevalidate {
+    async fn get(&self, key: &str) -> Option<ResolveWebHostMetaResult> {
+        let value = self.inner.get(key).await?;
+        if value.is_stale(self.soft_ttl) {
+            self.schedule_refresh(key);
+        }
+        Some(value)
+    }
+
+    async fn insert(&self, key: String, value: ResolveWebHostMetaResult) {
+        self.inner.insert(key, value).await
+    }
+
+    async fn invalidate(&self, key: &str) {
+        self.inner.invalidate(key).await
+    }
+}
+
+/// The host-meta, AT-URI, and DID document caches shared by every
+/// resolution.
+#[derive(Clone)]
+pub struct Caches {
+    pub webhostmeta: Arc<dyn CacheStore<ResolveWebHostMetaResult>>,
+    pub aturi: Arc<dyn CacheStore<ResolveAtUriResult>>,
+    pub did: Cache<String, Arc<DidDocument>>,
+}
+
+impl Caches {
+    pub fn new() -> Self {
+        Self::in_memory(
+            DEFAULT_NEGATIVE_JITTER,
+            DEFAULT_CACHE_CAPACITY,
+            DEFAULT_CACHE_CAPACITY,
+        )
+    }
+
+    /// Builds in-process caches with negative TTLs jittered by
+    /// `negative_jitter` and the given capacities.
+    pub fn in_memory(negative_jitter: f64, webhostmeta_capacity: u64, aturi_capacity: u64) -> Self {
+        Self {
+            webhostmeta: Arc::new(build_resolve_webhostmeta_cache(
+                negative_jitter,
+                webhostmeta_capacity,
+            )),
+            aturi: Arc::new(build_resolve_aturi_cache(negative_jitter, aturi_capacity)),
+            did: new_did_document_cache(),
+        }
+    }
+
+    /// Builds the caches selected by `HOPPER_CACHE_BACKEND`.
+    pub async fn from_config(config: &Config) -> Result<Self> {
+        let negative_jitter = *config.negative_cache_jitter.as_ref();
+        match &config.cache_backend {
+            CacheBackend::Memory => Ok(Self::in_memory(
+                negative_jitter,
+                *config.host_meta_cache_capacity.as_ref(),
+                *config.aturi_cache_capacity.as_ref(),
+            )),
+            #[cfg(feature = "redis")]
+            CacheBackend::Redis(url) => Self::redis(url, negative_jitter).await,
+            #[cfg(not(feature = "redis"))]
+            CacheBackend::Redis(_) => Err(anyhow!(
+                "HOPPER_CACHE_BACKEND is a Redis URL, but hopper was built without the redis feature"
+            )),
+        }
+    }
+
+    /// Wraps the host-meta cache so documents fetched more than `soft_ttl`
+    /// ago are refreshed in the background on `tracker`.
+    pub fn stale_while_revalidate(
+        mut self,
+        soft_ttl: Duration,
+        http_client: Arc<dyn HttpFetcher>,
+        resolver_base: &str,
+        host_meta_path: &str,
+        tracker: TaskTracker,
+    ) -> Self {
+        self.webhostmeta = Arc::new(StaleWhileRevalidate::new(
+            self.webhostmeta,
+            soft_ttl,
+            http_client,
+            resolver_base,
+            host_meta_path,
+            tracker,
+        ));
+        self
+    }
+
+    /// Builds caches whose host-meta and AT-URI results are shared through
+    /// the Redis server at `url`, so that replicas don't each query origins.
+    /// DID documents stay in process.
+    #[cfg(feature = "redis")]
+    pub async fn redis(url: &str, negative_jitter: f64) -> Result<Self> {
+        use crate::cache_redis::RedisStore;
+
+        let client = redis::Client::open(url)?;
+        let connection = client.get_connection_manager().await?;
+        Ok(Self {
+            webhostmeta: Arc::new(RedisStore::new(
+                connection.clone(),
+                "hopper:webhostmeta:",
+                negative_jitter,
+            )),
+            aturi: Arc::new(RedisStore::new(
+                connection,
+                "hopper:aturi:",
+                negative_jitter,
+            )),
+            did: new_did_document_cache(),
+        })
+    }
+}
+
+impl Default for Caches {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub fn new_resolve_webhostmeta_cache() -> Cache<String, ResolveWebHostMetaResult> {
-    let expiry = ResolveWebHostMetaExpiry;
+    build_resolve_webhostmeta_cache(DEFAULT_NEGATIVE_JITTER, DEFAULT_CACHE_CAPACITY)
+}
+
+fn build_resolve_webhostmeta_cache(
+    negative_jitter: f64,
+    max_capacity: u64,
+) -> Cache<String, ResolveWebHostMetaResult> {
+    let expiry = ResolveWebHostMetaExpiry { negative_jitter };
     Cache::builder()
-        .max_capacity(1024 * 20)
+        .max_capacity(max_capacity)
+        .weigher(|key: &String, value| webhostmeta_weight(key, value))
         .expire_after(expiry)
+        .eviction_listener(eviction_listener("webhostmeta", &WEBHOSTMETA_EVICTIONS))
         .build()
 }
 
 pub fn new_resolve_aturi_cache() -> Cache<String, ResolveAtUriResult> {
-    let expiry = ResolveAtUriExpiry;
+    build_resolve_aturi_cache(DEFAULT_NEGATIVE_JITTER, DEFAULT_CACHE_CAPACITY)
+}
+
+fn build_resolve_aturi_cache(
+    negative_jitter: f64,
+    max_capacity: u64,
+) -> Cache<String, ResolveAtUriResult> {
+    let expiry = ResolveAtUriExpiry { negative_jitter };
     Cache::builder()
-        .max_capacity(1024 * 20)
+        .max_capacity(max_capacity)
         .expire_after(expiry)
+        .eviction_listener(eviction_listener("aturi", &ATURI_EVICTIONS))
         .build()
 }
 
-pub(crate) async fn webhostmeta_cached(
-    cache: &Cache<String, ResolveWebHostMetaResult>,
-    http_client: &reqwest::Client,
+/// DID documents change rarely, but key rotation and PDS migration should be
+/// picked up within the hour.
+pub fn new_did_document_cache() -> Cache<String, Arc<DidDocument>> {
+    Cache::builder()
+        .max_capacity(1024 * 20)
+        .time_to_live(Duration::from_secs(60 * 60))
+        .build()
+}
+
+/// Returns the DID document of `did`, fetching it when it isn't cached.
+/// Failed fetches are not cached.
+pub async fn did_document_cached<F: HttpFetcher + ?Sized>(
+    cache: &Cache<String, Arc<DidDocument>>,
+    http_client: &F,
+    config: &Config,
+    did: &str,
+) -> Result<Arc<DidDocument>> {
+    if let Some(document) = cache.get(did).await {
+        return Ok(document);
+    }
+    let document = Arc::new(did::query(http_client, config, did).await?);
+    cache.insert(did.to_string(), document.clone()).await;
+    Ok(document)
+}
+
+pub(crate) async fn webhostmeta_cached<F: HttpFetcher + ?Sized>(
+    cache: &dyn CacheStore<ResolveWebHostMetaResult>,
+    http_client: &F,
+    resolver_base: &str,
+    host_meta_path: &str,
     hostname: &str,
-) -> Result<WebHostMeta> {
+) -> Result<Arc<WebHostMeta>> {
     if let Some(resolve_handle_result) = cache.get(hostname).await {
         return match resolve_handle_result {
-            ResolveWebHostMetaResult::Found(webhostmeta) => Ok(webhostmeta),
+            ResolveWebHostMetaResult::Found(webhostmeta)
+            | ResolveWebHostMetaResult::Fetched(webhostmeta, _) => Ok(webhostmeta),
             ResolveWebHostMetaResult::NotFound(err) => Err(anyhow!(err)),
         };
     }
-    let webfinger = query(http_client, hostname).await;
+    let span = tracing::info_span!("fetch_host_meta", host = hostname, outcome = Empty);
+    let webfinger = query(http_client, resolver_base, host_meta_path, hostname)
+        .instrument(span.clone())
+        .await
+        .map(Arc::new);
+    span.record("outcome", if webfinger.is_ok() { "found" } else { "error" });
 
     let cache_value = match webfinger.as_ref() {
-        Ok(webfinger) => ResolveWebHostMetaResult::Found(webfinger.clone()),
+        Ok(webfinger) => {
+            ResolveWebHostMetaResult::Fetched(Arc::clone(webfinger), SystemTime::now())
+        }
         Err(err) => ResolveWebHostMetaResult::NotFound(err.to_string()),
     };
 
@@ -92,56 +511,710 @@ pub(crate) async fn webhostmeta_cached(
     webfinger
 }
 
-pub(crate) async fn aturi_cached(
-    http_client: &reqwest::Client,
-    webfinger_cache: &Cache<String, ResolveWebHostMetaResult>,
-    aturi_cache: &Cache<String, ResolveAtUriResult>,
-    servers: &Vec<String>,
+#[tracing::instrument(
+    name = "resolve_aturi",
+    skip_all,
+    fields(aturi = Empty, host = Empty, outcome = Empty)
+)]
+pub(crate) async fn aturi_cached<F: HttpFetcher + ?Sized>(
+    http_client: &F,
+    webfinger_cache: &dyn CacheStore<ResolveWebHostMetaResult>,
+    aturi_cache: &dyn CacheStore<ResolveAtUriResult>,
+    config: &Config,
+    servers: &[String],
     aturi_input: &str,
     aturi: &AtUri,
-) -> Result<String> {
+) -> Result<Resolution> {
+    let server_timeout = *config.server_timeout.as_ref();
+    let rels = config.link_rels.as_ref();
+
+    // The key is built from the validated AT-URI, so spellings of the same
+    // AT-URI, such as a Unicode handle and its punycode form, share an entry.
     let mut hasher = cityhasher::CityHasher::new();
-    hasher.write(aturi_input.as_bytes());
+    hasher.write(aturi.identity.as_bytes());
+    for segment in aturi.collection.iter().chain(aturi.rkey.iter()) {
+        hasher.write(b"/");
+        hasher.write(segment.as_bytes());
+    }
     for server in servers {
         hasher.write(server.as_bytes());
     }
     let cache_key = hasher.finish().to_string();
 
+    let span = Span::current();
+    // The AT-URI is only recorded where the access log would show it too.
+    if *config.access_log_aturi.as_ref() {
+        span.record("aturi", aturi_input);
+    }
+
     if let Some(resolve_handle_result) = aturi_cache.get(&cache_key).await {
+        span.record("outcome", "cached");
         return match resolve_handle_result {
-            ResolveAtUriResult::Found(destination) => Ok(destination),
+            ResolveAtUriResult::Found(destination) => Ok(Resolution {
+                destination,
+                matched_server: None,
+                collection: aturi.collection.clone(),
+                rel: None,
+                template: None,
+            }),
             ResolveAtUriResult::NotFound(err) => Err(anyhow!(err)),
         };
     }
 
-    for server in servers {
-        let webfinger = webhostmeta_cached(webfinger_cache, http_client, server).await;
+    // Attempts that time out are transient, so neither the host-meta lookup nor
+    // the overall unsupported result are cached when one occurs.
+    let timed_out = AtomicBool::new(false);
+    let timed_out = &timed_out;
+    let insecure_destination = &AtomicBool::new(false);
+
+    let resolution = first_match(servers, |server| async move {
+        if !config.is_host_allowed(server) {
+            tracing::debug!(server, "server not allowed");
+            return None;
+        }
+
+        let webfinger = tokio::time::timeout(
+            server_timeout,
+            webhostmeta_cached(
+                webfinger_cache,
+                http_client,
+                config.resolver_base.as_ref(),
+                config.host_meta_path.as_ref(),
+                server,
+            ),
+        )
+        .await;
+
+        let Ok(webfinger) = webfinger else {
+            tracing::debug!(server, "server timed out");
+            timed_out.store(true, Ordering::Relaxed);
+            return None;
+        };
 
         if let Err(err) = webfinger {
-            tracing::debug!(error = ?err, "error encountered");
-            continue;
+            tracing::debug!(error = ?err, server, "error encountered");
+            return None;
         }
 
-        let webfinger = webfinger.unwrap();
+        match webfinger.unwrap().match_uri(server, aturi, rels) {
+            Ok(resolution) => Some(resolution),
+            Err(NoMatch::InsecureDestination) => {
+                tracing::debug!(server, "insecure destination");
+                insecure_destination.store(true, Ordering::Relaxed);
+                None
+            }
+            Err(NoMatch::Unsupported) => {
+                tracing::debug!(server, "no destination found");
+                None
+            }
+        }
+    })
+    .await;
 
-        let destination = webfinger.match_uri(server, aturi);
-        if destination.is_none() {
-            tracing::debug!("no destination found");
+    if let Some(resolution) = resolution {
+        span.record("host", resolution.matched_server.as_deref());
+        span.record("outcome", "found");
+        aturi_cache
+            .insert(
+                cache_key,
+                ResolveAtUriResult::Found(resolution.destination.clone()),
+            )
+            .await;
+        return Ok(resolution);
+    }
+
+    span.record("outcome", "not_found");
+    // A server whose link applied but pointed elsewhere makes the AT-URI's
+    // destination definitively invalid rather than unsupported.
+    let err = if insecure_destination.load(Ordering::Relaxed) {
+        anyhow!(ERROR_INVALID_DESTINATION)
+    } else {
+        anyhow!("error-web-unsupported-aturi Unsupported AT-URI")
+    };
+    if !timed_out.load(Ordering::Relaxed) {
+        aturi_cache
+            .insert(cache_key, ResolveAtUriResult::NotFound(err.to_string()))
+            .await;
+    }
+
+    Err(err)
+}
+
+/// Runs `attempt` against every server concurrently and returns the first
+/// successful result. When several attempts have completed by the time a
+/// match is found, the one for the server listed first wins. Attempts still in
+/// flight are dropped once a result is returned.
+async fn first_match<'a, T, F, Fut>(servers: &'a [String], attempt: F) -> Option<T>
+where
+    F: Fn(&'a str) -> Fut,
+    Fut: Future<Output = Option<T>>,
+{
+    let mut pending = servers
+        .iter()
+        .enumerate()
+        .map(|(index, server)| attempt(server).map(move |result| (index, result)))
+        .collect::<FuturesUnordered<_>>();
+
+    while let Some((index, result)) = pending.next().await {
+        let Some(mut destination) = result else {
             continue;
+        };
+
+        let mut matched_index = index;
+        while let Some(Some((index, result))) = pending.next().now_or_never() {
+            if let Some(result) = result {
+                if index < matched_index {
+                    matched_index = index;
+                    destination = result;
+                }
+            }
         }
 
-        let destination = destination.unwrap();
+        return Some(destination);
+    }
 
-        aturi_cache
-            .insert(cache_key, ResolveAtUriResult::Found(destination.clone()))
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use moka::{notification::RemovalCause, Expiry};
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+    use tokio::net::TcpListener;
+
+    use super::{
+        aturi_cached, build_resolve_aturi_cache, build_resolve_webhostmeta_cache,
+        did_document_cached, eviction_listener, first_match, new_did_document_cache,
+        new_resolve_aturi_cache, new_resolve_webhostmeta_cache, webhostmeta_cached,
+        webhostmeta_weight, CacheStore, CachedValue, ResolveAtUriExpiry, ResolveAtUriResult,
+        ResolveWebHostMetaExpiry, ResolveWebHostMetaResult, StaleWhileRevalidate, ATURI_EVICTIONS,
+        NEGATIVE_TTL, SHARED_FETCHED_TTL,
+    };
+    use crate::{
+        config::Config,
+        fetch::tests::MockFetcher,
+        model::{validate_aturi, DEFAULT_RESERVED_SUFFIXES},
+        webhostmeta::{Link, WebHostMeta, DEFAULT_HOST_META_PATH},
+    };
+
+    #[tokio::test]
+    async fn test_first_match_does_not_wait_for_slow_servers() {
+        let servers = vec!["slow.example".to_string(), "fast.example".to_string()];
+
+        let started = Instant::now();
+        let destination = first_match(&servers, |server| async move {
+            if server == "slow.example" {
+                tokio::time::sleep(Duration::from_secs(2)).await;
+                return None;
+            }
+            Some(format!("https://{}/", server))
+        })
+        .await;
+
+        assert_eq!(destination, Some("https://fast.example/".to_string()));
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_first_match_prefers_server_order() {
+        let servers = vec!["first.example".to_string(), "second.example".to_string()];
+
+        let destination = first_match(&servers, |server| async move {
+            Some(format!("https://{}/", server))
+        })
+        .await;
+
+        assert_eq!(destination, Some("https://first.example/".to_string()));
+
+        let destination: Option<String> =
+            first_match(&servers, |_server| async move { None }).await;
+        assert_eq!(destination, None);
+    }
+
+    #[tokio::test]
+    async fn test_aturi_cached_abandons_hanging_server() {
+        // Accept proxied connections and never answer them.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.push(stream);
+            }
+        });
+
+        let http_client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::all(proxy).unwrap())
+            .build()
+            .unwrap();
+        let webfinger_cache = new_resolve_webhostmeta_cache();
+        let aturi_cache = new_resolve_aturi_cache();
+
+        let aturi_input = "at://ngerakines.me";
+        let aturi =
+            validate_aturi(aturi_input, &HashMap::new(), &DEFAULT_RESERVED_SUFFIXES).unwrap();
+        let servers = vec!["hangs.example".to_string()];
+
+        let started = Instant::now();
+        let destination = aturi_cached(
+            &http_client,
+            &webfinger_cache,
+            &aturi_cache,
+            &Config::from_toml(
+                r#"
+external_base = "hopper.test"
+server_timeout = 100
+"#,
+            )
+            .unwrap(),
+            &servers,
+            aturi_input,
+            &aturi,
+        )
+        .await;
+
+        assert!(destination.is_err());
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(webfinger_cache.get("hangs.example").await.is_none());
+        aturi_cache.run_pending_tasks().await;
+        assert_eq!(aturi_cache.entry_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_webhostmeta_cached_shares_allocation() {
+        let http_client = reqwest::Client::new();
+        let webfinger_cache = new_resolve_webhostmeta_cache();
+        webfinger_cache
+            .insert(
+                "bsky.app".to_string(),
+                ResolveWebHostMetaResult::Found(Arc::new(WebHostMeta::new(vec![Link::new(
+                    "https://bsky.app/profile/{identity}",
+                    None,
+                )]))),
+            )
             .await;
-        return Ok(destination);
+
+        let first = webhostmeta_cached(
+            &webfinger_cache,
+            &http_client,
+            "https://{host}",
+            DEFAULT_HOST_META_PATH,
+            "bsky.app",
+        )
+        .await
+        .unwrap();
+        let second = webhostmeta_cached(
+            &webfinger_cache,
+            &http_client,
+            "https://{host}",
+            DEFAULT_HOST_META_PATH,
+            "bsky.app",
+        )
+        .await
+        .unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
     }
 
-    let err = anyhow!("error-web-unsupported-aturi Unsupported AT-URI");
-    aturi_cache
-        .insert(cache_key, ResolveAtUriResult::NotFound(err.to_string()))
+    #[tokio::test]
+    async fn test_aturi_cached_host_lists() {
+        let http_client = reqwest::Client::new();
+        let webfinger_cache = new_resolve_webhostmeta_cache();
+        for server in ["allowed.example", "denied.example"] {
+            webfinger_cache
+                .insert(
+                    server.to_string(),
+                    ResolveWebHostMetaResult::Found(Arc::new(WebHostMeta::new(vec![Link::new(
+                        &format!("https://{}/{{identity}}", server),
+                        None,
+                    )]))),
+                )
+                .await;
+        }
+
+        let aturi_input = "at://ngerakines.me";
+        let aturi =
+            validate_aturi(aturi_input, &HashMap::new(), &DEFAULT_RESERVED_SUFFIXES).unwrap();
+        let servers = vec!["denied.example".to_string(), "allowed.example".to_string()];
+
+        let config = Config::from_toml(
+            r#"
+external_base = "hopper.test"
+host_denylist = ["denied.example"]
+"#,
+        )
+        .unwrap();
+        let destination = aturi_cached(
+            &http_client,
+            &webfinger_cache,
+            &new_resolve_aturi_cache(),
+            &config,
+            &servers,
+            aturi_input,
+            &aturi,
+        )
         .await;
+        assert_eq!(
+            destination.unwrap().destination,
+            "https://allowed.example/ngerakines.me"
+        );
 
-    Err(err)
+        let config = Config::from_toml(
+            r#"
+external_base = "hopper.test"
+host_allowlist = ["allowed.example"]
+host_denylist = ["allowed.example"]
+"#,
+        )
+        .unwrap();
+        let destination = aturi_cached(
+            &http_client,
+            &webfinger_cache,
+            &new_resolve_aturi_cache(),
+            &config,
+            &servers,
+            aturi_input,
+            &aturi,
+        )
+        .await;
+        // The allowlist takes precedence over the denylist.
+        assert_eq!(
+            destination.unwrap().destination,
+            "https://allowed.example/ngerakines.me"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_aturi_cached_mock_fetcher() {
+        let http_fetcher = MockFetcher::new([
+            (
+                "https://links.example/.well-known/host-meta.json",
+                r#"{
+  "links": [
+    {
+      "rel": "http://hopper.at/rel/link",
+      "template": "https://links.example/{identity}/{rkey}",
+      "properties": { "http://hopper.at/ns/collection": "example.links.entry" }
+    }
+  ]
+}"#,
+            ),
+            ("https://empty.example/.well-known/host-meta.json", "{}"),
+        ]);
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+        let webfinger_cache = new_resolve_webhostmeta_cache();
+
+        let aturi_input = "at://alice.example/example.links.entry/abc";
+        let aturi =
+            validate_aturi(aturi_input, &HashMap::new(), &DEFAULT_RESERVED_SUFFIXES).unwrap();
+        let servers = vec!["empty.example".to_string(), "links.example".to_string()];
+
+        let resolution = aturi_cached(
+            &http_fetcher,
+            &webfinger_cache,
+            &new_resolve_aturi_cache(),
+            &config,
+            &servers,
+            aturi_input,
+            &aturi,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            resolution.destination,
+            "https://links.example/alice.example/abc"
+        );
+        assert_eq!(resolution.matched_server.as_deref(), Some("links.example"));
+        assert_eq!(http_fetcher.requests.lock().unwrap().len(), 2);
+        assert!(webfinger_cache.get("empty.example").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_did_document_cached() {
+        let http_fetcher = MockFetcher::new([(
+            "https://alice.example/.well-known/did.json",
+            r#"{ "id": "did:web:alice.example" }"#,
+        )]);
+        let cache = new_did_document_cache();
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+
+        for _ in 0..2 {
+            let document =
+                did_document_cached(&cache, &http_fetcher, &config, "did:web:alice.example")
+                    .await
+                    .unwrap();
+            assert_eq!(document.id, "did:web:alice.example");
+        }
+        assert_eq!(http_fetcher.requests.lock().unwrap().len(), 1);
+
+        assert!(
+            did_document_cached(&cache, &http_fetcher, &config, "did:web:bob.example")
+                .await
+                .is_err()
+        );
+        assert!(cache.get("did:web:bob.example").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_aturi_cached_idn_shares_entry() {
+        let http_fetcher = MockFetcher::new([(
+            "https://links.example/.well-known/host-meta.json",
+            r#"{
+  "links": [
+    { "rel": "http://hopper.at/rel/link", "template": "https://links.example/{identity}" }
+  ]
+}"#,
+        )]);
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+        let webfinger_cache = new_resolve_webhostmeta_cache();
+        let aturi_cache = new_resolve_aturi_cache();
+        let servers = vec!["links.example".to_string()];
+
+        for aturi_input in ["at://café.example", "at://xn--caf-dma.example"] {
+            let aturi =
+                validate_aturi(aturi_input, &HashMap::new(), &DEFAULT_RESERVED_SUFFIXES).unwrap();
+            let resolution = aturi_cached(
+                &http_fetcher,
+                &webfinger_cache,
+                &aturi_cache,
+                &config,
+                &servers,
+                aturi_input,
+                &aturi,
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                resolution.destination,
+                "https://links.example/xn--caf-dma.example"
+            );
+        }
+
+        aturi_cache.run_pending_tasks().await;
+        assert_eq!(aturi_cache.entry_count(), 1);
+    }
+
+    #[test]
+    fn test_negative_ttl_jitter() {
+        let expiry = ResolveAtUriExpiry {
+            negative_jitter: 0.1,
+        };
+        let value = ResolveAtUriResult::NotFound("not found".to_string());
+        let key = "at://alice.example".to_string();
+        let first = expiry
+            .expire_after_create(&key, &value, Instant::now())
+            .unwrap();
+        let second = expiry
+            .expire_after_create(&key, &value, Instant::now())
+            .unwrap();
+        assert_ne!(first, second);
+        for ttl in [first, second] {
+            assert!(ttl >= NEGATIVE_TTL.mul_f64(0.9), "{:?}", ttl);
+            assert!(ttl <= NEGATIVE_TTL.mul_f64(1.1), "{:?}", ttl);
+        }
+
+        // Positive entries keep their fixed TTL.
+        let value = ResolveAtUriResult::Found("https://bsky.app/".to_string());
+        assert_eq!(
+            expiry.expire_after_create(&key, &value, Instant::now()),
+            Some(Duration::from_secs(60 * 30))
+        );
+
+        // Without jitter, negative entries expire after exactly the TTL.
+        let expiry = ResolveWebHostMetaExpiry {
+            negative_jitter: 0.0,
+        };
+        let value = ResolveWebHostMetaResult::NotFound("not found".to_string());
+        assert_eq!(
+            expiry.expire_after_create(&"links.example".to_string(), &value, Instant::now()),
+            Some(NEGATIVE_TTL)
+        );
+    }
+
+    #[test]
+    fn test_shared_ttl() {
+        let webhostmeta = Arc::new(WebHostMeta::new(vec![]));
+        let fetched = ResolveWebHostMetaResult::Fetched(
+            Arc::clone(&webhostmeta),
+            std::time::SystemTime::now(),
+        );
+        assert_eq!(fetched.ttl(0.0), None);
+        assert_eq!(fetched.shared_ttl(0.0), Some(SHARED_FETCHED_TTL));
+
+        let seeded = ResolveWebHostMetaResult::Found(webhostmeta);
+        assert_eq!(seeded.shared_ttl(0.0), None);
+        let not_found = ResolveWebHostMetaResult::NotFound("not found".to_string());
+        assert_eq!(not_found.shared_ttl(0.0), Some(NEGATIVE_TTL));
+    }
+
+    #[tokio::test]
+    async fn test_stale_while_revalidate() {
+        let http_fetcher = Arc::new(MockFetcher::new([(
+            "https://links.example/.well-known/host-meta.json",
+            r#"{"links": [{"rel": "http://hopper.at/rel/link", "template": "https://links.example/{rkey}"}]}"#,
+        )]));
+        let inner = Arc::new(new_resolve_webhostmeta_cache());
+        let tracker = tokio_util::task::TaskTracker::new();
+        let cache = StaleWhileRevalidate::new(
+            inner.clone(),
+            Duration::from_secs(60 * 60),
+            http_fetcher.clone(),
+            "https://{host}",
+            DEFAULT_HOST_META_PATH,
+            tracker.clone(),
+        );
+
+        let stale = ResolveWebHostMetaResult::Fetched(
+            Arc::new(WebHostMeta::new(vec![])),
+            std::time::SystemTime::now() - Duration::from_secs(2 * 60 * 60),
+        );
+        cache
+            .insert("links.example".to_string(), stale.clone())
+            .await;
+
+        // The stale document is returned without waiting for the refresh, and
+        // only one refresh is scheduled for repeated hits.
+        assert_eq!(cache.get("links.example").await, Some(stale.clone()));
+        assert_eq!(cache.get("links.example").await, Some(stale));
+        assert!(http_fetcher.requests.lock().unwrap().is_empty());
+        assert_eq!(tracker.len(), 1);
+
+        tracker.close();
+        tracker.wait().await;
+        assert_eq!(http_fetcher.requests.lock().unwrap().len(), 1);
+        let Some(ResolveWebHostMetaResult::Fetched(webhostmeta, _)) =
+            inner.get("links.example").await
+        else {
+            panic!("refreshed document missing");
+        };
+        assert_eq!(webhostmeta.links.len(), 1);
+
+        // Seeded documents are never refreshed.
+        let seeded = ResolveWebHostMetaResult::Found(Arc::new(WebHostMeta::new(vec![])));
+        cache
+            .insert("seeded.example".to_string(), seeded.clone())
+            .await;
+        assert_eq!(cache.get("seeded.example").await, Some(seeded));
+        assert_eq!(http_fetcher.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stale_while_revalidate_failed_refresh() {
+        let http_fetcher = Arc::new(MockFetcher::new([]));
+        let inner = Arc::new(new_resolve_webhostmeta_cache());
+        let tracker = tokio_util::task::TaskTracker::new();
+        let cache = StaleWhileRevalidate::new(
+            inner.clone(),
+            Duration::from_secs(60 * 60),
+            http_fetcher.clone(),
+            "https://{host}",
+            DEFAULT_HOST_META_PATH,
+            tracker.clone(),
+        );
+
+        let stale = ResolveWebHostMetaResult::Fetched(
+            Arc::new(WebHostMeta::new(vec![])),
+            std::time::SystemTime::now() - Duration::from_secs(2 * 60 * 60),
+        );
+        cache
+            .insert("links.example".to_string(), stale.clone())
+            .await;
+
+        assert_eq!(cache.get("links.example").await, Some(stale.clone()));
+        while !tracker.is_empty() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(http_fetcher.requests.lock().unwrap().len(), 1);
+
+        // The stale document is kept, and the failed host isn't refetched
+        // until another soft TTL has passed.
+        assert_eq!(cache.get("links.example").await, Some(stale));
+        assert!(tracker.is_empty());
+        assert_eq!(http_fetcher.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_eviction_listener() {
+        let aturi_cache = build_resolve_aturi_cache(0.0, 4);
+        let before = ATURI_EVICTIONS.load(Ordering::Relaxed);
+
+        for index in 0..16 {
+            aturi_cache
+                .insert(
+                    index.to_string(),
+                    ResolveAtUriResult::Found(format!("https://links.example/{}", index)),
+                )
+                .await;
+            aturi_cache.run_pending_tasks().await;
+        }
+
+        assert!(aturi_cache.entry_count() <= 4);
+        assert!(ATURI_EVICTIONS.load(Ordering::Relaxed) - before >= 12);
+
+        // Only evictions for capacity are counted.
+        static EVICTIONS: AtomicU64 = AtomicU64::new(0);
+        let listener = eviction_listener::<()>("test", &EVICTIONS);
+        for cause in [
+            RemovalCause::Expired,
+            RemovalCause::Explicit,
+            RemovalCause::Replaced,
+            RemovalCause::Size,
+        ] {
+            listener(Arc::new("key".to_string()), (), cause);
+        }
+        assert_eq!(EVICTIONS.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_webhostmeta_weight() {
+        let small = ResolveWebHostMetaResult::Found(Arc::new(WebHostMeta::new(vec![Link::new(
+            "https://small.example/{rkey}",
+            None,
+        )])));
+        let large = ResolveWebHostMetaResult::Found(Arc::new(WebHostMeta::new(
+            (0..64)
+                .map(|index| {
+                    Link::new(
+                        &format!("https://large.example/{}/{{identity}}/{{rkey}}", index),
+                        Some("example.links.entry"),
+                    )
+                })
+                .collect(),
+        )));
+        assert_eq!(webhostmeta_weight("small.example", &small), 1);
+        assert!(webhostmeta_weight("large.example", &large) > 4);
+
+        // The large document doesn't fit alongside the frequently used small
+        // ones, so it is the entry that gets evicted.
+        let webhostmeta_cache = build_resolve_webhostmeta_cache(0.0, 8);
+        for index in 0..4 {
+            let hostname = format!("small{}.example", index);
+            webhostmeta_cache
+                .insert(hostname.clone(), small.clone())
+                .await;
+            for _ in 0..4 {
+                webhostmeta_cache.get(&hostname).await;
+            }
+        }
+        webhostmeta_cache
+            .insert("large.example".to_string(), large)
+            .await;
+        webhostmeta_cache.run_pending_tasks().await;
+
+        assert!(webhostmeta_cache.get("large.example").await.is_none());
+        for index in 0..4 {
+            assert!(webhostmeta_cache
+                .get(&format!("small{}.example", index))
+                .await
+                .is_some());
+        }
+    }
 }
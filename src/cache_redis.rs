@@ -0,0 +1,148 @@
+use async_trait::async_trait;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use std::marker::PhantomData;
+
+use crate::cache::{CacheStore, CachedValue};
+
+/// A cache kept in Redis, with values stored as JSON under `prefix` and
+/// expiring after their [`CachedValue::shared_ttl`]. Redis errors are
+/// logged and treated as cache misses, so an unavailable Redis only costs
+/// extra lookups.
+pub struct RedisStore<V> {
+    connection: ConnectionManager,
+    prefix: &'static str,
+    negative_jitter: f64,
+    value: PhantomData<fn() -> V>,
+}
+
+impl<V> RedisStore<V> {
+    pub fn new(connection: ConnectionManager, prefix: &'static str, negative_jitter: f64) -> Self {
+        Self {
+            connection,
+            prefix,
+            negative_jitter,
+            value: PhantomData,
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+#[async_trait]
+impl<V: CachedValue> CacheStore<V> for RedisStore<V> {
+    async fn get(&self, key: &str) -> Option<V> {
+        let mut connection = self.connection.clone();
+        let value: Option<String> = match connection.get(self.key(key)).await {
+            Ok(value) => value,
+            Err(err) => {
+                tracing::warn!(key, error = ?err, "redis get failed");
+                return None;
+            }
+        };
+        match serde_json::from_str(&value?) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                tracing::warn!(key, error = ?err, "redis value parse failed");
+                None
+            }
+        }
+    }
+
+    async fn insert(&self, key: String, value: V) {
+        let serialized = match serde_json::to_string(&value) {
+            Ok(serialized) => serialized,
+            Err(err) => {
+                tracing::warn!(key, error = ?err, "redis value serialize failed");
+                return;
+            }
+        };
+        let mut connection = self.connection.clone();
+        let result: redis::RedisResult<()> = match value.shared_ttl(self.negative_jitter) {
+            // Redis expirations have a granularity of a second.
+            Some(ttl) => {
+                connection
+                    .set_ex(self.key(&key), serialized, ttl.as_secs().max(1))
+                    .await
+            }
+            None => connection.set(self.key(&key), serialized).await,
+        };
+        if let Err(err) = result {
+            tracing::warn!(key, error = ?err, "redis set failed");
+        }
+    }
+
+    async fn invalidate(&self, key: &str) {
+        let mut connection = self.connection.clone();
+        let result: redis::RedisResult<()> = connection.del(self.key(key)).await;
+        if let Err(err) = result {
+            tracing::warn!(key, error = ?err, "redis del failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::RedisStore;
+    use crate::{
+        cache::{CacheStore, ResolveAtUriResult, ResolveWebHostMetaResult},
+        webhostmeta::WebHostMeta,
+    };
+
+    /// Runs against the Redis at `HOPPER_TEST_REDIS_URL`, such as one started
+    /// with `docker run --rm -p 6379:6379 redis`.
+    #[tokio::test]
+    #[ignore = "requires a Redis server"]
+    async fn test_redis_store() {
+        let url = std::env::var("HOPPER_TEST_REDIS_URL")
+            .unwrap_or_else(|_| "redis://127.0.0.1:6379/".to_string());
+        let connection = redis::Client::open(url)
+            .unwrap()
+            .get_connection_manager()
+            .await
+            .unwrap();
+        let prefix = "hopper-test:";
+
+        let webhostmeta = RedisStore::new(connection.clone(), prefix, 0.0);
+        let found = ResolveWebHostMetaResult::Found(Arc::new(WebHostMeta::new(vec![])));
+        webhostmeta
+            .insert("links.example".to_string(), found.clone())
+            .await;
+        assert_eq!(webhostmeta.get("links.example").await, Some(found));
+        webhostmeta.invalidate("links.example").await;
+        assert_eq!(webhostmeta.get("links.example").await, None);
+
+        // Fetched documents expire, unlike seeded ones.
+        let fetched = ResolveWebHostMetaResult::Fetched(
+            Arc::new(WebHostMeta::new(vec![])),
+            std::time::SystemTime::now(),
+        );
+        webhostmeta
+            .insert("fetched.example".to_string(), fetched.clone())
+            .await;
+        assert_eq!(webhostmeta.get("fetched.example").await, Some(fetched));
+        let ttl: i64 = redis::cmd("TTL")
+            .arg("hopper-test:fetched.example")
+            .query_async(&mut connection.clone())
+            .await
+            .unwrap();
+        assert!(ttl > 600, "{}", ttl);
+        webhostmeta.invalidate("fetched.example").await;
+
+        // Negative results are stored with an expiration.
+        let aturi = RedisStore::new(connection.clone(), prefix, 0.0);
+        let not_found = ResolveAtUriResult::NotFound("not found".to_string());
+        aturi.insert("abc".to_string(), not_found.clone()).await;
+        assert_eq!(aturi.get("abc").await, Some(not_found));
+        let ttl: i64 = redis::cmd("TTL")
+            .arg("hopper-test:abc")
+            .query_async(&mut connection.clone())
+            .await
+            .unwrap();
+        assert!((1..=600).contains(&ttl), "{}", ttl);
+        aturi.invalidate("abc").await;
+    }
+}
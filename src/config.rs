@@ -1,4 +1,26 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use http::HeaderValue;
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use unic_langid::LanguageIdentifier;
+
+use crate::{
+    cache::{DEFAULT_CACHE_CAPACITY, DEFAULT_NEGATIVE_JITTER},
+    did::DEFAULT_PLC_DIRECTORY,
+    model::{is_valid_hostname, is_valid_nsid, DEFAULT_RESERVED_SUFFIXES},
+    resolve::DEFAULT_SERVERS,
+    webhostmeta::{seeded, WebHostMeta, DEFAULT_HOST_META_PATH, REL_LINK, REL_SPEC_LINK},
+};
+
+/// Scripts and styles are limited to hopper's own static assets. Inline styles
+/// are allowed because htmx injects its indicator styles at runtime.
+pub const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; script-src 'self'; style-src 'self' 'unsafe-inline'; img-src 'self' https: data:; frame-ancestors 'none'; base-uri 'self'; form-action 'self'";
 
 #[derive(Clone)]
 pub struct HttpPort(u16);
@@ -6,6 +28,212 @@ pub struct HttpPort(u16);
 #[derive(Clone)]
 pub struct CertificateBundles(Vec<String>);
 
+#[derive(Clone)]
+pub struct ServerTimeout(Duration);
+
+#[derive(Clone)]
+pub struct Languages(Vec<LanguageIdentifier>);
+
+#[derive(Clone)]
+pub struct BindAddress(IpAddr);
+
+#[derive(Clone)]
+pub struct ContentSecurityPolicy(HeaderValue);
+
+#[derive(Clone)]
+pub struct RobotsTxt(Option<String>);
+
+/// An icon served at `/favicon.ico` in place of the bundled one, with the
+/// content type derived from its extension.
+#[derive(Clone)]
+pub struct Favicon(Option<(&'static str, Vec<u8>)>);
+
+#[derive(Clone)]
+pub struct RateLimitRequests(u32);
+
+#[derive(Clone)]
+pub struct RateLimitWindow(Duration);
+
+/// Idle connections kept per host by the outbound HTTP client. Unlimited when
+/// unset.
+#[derive(Clone)]
+pub struct PoolMaxIdlePerHost(usize);
+
+#[derive(Clone)]
+pub struct PoolIdleTimeout(Duration);
+
+#[derive(Clone)]
+pub struct ConnectTimeout(Duration);
+
+#[derive(Clone)]
+pub struct ReadTimeout(Duration);
+
+/// The total time allowed for an outbound request, including the body.
+#[derive(Clone)]
+pub struct RequestTimeout(Duration);
+
+/// A host whose host-meta `/readyz` fetches to confirm outbound
+/// connectivity. Without one, readiness only reflects local state.
+#[derive(Clone, Default)]
+pub struct ReadinessCanary(Option<String>);
+
+/// How long a readiness canary fetch answers `/readyz` probes. A degraded
+/// response asks clients to retry once it expires.
+#[derive(Clone)]
+pub struct ReadinessCanaryTtl(Duration);
+
+/// The bearer token required by the admin endpoints under `/debug`.
+#[derive(Clone, Default)]
+pub struct AdminToken(Option<String>);
+
+/// Client networks allowed to use the admin endpoints under `/debug`. The
+/// admin endpoints are disabled when neither this nor the admin token is set.
+#[derive(Clone, Default)]
+pub struct AdminAllowlist(Vec<IpNet>);
+
+/// How long shutdown waits for in-flight tasks before giving up on them.
+#[derive(Clone)]
+pub struct ShutdownTimeout(Duration);
+
+#[derive(Clone)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+/// Origins allowed to make cross-origin requests.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum CorsOrigins {
+    /// The https origin of `EXTERNAL_BASE`.
+    #[default]
+    ExternalBase,
+    /// Any origin, configured as `*`.
+    Any,
+    List(Vec<HeaderValue>),
+}
+
+/// Short collection names, such as `post`, mapped to the NSID they expand to.
+#[derive(Clone, Default)]
+pub struct CollectionAliases(HashMap<String, String>);
+
+/// Query parameters, such as `utm_source=hopper`, added to every destination
+/// that doesn't already have them. The environment variable is a
+/// form-encoded query string.
+#[derive(Clone, Default)]
+pub struct AppendQuery(Vec<(String, String)>);
+
+#[derive(Clone)]
+pub struct MaxServers(usize);
+
+/// How many of the tried servers an unsupported AT-URI error names. The rest
+/// are only counted.
+#[derive(Clone)]
+pub struct MaxErrorServers(usize);
+
+/// The longest `aturi` input, in bytes, that is parsed. Longer input is
+/// rejected as an invalid AT-URI.
+#[derive(Clone)]
+pub struct MaxInputLength(usize);
+
+/// Outbound requests in flight to a single host.
+#[derive(Clone)]
+pub struct MaxFetchesPerHost(usize);
+
+/// Outbound requests in flight across all hosts.
+#[derive(Clone)]
+pub struct MaxFetches(usize);
+
+/// Redirects followed by outbound requests. Every redirect target must also
+/// be an allowed host.
+#[derive(Clone)]
+pub struct MaxRedirects(usize);
+
+/// The share of negative cache TTLs they are randomly varied by, between 0
+/// and 1.
+#[derive(Clone)]
+pub struct NegativeCacheJitter(f64);
+
+/// How old a fetched host-meta document may get before it is refreshed in
+/// the background. Unset, documents are kept until they are evicted.
+#[derive(Clone, Default)]
+pub struct HostMetaSoftTtl(Option<Duration>);
+
+/// The capacity of the in-process host-meta cache, in units of a started KiB
+/// of each document's estimated size.
+#[derive(Clone)]
+pub struct HostMetaCacheCapacity(u64);
+
+/// The number of resolved AT-URIs kept by the in-process cache.
+#[derive(Clone)]
+pub struct AtUriCacheCapacity(u64);
+
+/// Where host-meta and AT-URI resolution results are cached.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum CacheBackend {
+    /// Each process keeps its own moka cache.
+    #[default]
+    Memory,
+
+    /// Results are shared through the Redis server at the URL, which needs
+    /// the `redis` feature.
+    Redis(String),
+}
+
+/// Whether the access log line of a resolution includes the input AT-URI.
+/// When disabled the field is logged as `redacted`.
+#[derive(Clone)]
+pub struct AccessLogAturi(bool);
+
+/// Whether AT-URIs must match the atproto handle and NSID grammar exactly,
+/// rather than the more lenient checks used by default.
+#[derive(Clone)]
+pub struct StrictValidation(bool);
+
+/// Whether the fragment of an AT-URI, such as `#main`, replaces the fragment
+/// of its destination. When disabled the fragment is dropped.
+#[derive(Clone)]
+pub struct PropagateFragment(bool);
+
+/// Hostnames, where a `*.` prefix matches any subdomain of the remainder.
+#[derive(Clone, Default)]
+pub struct HostPatterns(Vec<String>);
+
+/// Hostname suffixes that servers and redirect targets must not end with,
+/// each starting with a `.`.
+#[derive(Clone)]
+pub struct ReservedSuffixes(Vec<String>);
+
+/// Where host-meta documents are fetched from, with `{host}` replaced by the
+/// server hostname.
+#[derive(Clone)]
+pub struct ResolverBase(String);
+
+/// The path of the host-meta document under the resolver base.
+#[derive(Clone)]
+pub struct HostMetaPath(String);
+
+/// The PLC directory `did:plc` documents are fetched from, as an https base
+/// URL without a trailing slash.
+#[derive(Clone)]
+pub struct PlcDirectory(String);
+
+/// Link relations that host-meta links must use to be considered.
+#[derive(Clone)]
+pub struct LinkRels(Vec<String>);
+
+/// Host-meta documents inserted into the host-meta cache at startup, keyed by
+/// hostname.
+#[derive(Clone)]
+pub struct Seeds(BTreeMap<String, WebHostMeta>);
+
+/// Hostnames and AT-URIs resolved before the server starts accepting
+/// traffic, read one per line from `HOPPER_PREWARM_FILE`.
+#[derive(Clone, Default)]
+pub struct Prewarm(Vec<String>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Listen {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
 #[derive(Clone)]
 pub struct Config {
     pub version: String,
@@ -13,19 +241,318 @@ pub struct Config {
     pub external_base: String,
     pub certificate_bundles: CertificateBundles,
     pub user_agent: String,
+    pub server_timeout: ServerTimeout,
+    pub languages: Languages,
+    pub bind_address: BindAddress,
+    pub listen: Listen,
+    pub content_security_policy: ContentSecurityPolicy,
+    pub robots_txt: RobotsTxt,
+    pub favicon: Favicon,
+    pub rate_limit_requests: RateLimitRequests,
+    pub rate_limit_window: RateLimitWindow,
+    pub trusted_proxies: TrustedProxies,
+    pub cors_origins: CorsOrigins,
+    pub collection_aliases: CollectionAliases,
+    pub append_query: AppendQuery,
+    pub seeds: Seeds,
+    pub link_rels: LinkRels,
+    pub max_servers: MaxServers,
+    pub max_error_servers: MaxErrorServers,
+    pub max_input_length: MaxInputLength,
+    pub max_redirects: MaxRedirects,
+    pub max_fetches_per_host: MaxFetchesPerHost,
+    pub max_fetches: MaxFetches,
+    pub host_allowlist: HostPatterns,
+    pub host_denylist: HostPatterns,
+    pub reserved_suffixes: ReservedSuffixes,
+    pub resolver_base: ResolverBase,
+    pub host_meta_path: HostMetaPath,
+    pub plc_directory: PlcDirectory,
+    pub prewarm: Prewarm,
+    pub shutdown_timeout: ShutdownTimeout,
+    pub pool_max_idle_per_host: PoolMaxIdlePerHost,
+    pub pool_idle_timeout: PoolIdleTimeout,
+    pub connect_timeout: ConnectTimeout,
+    pub read_timeout: ReadTimeout,
+    pub request_timeout: RequestTimeout,
+    pub admin_token: AdminToken,
+    pub admin_allowlist: AdminAllowlist,
+    pub readiness_canary: ReadinessCanary,
+    pub readiness_canary_ttl: ReadinessCanaryTtl,
+    pub access_log_aturi: AccessLogAturi,
+    pub propagate_fragment: PropagateFragment,
+    pub strict_validation: StrictValidation,
+    pub negative_cache_jitter: NegativeCacheJitter,
+    pub cache_backend: CacheBackend,
+    pub host_meta_soft_ttl: HostMetaSoftTtl,
+    pub host_meta_cache_capacity: HostMetaCacheCapacity,
+    pub aturi_cache_capacity: AtUriCacheCapacity,
+}
+
+/// Settings read from the file referenced by `HOPPER_CONFIG`. Every field is
+/// optional, and environment variables take precedence over file values.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub http_port: Option<u16>,
+    pub external_base: Option<String>,
+    pub certificate_bundles: Option<Vec<String>>,
+    pub user_agent: Option<String>,
+    pub server_timeout: Option<u64>,
+    pub languages: Option<Vec<String>>,
+    pub bind_address: Option<String>,
+    pub listen: Option<String>,
+    pub content_security_policy: Option<String>,
+    pub robots_file: Option<String>,
+    pub favicon_file: Option<String>,
+    pub rate_limit_requests: Option<u32>,
+    pub rate_limit_window: Option<u64>,
+    pub trusted_proxies: Option<Vec<String>>,
+    pub cors_origins: Option<Vec<String>>,
+    pub collection_aliases: Option<HashMap<String, String>>,
+    pub append_query: Option<BTreeMap<String, String>>,
+    pub seed_file: Option<String>,
+    pub prewarm_file: Option<String>,
+    pub shutdown_timeout: Option<u64>,
+    pub pool_max_idle_per_host: Option<usize>,
+    pub pool_idle_timeout: Option<u64>,
+    pub connect_timeout: Option<u64>,
+    pub read_timeout: Option<u64>,
+    pub request_timeout: Option<u64>,
+    pub admin_token: Option<String>,
+    pub readiness_canary: Option<String>,
+    pub readiness_canary_ttl: Option<u64>,
+    pub admin_allowlist: Option<Vec<String>>,
+    pub access_log_aturi: Option<bool>,
+    pub propagate_fragment: Option<bool>,
+    pub strict_validation: Option<bool>,
+    pub negative_cache_jitter: Option<f64>,
+    pub cache_backend: Option<String>,
+    pub host_meta_soft_ttl: Option<u64>,
+    pub host_meta_cache_capacity: Option<u64>,
+    pub aturi_cache_capacity: Option<u64>,
+    pub link_rels: Option<Vec<String>>,
+    pub max_servers: Option<usize>,
+    pub max_error_servers: Option<usize>,
+    pub max_input_length: Option<usize>,
+    pub max_redirects: Option<usize>,
+    pub max_fetches_per_host: Option<usize>,
+    pub max_fetches: Option<usize>,
+    pub host_allowlist: Option<Vec<String>>,
+    pub host_denylist: Option<Vec<String>>,
+    pub reserved_suffixes: Option<Vec<String>>,
+    pub resolver_base: Option<String>,
+    pub host_meta_path: Option<String>,
+    pub plc_directory: Option<String>,
 }
 
+/// Looks up settings by their environment variable name, falling back to
+/// values from a config file.
+#[derive(Default)]
+struct ConfigSource(HashMap<&'static str, String>);
+
 impl Config {
     pub fn new() -> Result<Self> {
-        let http_port: HttpPort = default_env("HTTP_PORT", "4060").try_into()?;
-        let external_base = require_env("EXTERNAL_BASE")?;
+        match std::env::var("HOPPER_CONFIG") {
+            Ok(path) if !path.is_empty() => Self::from_file(path),
+            _ => Self::from_source(&ConfigSource::default()),
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("reading config file {} failed", path.display()))?;
+        Self::from_toml(&content)
+            .with_context(|| format!("loading config file {} failed", path.display()))
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self> {
+        let config_file: ConfigFile = toml::from_str(content).context("parsing config failed")?;
+        Self::from_source(&config_file.into())
+    }
+
+    fn from_source(source: &ConfigSource) -> Result<Self> {
+        let http_port: HttpPort = source.default_env("HTTP_PORT", "4060").try_into()?;
+        let external_base = source.require_env("EXTERNAL_BASE")?;
 
         let certificate_bundles: CertificateBundles =
-            optional_env("CERTIFICATE_BUNDLES").try_into()?;
+            source.optional_env("CERTIFICATE_BUNDLES").try_into()?;
 
         let default_user_agent = format!("hopper ({}; +https://hopper.at/)", version()?);
 
-        let user_agent = default_env("USER_AGENT", &default_user_agent);
+        let user_agent = source.default_env("USER_AGENT", &default_user_agent);
+
+        let server_timeout: ServerTimeout = source
+            .default_env("HOPPER_SERVER_TIMEOUT", "2000")
+            .try_into()?;
+
+        let languages: Languages = source.default_env("HOPPER_LANGUAGES", "en-us").try_into()?;
+
+        let bind_address: BindAddress = source
+            .default_env("HOPPER_BIND_ADDRESS", "0.0.0.0")
+            .try_into()?;
+
+        let default_listen = SocketAddr::new(*bind_address.as_ref(), *http_port.as_ref());
+        let listen: Listen = source
+            .default_env("HOPPER_LISTEN", &format!("tcp://{}", default_listen))
+            .try_into()?;
+
+        let content_security_policy: ContentSecurityPolicy = source
+            .default_env(
+                "HOPPER_CONTENT_SECURITY_POLICY",
+                DEFAULT_CONTENT_SECURITY_POLICY,
+            )
+            .try_into()?;
+
+        let robots_txt: RobotsTxt = source.optional_env("HOPPER_ROBOTS_FILE").try_into()?;
+
+        let favicon: Favicon = source.optional_env("HOPPER_FAVICON_FILE").try_into()?;
+
+        let rate_limit_requests: RateLimitRequests = source
+            .default_env("HOPPER_RATE_LIMIT_REQUESTS", "60")
+            .try_into()?;
+        let rate_limit_window: RateLimitWindow = source
+            .default_env("HOPPER_RATE_LIMIT_WINDOW", "60")
+            .try_into()?;
+
+        let trusted_proxies: TrustedProxies =
+            source.optional_env("HOPPER_TRUSTED_PROXIES").try_into()?;
+
+        let cors_origins: CorsOrigins = source.optional_env("HOPPER_CORS_ORIGINS").try_into()?;
+
+        let collection_aliases: CollectionAliases = source
+            .optional_env("HOPPER_COLLECTION_ALIASES")
+            .try_into()?;
+
+        let append_query: AppendQuery = source.optional_env("HOPPER_APPEND_QUERY").try_into()?;
+
+        let seeds: Seeds = source.optional_env("HOPPER_SEED_FILE").try_into()?;
+
+        let link_rels: LinkRels = source
+            .default_env(
+                "HOPPER_LINK_RELS",
+                &format!("{},{}", REL_LINK, REL_SPEC_LINK),
+            )
+            .try_into()?;
+
+        let max_servers: MaxServers = source.default_env("HOPPER_MAX_SERVERS", "8").try_into()?;
+
+        let max_error_servers: MaxErrorServers = source
+            .default_env("HOPPER_MAX_ERROR_SERVERS", "5")
+            .try_into()?;
+
+        let max_input_length: MaxInputLength = source
+            .default_env("HOPPER_MAX_INPUT_LENGTH", "8192")
+            .try_into()?;
+
+        let max_redirects: MaxRedirects =
+            source.default_env("HOPPER_MAX_REDIRECTS", "2").try_into()?;
+
+        let max_fetches_per_host: MaxFetchesPerHost = source
+            .default_env("HOPPER_MAX_FETCHES_PER_HOST", "4")
+            .try_into()?;
+        let max_fetches: MaxFetches = source.default_env("HOPPER_MAX_FETCHES", "64").try_into()?;
+
+        let access_log_aturi: AccessLogAturi = source
+            .default_env("HOPPER_ACCESS_LOG_ATURI", "true")
+            .try_into()?;
+
+        let propagate_fragment: PropagateFragment = source
+            .default_env("HOPPER_PROPAGATE_FRAGMENT", "false")
+            .try_into()?;
+
+        let strict_validation: StrictValidation = source
+            .default_env("HOPPER_STRICT_VALIDATION", "false")
+            .try_into()?;
+
+        let cache_backend: CacheBackend = source
+            .default_env("HOPPER_CACHE_BACKEND", "memory")
+            .try_into()?;
+
+        let host_meta_soft_ttl: HostMetaSoftTtl = source
+            .optional_env("HOPPER_HOST_META_SOFT_TTL")
+            .try_into()?;
+
+        let host_meta_cache_capacity: HostMetaCacheCapacity = source
+            .default_env(
+                "HOPPER_HOST_META_CACHE_CAPACITY",
+                &DEFAULT_CACHE_CAPACITY.to_string(),
+            )
+            .try_into()?;
+        let aturi_cache_capacity: AtUriCacheCapacity = source
+            .default_env(
+                "HOPPER_ATURI_CACHE_CAPACITY",
+                &DEFAULT_CACHE_CAPACITY.to_string(),
+            )
+            .try_into()?;
+
+        let negative_cache_jitter: NegativeCacheJitter = source
+            .default_env(
+                "HOPPER_NEGATIVE_CACHE_JITTER",
+                &DEFAULT_NEGATIVE_JITTER.to_string(),
+            )
+            .try_into()?;
+
+        let host_allowlist: HostPatterns =
+            source.optional_env("HOPPER_HOST_ALLOWLIST").try_into()?;
+        let host_denylist: HostPatterns = source.optional_env("HOPPER_HOST_DENYLIST").try_into()?;
+
+        let reserved_suffixes: ReservedSuffixes = source
+            .default_env(
+                "HOPPER_RESERVED_SUFFIXES",
+                &DEFAULT_RESERVED_SUFFIXES.join(","),
+            )
+            .try_into()?;
+
+        let resolver_base: ResolverBase = source
+            .default_env("HOPPER_RESOLVER_BASE", "https://{host}")
+            .try_into()?;
+
+        let host_meta_path: HostMetaPath = source
+            .default_env("HOPPER_HOST_META_PATH", DEFAULT_HOST_META_PATH)
+            .try_into()?;
+
+        let plc_directory: PlcDirectory = source
+            .default_env("HOPPER_PLC_DIRECTORY", DEFAULT_PLC_DIRECTORY)
+            .try_into()?;
+
+        let prewarm: Prewarm = source.optional_env("HOPPER_PREWARM_FILE").try_into()?;
+
+        let shutdown_timeout: ShutdownTimeout = source
+            .default_env("HOPPER_SHUTDOWN_TIMEOUT", "30")
+            .try_into()?;
+
+        let pool_max_idle_per_host: PoolMaxIdlePerHost = source
+            .optional_env("HOPPER_POOL_MAX_IDLE_PER_HOST")
+            .try_into()?;
+        let pool_idle_timeout: PoolIdleTimeout = source
+            .default_env("HOPPER_POOL_IDLE_TIMEOUT", "90")
+            .try_into()?;
+        let connect_timeout: ConnectTimeout = source
+            .default_env("HOPPER_CONNECT_TIMEOUT", "1000")
+            .try_into()?;
+        let read_timeout: ReadTimeout = source
+            .default_env("HOPPER_READ_TIMEOUT", "1000")
+            .try_into()?;
+        let request_timeout: RequestTimeout = source
+            .default_env("HOPPER_REQUEST_TIMEOUT", "3000")
+            .try_into()?;
+        if connect_timeout.0 > request_timeout.0 || read_timeout.0 > request_timeout.0 {
+            return Err(anyhow!(
+                "HOPPER_CONNECT_TIMEOUT and HOPPER_READ_TIMEOUT must not exceed HOPPER_REQUEST_TIMEOUT"
+            ));
+        }
+
+        let admin_token: AdminToken = source.optional_env("HOPPER_ADMIN_TOKEN").try_into()?;
+
+        let readiness_canary: ReadinessCanary =
+            source.optional_env("HOPPER_READINESS_CANARY").try_into()?;
+        let readiness_canary_ttl: ReadinessCanaryTtl = source
+            .default_env("HOPPER_READINESS_CANARY_TTL", "30")
+            .try_into()?;
+        let admin_allowlist: AdminAllowlist =
+            source.optional_env("HOPPER_ADMIN_ALLOWLIST").try_into()?;
 
         Ok(Self {
             version: version()?,
@@ -33,21 +560,338 @@ impl Config {
             external_base,
             certificate_bundles,
             user_agent,
+            server_timeout,
+            languages,
+            bind_address,
+            listen,
+            content_security_policy,
+            robots_txt,
+            favicon,
+            rate_limit_requests,
+            rate_limit_window,
+            trusted_proxies,
+            cors_origins,
+            collection_aliases,
+            append_query,
+            seeds,
+            link_rels,
+            max_servers,
+            max_error_servers,
+            max_input_length,
+            max_redirects,
+            max_fetches_per_host,
+            max_fetches,
+            host_allowlist,
+            host_denylist,
+            reserved_suffixes,
+            resolver_base,
+            host_meta_path,
+            plc_directory,
+            prewarm,
+            shutdown_timeout,
+            pool_max_idle_per_host,
+            pool_idle_timeout,
+            connect_timeout,
+            read_timeout,
+            request_timeout,
+            admin_token,
+            readiness_canary,
+            readiness_canary_ttl,
+            admin_allowlist,
+            access_log_aturi,
+            propagate_fragment,
+            strict_validation,
+            negative_cache_jitter,
+            cache_backend,
+            host_meta_soft_ttl,
+            host_meta_cache_capacity,
+            aturi_cache_capacity,
         })
     }
-}
 
-fn require_env(name: &str) -> Result<String> {
-    std::env::var(name)
-        .map_err(|err| anyhow::Error::new(err).context(anyhow!("{} must be set", name)))
+    /// Checks settings that are valid on their own but can't be served with,
+    /// reporting every problem found rather than only the first.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        let origin = format!("https://{}", self.external_base);
+        match url::Url::parse(&origin) {
+            Ok(url)
+                if url.host_str().is_some()
+                    && url.path() == "/"
+                    && url.query().is_none()
+                    && url.fragment().is_none()
+                    && url.username().is_empty()
+                    && origin.parse::<HeaderValue>().is_ok() => {}
+            _ => problems.push(format!(
+                "EXTERNAL_BASE {:?} must be a host, optionally with a port, that forms the https origin {}",
+                self.external_base, origin
+            )),
+        }
+
+        if let Listen::Tcp(address) = &self.listen {
+            if address.port() == 0 {
+                problems.push(format!(
+                    "listen address {} must have a nonzero port",
+                    address
+                ));
+            }
+        }
+
+        for path in self.certificate_bundles.as_ref() {
+            if let Err(err) = std::fs::File::open(path) {
+                problems.push(format!(
+                    "CERTIFICATE_BUNDLES entry {} can't be read: {}",
+                    path, err
+                ));
+            }
+        }
+
+        for server in DEFAULT_SERVERS {
+            if !is_valid_hostname(server, self.reserved_suffixes.as_ref()) {
+                problems.push(format!(
+                    "default server {} is not a valid hostname with HOPPER_RESERVED_SUFFIXES",
+                    server
+                ));
+            }
+        }
+
+        if let Some(canary) = self.readiness_canary.as_ref() {
+            if !is_valid_hostname(canary, self.reserved_suffixes.as_ref()) {
+                problems.push(format!(
+                    "HOPPER_READINESS_CANARY {} is not a valid hostname with HOPPER_RESERVED_SUFFIXES",
+                    canary
+                ));
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "invalid configuration:\n  - {}",
+            problems.join("\n  - ")
+        ))
+    }
+
+    /// Returns true when resolution may query `host`. When an allowlist is
+    /// configured only hosts on it are allowed and the denylist is ignored.
+    pub fn is_host_allowed(&self, host: &str) -> bool {
+        if !self.host_allowlist.0.is_empty() {
+            return self.host_allowlist.matches(host);
+        }
+        !self.host_denylist.matches(host)
+    }
 }
 
-fn optional_env(name: &str) -> String {
-    std::env::var(name).unwrap_or("".to_string())
+impl From<ConfigFile> for ConfigSource {
+    fn from(config_file: ConfigFile) -> Self {
+        let mut values = HashMap::new();
+        if let Some(http_port) = config_file.http_port {
+            values.insert("HTTP_PORT", http_port.to_string());
+        }
+        if let Some(external_base) = config_file.external_base {
+            values.insert("EXTERNAL_BASE", external_base);
+        }
+        if let Some(certificate_bundles) = config_file.certificate_bundles {
+            values.insert("CERTIFICATE_BUNDLES", certificate_bundles.join(";"));
+        }
+        if let Some(user_agent) = config_file.user_agent {
+            values.insert("USER_AGENT", user_agent);
+        }
+        if let Some(server_timeout) = config_file.server_timeout {
+            values.insert("HOPPER_SERVER_TIMEOUT", server_timeout.to_string());
+        }
+        if let Some(languages) = config_file.languages {
+            values.insert("HOPPER_LANGUAGES", languages.join(","));
+        }
+        if let Some(bind_address) = config_file.bind_address {
+            values.insert("HOPPER_BIND_ADDRESS", bind_address);
+        }
+        if let Some(listen) = config_file.listen {
+            values.insert("HOPPER_LISTEN", listen);
+        }
+        if let Some(content_security_policy) = config_file.content_security_policy {
+            values.insert("HOPPER_CONTENT_SECURITY_POLICY", content_security_policy);
+        }
+        if let Some(robots_file) = config_file.robots_file {
+            values.insert("HOPPER_ROBOTS_FILE", robots_file);
+        }
+        if let Some(favicon_file) = config_file.favicon_file {
+            values.insert("HOPPER_FAVICON_FILE", favicon_file);
+        }
+        if let Some(rate_limit_requests) = config_file.rate_limit_requests {
+            values.insert(
+                "HOPPER_RATE_LIMIT_REQUESTS",
+                rate_limit_requests.to_string(),
+            );
+        }
+        if let Some(rate_limit_window) = config_file.rate_limit_window {
+            values.insert("HOPPER_RATE_LIMIT_WINDOW", rate_limit_window.to_string());
+        }
+        if let Some(trusted_proxies) = config_file.trusted_proxies {
+            values.insert("HOPPER_TRUSTED_PROXIES", trusted_proxies.join(","));
+        }
+        if let Some(cors_origins) = config_file.cors_origins {
+            values.insert("HOPPER_CORS_ORIGINS", cors_origins.join(","));
+        }
+        if let Some(collection_aliases) = config_file.collection_aliases {
+            values.insert(
+                "HOPPER_COLLECTION_ALIASES",
+                collection_aliases
+                    .into_iter()
+                    .map(|(alias, nsid)| format!("{}={}", alias, nsid))
+                    .collect::<Vec<String>>()
+                    .join(","),
+            );
+        }
+        if let Some(append_query) = config_file.append_query {
+            values.insert(
+                "HOPPER_APPEND_QUERY",
+                url::form_urlencoded::Serializer::new(String::new())
+                    .extend_pairs(append_query)
+                    .finish(),
+            );
+        }
+        if let Some(seed_file) = config_file.seed_file {
+            values.insert("HOPPER_SEED_FILE", seed_file);
+        }
+        if let Some(prewarm_file) = config_file.prewarm_file {
+            values.insert("HOPPER_PREWARM_FILE", prewarm_file);
+        }
+        if let Some(shutdown_timeout) = config_file.shutdown_timeout {
+            values.insert("HOPPER_SHUTDOWN_TIMEOUT", shutdown_timeout.to_string());
+        }
+        if let Some(pool_max_idle_per_host) = config_file.pool_max_idle_per_host {
+            values.insert(
+                "HOPPER_POOL_MAX_IDLE_PER_HOST",
+                pool_max_idle_per_host.to_string(),
+            );
+        }
+        if let Some(pool_idle_timeout) = config_file.pool_idle_timeout {
+            values.insert("HOPPER_POOL_IDLE_TIMEOUT", pool_idle_timeout.to_string());
+        }
+        if let Some(connect_timeout) = config_file.connect_timeout {
+            values.insert("HOPPER_CONNECT_TIMEOUT", connect_timeout.to_string());
+        }
+        if let Some(read_timeout) = config_file.read_timeout {
+            values.insert("HOPPER_READ_TIMEOUT", read_timeout.to_string());
+        }
+        if let Some(request_timeout) = config_file.request_timeout {
+            values.insert("HOPPER_REQUEST_TIMEOUT", request_timeout.to_string());
+        }
+        if let Some(admin_token) = config_file.admin_token {
+            values.insert("HOPPER_ADMIN_TOKEN", admin_token);
+        }
+        if let Some(readiness_canary) = config_file.readiness_canary {
+            values.insert("HOPPER_READINESS_CANARY", readiness_canary);
+        }
+        if let Some(readiness_canary_ttl) = config_file.readiness_canary_ttl {
+            values.insert(
+                "HOPPER_READINESS_CANARY_TTL",
+                readiness_canary_ttl.to_string(),
+            );
+        }
+        if let Some(admin_allowlist) = config_file.admin_allowlist {
+            values.insert("HOPPER_ADMIN_ALLOWLIST", admin_allowlist.join(","));
+        }
+        if let Some(access_log_aturi) = config_file.access_log_aturi {
+            values.insert("HOPPER_ACCESS_LOG_ATURI", access_log_aturi.to_string());
+        }
+        if let Some(propagate_fragment) = config_file.propagate_fragment {
+            values.insert("HOPPER_PROPAGATE_FRAGMENT", propagate_fragment.to_string());
+        }
+        if let Some(strict_validation) = config_file.strict_validation {
+            values.insert("HOPPER_STRICT_VALIDATION", strict_validation.to_string());
+        }
+        if let Some(cache_backend) = config_file.cache_backend {
+            values.insert("HOPPER_CACHE_BACKEND", cache_backend);
+        }
+        if let Some(host_meta_soft_ttl) = config_file.host_meta_soft_ttl {
+            values.insert("HOPPER_HOST_META_SOFT_TTL", host_meta_soft_ttl.to_string());
+        }
+        if let Some(host_meta_cache_capacity) = config_file.host_meta_cache_capacity {
+            values.insert(
+                "HOPPER_HOST_META_CACHE_CAPACITY",
+                host_meta_cache_capacity.to_string(),
+            );
+        }
+        if let Some(aturi_cache_capacity) = config_file.aturi_cache_capacity {
+            values.insert(
+                "HOPPER_ATURI_CACHE_CAPACITY",
+                aturi_cache_capacity.to_string(),
+            );
+        }
+        if let Some(negative_cache_jitter) = config_file.negative_cache_jitter {
+            values.insert(
+                "HOPPER_NEGATIVE_CACHE_JITTER",
+                negative_cache_jitter.to_string(),
+            );
+        }
+        if let Some(link_rels) = config_file.link_rels {
+            values.insert("HOPPER_LINK_RELS", link_rels.join(","));
+        }
+        if let Some(max_servers) = config_file.max_servers {
+            values.insert("HOPPER_MAX_SERVERS", max_servers.to_string());
+        }
+        if let Some(max_error_servers) = config_file.max_error_servers {
+            values.insert("HOPPER_MAX_ERROR_SERVERS", max_error_servers.to_string());
+        }
+        if let Some(max_input_length) = config_file.max_input_length {
+            values.insert("HOPPER_MAX_INPUT_LENGTH", max_input_length.to_string());
+        }
+        if let Some(max_redirects) = config_file.max_redirects {
+            values.insert("HOPPER_MAX_REDIRECTS", max_redirects.to_string());
+        }
+        if let Some(max_fetches_per_host) = config_file.max_fetches_per_host {
+            values.insert(
+                "HOPPER_MAX_FETCHES_PER_HOST",
+                max_fetches_per_host.to_string(),
+            );
+        }
+        if let Some(max_fetches) = config_file.max_fetches {
+            values.insert("HOPPER_MAX_FETCHES", max_fetches.to_string());
+        }
+        if let Some(host_allowlist) = config_file.host_allowlist {
+            values.insert("HOPPER_HOST_ALLOWLIST", host_allowlist.join(","));
+        }
+        if let Some(host_denylist) = config_file.host_denylist {
+            values.insert("HOPPER_HOST_DENYLIST", host_denylist.join(","));
+        }
+        if let Some(reserved_suffixes) = config_file.reserved_suffixes {
+            values.insert("HOPPER_RESERVED_SUFFIXES", reserved_suffixes.join(","));
+        }
+        if let Some(resolver_base) = config_file.resolver_base {
+            values.insert("HOPPER_RESOLVER_BASE", resolver_base);
+        }
+        if let Some(host_meta_path) = config_file.host_meta_path {
+            values.insert("HOPPER_HOST_META_PATH", host_meta_path);
+        }
+        if let Some(plc_directory) = config_file.plc_directory {
+            values.insert("HOPPER_PLC_DIRECTORY", plc_directory);
+        }
+        Self(values)
+    }
 }
 
-fn default_env(name: &str, default_value: &str) -> String {
-    std::env::var(name).unwrap_or(default_value.to_string())
+impl ConfigSource {
+    fn get(&self, name: &str) -> Option<String> {
+        std::env::var(name)
+            .ok()
+            .or_else(|| self.0.get(name).cloned())
+    }
+
+    fn require_env(&self, name: &str) -> Result<String> {
+        self.get(name).ok_or(anyhow!("{} must be set", name))
+    }
+
+    fn optional_env(&self, name: &str) -> String {
+        self.get(name).unwrap_or_default()
+    }
+
+    fn default_env(&self, name: &str, default_value: &str) -> String {
+        self.get(name).unwrap_or(default_value.to_string())
+    }
 }
 
 pub fn version() -> Result<String> {
@@ -99,3 +943,1250 @@ impl AsRef<Vec<String>> for CertificateBundles {
         &self.0
     }
 }
+
+impl TryFrom<String> for ServerTimeout {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value
+            .parse::<u64>()
+            .map(|millis| Self(Duration::from_millis(millis)))
+            .map_err(|err| {
+                anyhow::Error::new(err).context(anyhow!(
+                    "parsing HOPPER_SERVER_TIMEOUT into milliseconds failed"
+                ))
+            })
+    }
+}
+
+impl AsRef<Duration> for ServerTimeout {
+    fn as_ref(&self) -> &Duration {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for Languages {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let languages = value
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<LanguageIdentifier>().map_err(|err| {
+                    anyhow::Error::new(err).context(anyhow!("parsing language {} failed", s))
+                })
+            })
+            .collect::<Result<Vec<LanguageIdentifier>>>()?;
+
+        if languages.is_empty() {
+            return Err(anyhow!(
+                "HOPPER_LANGUAGES must contain at least one language"
+            ));
+        }
+
+        Ok(Self(languages))
+    }
+}
+
+impl AsRef<Vec<LanguageIdentifier>> for Languages {
+    fn as_ref(&self) -> &Vec<LanguageIdentifier> {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for Listen {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if let Some(address) = value.strip_prefix("tcp://") {
+            address.parse::<SocketAddr>().map(Self::Tcp).map_err(|err| {
+                anyhow::Error::new(err)
+                    .context(anyhow!("parsing HOPPER_LISTEN address {} failed", address))
+            })
+        } else if let Some(path) = value.strip_prefix("unix://") {
+            if path.is_empty() {
+                return Err(anyhow!("HOPPER_LISTEN unix socket path must not be empty"));
+            }
+            Ok(Self::Unix(PathBuf::from(path)))
+        } else {
+            Err(anyhow!(
+                "HOPPER_LISTEN must start with tcp:// or unix://, got {}",
+                value
+            ))
+        }
+    }
+}
+
+impl TryFrom<String> for BindAddress {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let trimmed = value
+            .strip_prefix('[')
+            .and_then(|inner| inner.strip_suffix(']'))
+            .unwrap_or(&value);
+        trimmed.parse::<IpAddr>().map(Self).map_err(|err| {
+            anyhow::Error::new(err).context(anyhow!(
+                "parsing HOPPER_BIND_ADDRESS {} into an IP address failed",
+                value
+            ))
+        })
+    }
+}
+
+impl AsRef<IpAddr> for BindAddress {
+    fn as_ref(&self) -> &IpAddr {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for ContentSecurityPolicy {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        HeaderValue::from_str(&value).map(Self).map_err(|err| {
+            anyhow::Error::new(err).context(anyhow!(
+                "HOPPER_CONTENT_SECURITY_POLICY is not a valid header value"
+            ))
+        })
+    }
+}
+
+impl AsRef<HeaderValue> for ContentSecurityPolicy {
+    fn as_ref(&self) -> &HeaderValue {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for RobotsTxt {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            return Ok(Self(None));
+        }
+        std::fs::read_to_string(&value)
+            .map(|content| Self(Some(content)))
+            .with_context(|| format!("reading HOPPER_ROBOTS_FILE {} failed", value))
+    }
+}
+
+impl AsRef<Option<String>> for RobotsTxt {
+    fn as_ref(&self) -> &Option<String> {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for Favicon {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            return Ok(Self(None));
+        }
+        let content_type = match Path::new(&value).extension().and_then(|ext| ext.to_str()) {
+            Some("ico") => "image/x-icon",
+            Some("png") => "image/png",
+            Some("svg") => "image/svg+xml",
+            _ => {
+                return Err(anyhow!(
+                    "HOPPER_FAVICON_FILE must be an .ico, .png, or .svg file"
+                ))
+            }
+        };
+        std::fs::read(&value)
+            .map(|content| Self(Some((content_type, content))))
+            .with_context(|| format!("reading HOPPER_FAVICON_FILE {} failed", value))
+    }
+}
+
+impl AsRef<Option<(&'static str, Vec<u8>)>> for Favicon {
+    fn as_ref(&self) -> &Option<(&'static str, Vec<u8>)> {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for RateLimitRequests {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse::<u32>().map(Self).map_err(|err| {
+            anyhow::Error::new(err).context(anyhow!("parsing HOPPER_RATE_LIMIT_REQUESTS failed"))
+        })
+    }
+}
+
+impl AsRef<u32> for RateLimitRequests {
+    fn as_ref(&self) -> &u32 {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for RateLimitWindow {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.parse::<u64>() {
+            Ok(0) => Err(anyhow!("HOPPER_RATE_LIMIT_WINDOW must be greater than 0")),
+            Ok(seconds) => Ok(Self(Duration::from_secs(seconds))),
+            Err(err) => Err(anyhow::Error::new(err).context(anyhow!(
+                "parsing HOPPER_RATE_LIMIT_WINDOW into seconds failed"
+            ))),
+        }
+    }
+}
+
+impl AsRef<Duration> for RateLimitWindow {
+    fn as_ref(&self) -> &Duration {
+        &self.0
+    }
+}
+
+/// Parses a comma separated list of networks and addresses, where an address
+/// is a network of one.
+fn parse_networks(name: &str, value: &str) -> Result<Vec<IpNet>> {
+    value
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            s.parse::<IpNet>()
+                .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|err| {
+                    anyhow::Error::new(err).context(anyhow!("parsing {} entry {} failed", name, s))
+                })
+        })
+        .collect()
+}
+
+impl TryFrom<String> for TrustedProxies {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        parse_networks("HOPPER_TRUSTED_PROXIES", &value).map(Self)
+    }
+}
+
+impl AsRef<Vec<IpNet>> for TrustedProxies {
+    fn as_ref(&self) -> &Vec<IpNet> {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for CorsOrigins {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let entries = value
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<&str>>();
+        match entries.as_slice() {
+            [] => return Ok(Self::ExternalBase),
+            ["*"] => return Ok(Self::Any),
+            _ => {}
+        }
+
+        // Browsers send the serialized origin, so entries must be exactly a
+        // scheme, host and optional port.
+        entries
+            .into_iter()
+            .map(|entry| {
+                let url = url::Url::parse(entry).with_context(|| {
+                    format!("parsing HOPPER_CORS_ORIGINS entry {} failed", entry)
+                })?;
+                let origin = url.origin().ascii_serialization();
+                if !matches!(url.scheme(), "https" | "http")
+                    || origin != entry.trim_end_matches('/')
+                {
+                    return Err(anyhow!(
+                        "HOPPER_CORS_ORIGINS entry {} must be an http(s) origin, or * alone",
+                        entry
+                    ));
+                }
+                origin.parse::<HeaderValue>().map_err(anyhow::Error::from)
+            })
+            .collect::<Result<Vec<HeaderValue>>>()
+            .map(Self::List)
+    }
+}
+
+impl TryFrom<String> for CollectionAliases {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                let (alias, nsid) = s
+                    .split_once('=')
+                    .map(|(alias, nsid)| (alias.trim(), nsid.trim()))
+                    .ok_or(anyhow!(
+                        "HOPPER_COLLECTION_ALIASES entry {} must be alias=nsid",
+                        s
+                    ))?;
+                if alias.is_empty() || !is_valid_nsid(nsid) {
+                    return Err(anyhow!(
+                        "HOPPER_COLLECTION_ALIASES entry {} must map an alias to a valid NSID",
+                        s
+                    ));
+                }
+                Ok((alias.to_string(), nsid.to_string()))
+            })
+            .collect::<Result<HashMap<String, String>>>()
+            .map(Self)
+    }
+}
+
+impl TryFrom<String> for AppendQuery {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let pairs = url::form_urlencoded::parse(value.trim().as_bytes())
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect::<Vec<_>>();
+        if pairs.iter().any(|(key, _)| key.is_empty()) {
+            return Err(anyhow!(
+                "HOPPER_APPEND_QUERY {} has a parameter without a name",
+                value
+            ));
+        }
+        Ok(Self(pairs))
+    }
+}
+
+impl AsRef<[(String, String)]> for AppendQuery {
+    fn as_ref(&self) -> &[(String, String)] {
+        &self.0
+    }
+}
+
+impl AsRef<HashMap<String, String>> for CollectionAliases {
+    fn as_ref(&self) -> &HashMap<String, String> {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for Seeds {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            return Ok(Self(seeded().into_iter().collect()));
+        }
+        let content = std::fs::read_to_string(&value)
+            .with_context(|| format!("reading HOPPER_SEED_FILE {} failed", value))?;
+        let seeds = if value.ends_with(".toml") {
+            toml::from_str(&content).map_err(anyhow::Error::from)
+        } else {
+            serde_json::from_str(&content).map_err(anyhow::Error::from)
+        };
+        seeds
+            .map(Self)
+            .with_context(|| format!("parsing HOPPER_SEED_FILE {} failed", value))
+    }
+}
+
+impl AsRef<BTreeMap<String, WebHostMeta>> for Seeds {
+    fn as_ref(&self) -> &BTreeMap<String, WebHostMeta> {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for ShutdownTimeout {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value
+            .parse::<u64>()
+            .map(|seconds| Self(Duration::from_secs(seconds)))
+            .map_err(|err| {
+                anyhow::Error::new(err).context(anyhow!(
+                    "parsing HOPPER_SHUTDOWN_TIMEOUT into seconds failed"
+                ))
+            })
+    }
+}
+
+impl AsRef<Duration> for ShutdownTimeout {
+    fn as_ref(&self) -> &Duration {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for PoolMaxIdlePerHost {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            return Ok(Self(usize::MAX));
+        }
+        value.parse::<usize>().map(Self).map_err(|err| {
+            anyhow::Error::new(err).context(anyhow!("parsing HOPPER_POOL_MAX_IDLE_PER_HOST failed"))
+        })
+    }
+}
+
+impl AsRef<usize> for PoolMaxIdlePerHost {
+    fn as_ref(&self) -> &usize {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for PoolIdleTimeout {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value
+            .parse::<u64>()
+            .map(|seconds| Self(Duration::from_secs(seconds)))
+            .map_err(|err| {
+                anyhow::Error::new(err).context(anyhow!(
+                    "parsing HOPPER_POOL_IDLE_TIMEOUT into seconds failed"
+                ))
+            })
+    }
+}
+
+impl AsRef<Duration> for PoolIdleTimeout {
+    fn as_ref(&self) -> &Duration {
+        &self.0
+    }
+}
+
+/// Parses a non-zero timeout in milliseconds from the variable `name`.
+fn parse_timeout_millis(name: &str, value: &str) -> Result<Duration> {
+    match value.parse::<u64>() {
+        Ok(0) => Err(anyhow!("{} must be greater than 0", name)),
+        Ok(millis) => Ok(Duration::from_millis(millis)),
+        Err(err) => {
+            Err(anyhow::Error::new(err)
+                .context(anyhow!("parsing {} into milliseconds failed", name)))
+        }
+    }
+}
+
+impl TryFrom<String> for ConnectTimeout {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        parse_timeout_millis("HOPPER_CONNECT_TIMEOUT", &value).map(Self)
+    }
+}
+
+impl AsRef<Duration> for ConnectTimeout {
+    fn as_ref(&self) -> &Duration {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for ReadTimeout {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        parse_timeout_millis("HOPPER_READ_TIMEOUT", &value).map(Self)
+    }
+}
+
+impl AsRef<Duration> for ReadTimeout {
+    fn as_ref(&self) -> &Duration {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for RequestTimeout {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        parse_timeout_millis("HOPPER_REQUEST_TIMEOUT", &value).map(Self)
+    }
+}
+
+impl AsRef<Duration> for RequestTimeout {
+    fn as_ref(&self) -> &Duration {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for ReadinessCanary {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let value = value.trim().to_lowercase();
+        if value.is_empty() {
+            return Ok(Self(None));
+        }
+        // Reserved suffixes are checked by `Config::validate`, against the
+        // configured list.
+        let reserved_suffixes: [&str; 0] = [];
+        if !is_valid_hostname(&value, &reserved_suffixes) {
+            return Err(anyhow!(
+                "HOPPER_READINESS_CANARY {} is not a valid hostname",
+                value
+            ));
+        }
+        Ok(Self(Some(value)))
+    }
+}
+
+impl AsRef<Option<String>> for ReadinessCanary {
+    fn as_ref(&self) -> &Option<String> {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for ReadinessCanaryTtl {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.parse::<u64>() {
+            Ok(0) => Err(anyhow!(
+                "HOPPER_READINESS_CANARY_TTL must be greater than 0"
+            )),
+            Ok(seconds) => Ok(Self(Duration::from_secs(seconds))),
+            Err(err) => Err(anyhow::Error::new(err).context(anyhow!(
+                "parsing HOPPER_READINESS_CANARY_TTL into seconds failed"
+            ))),
+        }
+    }
+}
+
+impl AsRef<Duration> for ReadinessCanaryTtl {
+    fn as_ref(&self) -> &Duration {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for AdminToken {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            return Ok(Self(None));
+        }
+        if value.len() < 16 {
+            return Err(anyhow!("HOPPER_ADMIN_TOKEN must be at least 16 characters"));
+        }
+        Ok(Self(Some(value)))
+    }
+}
+
+impl AsRef<Option<String>> for AdminToken {
+    fn as_ref(&self) -> &Option<String> {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for AdminAllowlist {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        parse_networks("HOPPER_ADMIN_ALLOWLIST", &value).map(Self)
+    }
+}
+
+impl AsRef<Vec<IpNet>> for AdminAllowlist {
+    fn as_ref(&self) -> &Vec<IpNet> {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for Prewarm {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(&value)
+            .with_context(|| format!("reading HOPPER_PREWARM_FILE {} failed", value))?;
+        Ok(Self(
+            content
+                .lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| line.to_string())
+                .collect(),
+        ))
+    }
+}
+
+impl AsRef<Vec<String>> for Prewarm {
+    fn as_ref(&self) -> &Vec<String> {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for LinkRels {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let rels = value
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+        if rels.is_empty() {
+            return Err(anyhow!("HOPPER_LINK_RELS must contain at least one rel"));
+        }
+        Ok(Self(rels))
+    }
+}
+
+impl AsRef<Vec<String>> for LinkRels {
+    fn as_ref(&self) -> &Vec<String> {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for MaxServers {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse::<usize>().map(Self).map_err(|err| {
+            anyhow::Error::new(err).context(anyhow!("parsing HOPPER_MAX_SERVERS failed"))
+        })
+    }
+}
+
+impl AsRef<usize> for MaxServers {
+    fn as_ref(&self) -> &usize {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for MaxErrorServers {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse::<usize>().map(Self).map_err(|err| {
+            anyhow::Error::new(err).context(anyhow!("parsing HOPPER_MAX_ERROR_SERVERS failed"))
+        })
+    }
+}
+
+impl AsRef<usize> for MaxErrorServers {
+    fn as_ref(&self) -> &usize {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for MaxInputLength {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let max_input_length = value.parse::<usize>().map_err(|err| {
+            anyhow::Error::new(err).context(anyhow!("parsing HOPPER_MAX_INPUT_LENGTH failed"))
+        })?;
+        if max_input_length == 0 {
+            return Err(anyhow!("HOPPER_MAX_INPUT_LENGTH must be greater than 0"));
+        }
+        Ok(Self(max_input_length))
+    }
+}
+
+impl AsRef<usize> for MaxInputLength {
+    fn as_ref(&self) -> &usize {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for HostMetaSoftTtl {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            return Ok(Self(None));
+        }
+        match value.parse::<u64>() {
+            Ok(0) => Err(anyhow!("HOPPER_HOST_META_SOFT_TTL must be greater than 0")),
+            Ok(seconds) => Ok(Self(Some(Duration::from_secs(seconds)))),
+            Err(err) => Err(anyhow::Error::new(err).context(anyhow!(
+                "parsing HOPPER_HOST_META_SOFT_TTL into seconds failed"
+            ))),
+        }
+    }
+}
+
+impl AsRef<Option<Duration>> for HostMetaSoftTtl {
+    fn as_ref(&self) -> &Option<Duration> {
+        &self.0
+    }
+}
+
+/// Parses a non-zero count, such as a capacity, from the variable `name`.
+fn parse_nonzero(name: &str, value: &str) -> Result<u64> {
+    match value.parse::<u64>() {
+        Ok(0) => Err(anyhow!("{} must be greater than 0", name)),
+        Ok(capacity) => Ok(capacity),
+        Err(err) => Err(anyhow::Error::new(err).context(anyhow!("parsing {} failed", name))),
+    }
+}
+
+impl TryFrom<String> for HostMetaCacheCapacity {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        parse_nonzero("HOPPER_HOST_META_CACHE_CAPACITY", &value).map(Self)
+    }
+}
+
+impl AsRef<u64> for HostMetaCacheCapacity {
+    fn as_ref(&self) -> &u64 {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for AtUriCacheCapacity {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        parse_nonzero("HOPPER_ATURI_CACHE_CAPACITY", &value).map(Self)
+    }
+}
+
+impl AsRef<u64> for AtUriCacheCapacity {
+    fn as_ref(&self) -> &u64 {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for CacheBackend {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value == "memory" {
+            return Ok(Self::Memory);
+        }
+        // The redis client is built without TLS support.
+        if value.starts_with("rediss://") {
+            return Err(anyhow!(
+                "HOPPER_CACHE_BACKEND rediss:// URLs aren't supported, use redis://"
+            ));
+        }
+        if !value.starts_with("redis://") {
+            return Err(anyhow!(
+                "HOPPER_CACHE_BACKEND must be memory or a redis:// URL, got {}",
+                value
+            ));
+        }
+        Ok(Self::Redis(value))
+    }
+}
+
+impl TryFrom<String> for NegativeCacheJitter {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let jitter = value.parse::<f64>().map_err(|err| {
+            anyhow::Error::new(err).context(anyhow!("parsing HOPPER_NEGATIVE_CACHE_JITTER failed"))
+        })?;
+        if !(0.0..1.0).contains(&jitter) {
+            return Err(anyhow!(
+                "HOPPER_NEGATIVE_CACHE_JITTER must be at least 0 and less than 1"
+            ));
+        }
+        Ok(Self(jitter))
+    }
+}
+
+impl AsRef<f64> for NegativeCacheJitter {
+    fn as_ref(&self) -> &f64 {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for AccessLogAturi {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "true" | "1" | "yes" => Ok(Self(true)),
+            "false" | "0" | "no" => Ok(Self(false)),
+            _ => Err(anyhow!(
+                "HOPPER_ACCESS_LOG_ATURI must be true or false, got {}",
+                value
+            )),
+        }
+    }
+}
+
+impl AsRef<bool> for AccessLogAturi {
+    fn as_ref(&self) -> &bool {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for PropagateFragment {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "true" | "1" | "yes" => Ok(Self(true)),
+            "false" | "0" | "no" => Ok(Self(false)),
+            _ => Err(anyhow!(
+                "HOPPER_PROPAGATE_FRAGMENT must be true or false, got {}",
+                value
+            )),
+        }
+    }
+}
+
+impl AsRef<bool> for PropagateFragment {
+    fn as_ref(&self) -> &bool {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for StrictValidation {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "true" | "1" | "yes" => Ok(Self(true)),
+            "false" | "0" | "no" => Ok(Self(false)),
+            _ => Err(anyhow!(
+                "HOPPER_STRICT_VALIDATION must be true or false, got {}",
+                value
+            )),
+        }
+    }
+}
+
+impl AsRef<bool> for StrictValidation {
+    fn as_ref(&self) -> &bool {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for MaxFetchesPerHost {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        parse_nonzero("HOPPER_MAX_FETCHES_PER_HOST", &value).map(|max| Self(max as usize))
+    }
+}
+
+impl AsRef<usize> for MaxFetchesPerHost {
+    fn as_ref(&self) -> &usize {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for MaxFetches {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        parse_nonzero("HOPPER_MAX_FETCHES", &value).map(|max| Self(max as usize))
+    }
+}
+
+impl AsRef<usize> for MaxFetches {
+    fn as_ref(&self) -> &usize {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for MaxRedirects {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse::<usize>().map(Self).map_err(|err| {
+            anyhow::Error::new(err).context(anyhow!("parsing HOPPER_MAX_REDIRECTS failed"))
+        })
+    }
+}
+
+impl AsRef<usize> for MaxRedirects {
+    fn as_ref(&self) -> &usize {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for ResolverBase {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let value = value.trim_end_matches('/');
+        if !value.starts_with("https://") && !value.starts_with("http://") {
+            return Err(anyhow!(
+                "HOPPER_RESOLVER_BASE must start with https:// or http://"
+            ));
+        }
+        Ok(Self(value.to_string()))
+    }
+}
+
+impl AsRef<str> for ResolverBase {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for HostMetaPath {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if !value.starts_with('/') {
+            return Err(anyhow!("HOPPER_HOST_META_PATH must start with /"));
+        }
+        if value.contains(|c: char| c.is_whitespace() || c == '#') {
+            return Err(anyhow!(
+                "HOPPER_HOST_META_PATH {} must not contain whitespace or a fragment",
+                value
+            ));
+        }
+        Ok(Self(value))
+    }
+}
+
+impl AsRef<str> for HostMetaPath {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for PlcDirectory {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let value = value.trim().trim_end_matches('/');
+        let valid = url::Url::parse(value).is_ok_and(|url| {
+            url.scheme() == "https"
+                && url.host_str().is_some()
+                && url.query().is_none()
+                && url.fragment().is_none()
+        });
+        if !valid {
+            return Err(anyhow!(
+                "HOPPER_PLC_DIRECTORY {} must be an https base URL",
+                value
+            ));
+        }
+        Ok(Self(value.to_string()))
+    }
+}
+
+impl AsRef<str> for PlcDirectory {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for ReservedSuffixes {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let mut suffixes: Vec<String> = Vec::new();
+        for suffix in value.split(',').map(|s| s.trim().to_lowercase()) {
+            let suffix = match suffix.trim_start_matches('.') {
+                "" => continue,
+                label => format!(".{}", label),
+            };
+            if !suffixes.contains(&suffix) {
+                suffixes.push(suffix);
+            }
+        }
+        Ok(Self(suffixes))
+    }
+}
+
+impl AsRef<[String]> for ReservedSuffixes {
+    fn as_ref(&self) -> &[String] {
+        &self.0
+    }
+}
+
+impl HostPatterns {
+    pub fn matches(&self, host: &str) -> bool {
+        self.0
+            .iter()
+            .any(|pattern| match pattern.strip_prefix("*.") {
+                Some(suffix) => host
+                    .strip_suffix(suffix)
+                    .is_some_and(|subdomain| subdomain.ends_with('.') && subdomain.len() > 1),
+                None => host.eq_ignore_ascii_case(pattern),
+            })
+    }
+}
+
+impl TryFrom<String> for HostPatterns {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Ok(Self(
+            value
+                .split(',')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+        ))
+    }
+}
+
+impl AsRef<Vec<String>> for HostPatterns {
+    fn as_ref(&self) -> &Vec<String> {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use std::{
+        net::{IpAddr, Ipv6Addr, SocketAddr},
+        path::PathBuf,
+    };
+
+    use super::{
+        AppendQuery, BindAddress, CollectionAliases, Config, HostPatterns, Listen, ReservedSuffixes,
+    };
+
+    #[test]
+    fn test_from_file() {
+        let path = std::env::temp_dir().join(format!("hopper-config-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+http_port = 8080
+external_base = "hopper.example"
+certificate_bundles = ["/etc/ssl/a.pem", "/etc/ssl/b.pem"]
+user_agent = "hopper-test"
+server_timeout = 500
+languages = ["en-us", "fr-fr"]
+"#,
+        )
+        .unwrap();
+
+        let config = Config::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(*config.http_port.as_ref(), 8080);
+        assert_eq!(config.external_base, "hopper.example");
+        assert_eq!(
+            config.certificate_bundles.as_ref(),
+            &vec!["/etc/ssl/a.pem".to_string(), "/etc/ssl/b.pem".to_string()]
+        );
+        assert_eq!(config.user_agent, "hopper-test");
+        assert_eq!(*config.server_timeout.as_ref(), Duration::from_millis(500));
+        assert_eq!(
+            config
+                .languages
+                .as_ref()
+                .iter()
+                .map(|language| language.to_string())
+                .collect::<Vec<String>>(),
+            vec!["en-US".to_string(), "fr-FR".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_listen() {
+        assert_eq!(
+            Listen::try_from("tcp://0.0.0.0:4060".to_string()).unwrap(),
+            Listen::Tcp("0.0.0.0:4060".parse().unwrap())
+        );
+        assert_eq!(
+            Listen::try_from("unix:///run/hopper/hopper.sock".to_string()).unwrap(),
+            Listen::Unix(PathBuf::from("/run/hopper/hopper.sock"))
+        );
+        assert!(Listen::try_from("unix://".to_string()).is_err());
+        assert!(Listen::try_from("tcp://localhost".to_string()).is_err());
+        assert!(Listen::try_from("0.0.0.0:4060".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_bind_address() {
+        let path = std::env::temp_dir().join(format!("hopper-bind-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+http_port = 4060
+external_base = "hopper.example"
+bind_address = "[::]"
+"#,
+        )
+        .unwrap();
+
+        let config = Config::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        let unspecified = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+        assert_eq!(*config.bind_address.as_ref(), unspecified);
+        assert_eq!(
+            config.listen,
+            Listen::Tcp(SocketAddr::new(unspecified, 4060))
+        );
+
+        assert!(BindAddress::try_from("127.0.0.1".to_string()).is_ok());
+        assert!(BindAddress::try_from("::1".to_string()).is_ok());
+        assert!(BindAddress::try_from("localhost".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_append_query() {
+        let append_query =
+            AppendQuery::try_from("utm_source=hopper&ref=a%26b".to_string()).unwrap();
+        assert_eq!(
+            append_query.as_ref(),
+            &[
+                ("utm_source".to_string(), "hopper".to_string()),
+                ("ref".to_string(), "a&b".to_string()),
+            ]
+        );
+        assert!(AppendQuery::try_from(String::new())
+            .unwrap()
+            .as_ref()
+            .is_empty());
+        assert!(AppendQuery::try_from("=hopper".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_collection_aliases() {
+        let config = Config::from_toml(
+            r#"
+external_base = "hopper.example"
+
+[collection_aliases]
+post = "app.bsky.feed.post"
+event = "events.smokesignal.calendar.event"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.collection_aliases.as_ref().get("post"),
+            Some(&"app.bsky.feed.post".to_string())
+        );
+        assert_eq!(
+            config.collection_aliases.as_ref().get("event"),
+            Some(&"events.smokesignal.calendar.event".to_string())
+        );
+
+        assert!(CollectionAliases::try_from("post".to_string()).is_err());
+        assert!(CollectionAliases::try_from("post=post".to_string()).is_err());
+        assert!(CollectionAliases::try_from(String::new())
+            .unwrap()
+            .as_ref()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_host_patterns() {
+        let patterns = HostPatterns::try_from("example.com, *.Example.NET".to_string()).unwrap();
+        assert!(patterns.matches("example.com"));
+        assert!(!patterns.matches("www.example.com"));
+        assert!(patterns.matches("www.example.net"));
+        assert!(!patterns.matches("example.net"));
+        assert!(!patterns.matches("badexample.net"));
+    }
+
+    #[test]
+    fn test_reserved_suffixes() {
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+        assert_eq!(
+            config.reserved_suffixes.as_ref(),
+            [".localhost", ".internal", ".arpa", ".local"]
+        );
+
+        let config = Config::from_toml(
+            r#"
+external_base = "hopper.test"
+reserved_suffixes = ["local", ".CORP", "localhost"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.reserved_suffixes.as_ref(),
+            [".local", ".corp", ".localhost"]
+        );
+
+        let reserved_suffixes: ReservedSuffixes = " , .,".to_string().try_into().unwrap();
+        assert!(reserved_suffixes.as_ref().is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+        assert!(config.validate().is_ok());
+
+        let config = Config::from_toml(r#"external_base = "hopper.test:8443""#).unwrap();
+        assert!(config.validate().is_ok());
+
+        let config = Config::from_toml(
+            r#"
+http_port = 0
+external_base = "https://hopper.test/"
+certificate_bundles = ["/nonexistent/hopper.pem"]
+reserved_suffixes = [".app", ".events"]
+readiness_canary = "canary.events"
+"#,
+        )
+        .unwrap();
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("EXTERNAL_BASE"), "{}", err);
+        assert!(err.contains("nonzero port"), "{}", err);
+        assert!(err.contains("/nonexistent/hopper.pem"), "{}", err);
+        assert!(err.contains("default server bsky.app"), "{}", err);
+        assert!(
+            err.contains("HOPPER_READINESS_CANARY canary.events"),
+            "{}",
+            err
+        );
+
+        for external_base in ["hopper test", "hopper.test/path", "hopper.test?q=1", ""] {
+            let config = Config::from_toml(&format!("external_base = {:?}", external_base));
+            assert!(
+                config.unwrap().validate().is_err(),
+                "{} should be rejected",
+                external_base
+            );
+        }
+    }
+
+    #[test]
+    fn test_plc_directory() {
+        use super::PlcDirectory;
+
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+        assert_eq!(config.plc_directory.as_ref(), "https://plc.directory");
+
+        let plc_directory: PlcDirectory = "https://plc.example/mirror/"
+            .to_string()
+            .try_into()
+            .unwrap();
+        assert_eq!(plc_directory.as_ref(), "https://plc.example/mirror");
+
+        for invalid in [
+            "http://plc.example",
+            "plc.example",
+            "https://plc.example/?q=1",
+            "https://",
+        ] {
+            assert!(
+                PlcDirectory::try_from(invalid.to_string()).is_err(),
+                "{} should be rejected",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_cache_backend() {
+        use super::CacheBackend;
+
+        assert_eq!(
+            CacheBackend::try_from("memory".to_string()).unwrap(),
+            CacheBackend::Memory
+        );
+        assert_eq!(
+            CacheBackend::try_from("redis://127.0.0.1:6379/".to_string()).unwrap(),
+            CacheBackend::Redis("redis://127.0.0.1:6379/".to_string())
+        );
+        for invalid in ["rediss://redis.example:6380/", "valkey://127.0.0.1", ""] {
+            assert!(
+                CacheBackend::try_from(invalid.to_string()).is_err(),
+                "{} should be rejected",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_cors_origins() {
+        use super::CorsOrigins;
+
+        assert_eq!(
+            CorsOrigins::try_from(String::new()).unwrap(),
+            CorsOrigins::ExternalBase
+        );
+        assert_eq!(
+            CorsOrigins::try_from(" * ".to_string()).unwrap(),
+            CorsOrigins::Any
+        );
+        assert_eq!(
+            CorsOrigins::try_from("https://hopper.test, http://links.example:8080/".to_string())
+                .unwrap(),
+            CorsOrigins::List(vec![
+                "https://hopper.test".parse().unwrap(),
+                "http://links.example:8080".parse().unwrap(),
+            ])
+        );
+
+        for invalid in [
+            "hopper.test",
+            "https://hopper.test/app",
+            "ftp://hopper.test",
+            "https://hopper.test,*",
+        ] {
+            assert!(
+                CorsOrigins::try_from(invalid.to_string()).is_err(),
+                "{} should be rejected",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use urlencoding::decode;
+
+use crate::{config::Config, fetch::HttpFetcher, model::is_valid_hostname};
+
+/// The service entry of a DID document that points at the account's PDS.
+const SERVICE_ATPROTO_PDS: &str = "#atproto_pds";
+const TYPE_ATPROTO_PDS: &str = "AtprotoPersonalDataServer";
+
+/// The PLC directory `did:plc` documents are fetched from by default.
+pub const DEFAULT_PLC_DIRECTORY: &str = "https://plc.directory";
+
+const ACCEPT_DID: &str = "application/did+ld+json, application/did+json, application/json";
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DidDocument {
+    pub id: String,
+
+    #[serde(default)]
+    pub also_known_as: Vec<String>,
+
+    #[serde(default)]
+    pub service: Vec<Service>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Service {
+    pub id: String,
+
+    #[serde(rename = "type")]
+    pub service_type: String,
+
+    pub service_endpoint: String,
+}
+
+impl DidDocument {
+    /// Returns the handle claimed by the document, the first `at://` entry of
+    /// `alsoKnownAs`.
+    pub fn handle(&self) -> Option<&str> {
+        self.also_known_as
+            .iter()
+            .find_map(|aka| aka.strip_prefix("at://"))
+    }
+
+    /// Returns the endpoint of the atproto PDS service. Service ids may be
+    /// relative, as `#atproto_pds`, or qualified with the DID.
+    pub fn pds_endpoint(&self) -> Option<&str> {
+        self.service
+            .iter()
+            .find(|service| {
+                service.service_type == TYPE_ATPROTO_PDS
+                    && service
+                        .id
+                        .strip_prefix(self.id.as_str())
+                        .unwrap_or(&service.id)
+                        == SERVICE_ATPROTO_PDS
+            })
+            .map(|service| service.service_endpoint.as_str())
+    }
+}
+
+/// Returns the URL of the DID document of a `did:web` DID, following the
+/// did:web method spec. A bare host uses `/.well-known/did.json`, and any
+/// further colon separated segments become the path to `did.json`.
+///
+/// The host must be a domain name outside the reserved suffixes that
+/// `config` allows resolution to query, as for host-meta servers. Ports,
+/// which the spec encodes in the host as `%3A`, aren't supported.
+pub fn did_web_url(config: &Config, did: &str) -> Result<String> {
+    let method_specific_id = did
+        .strip_prefix("did:web:")
+        .ok_or(anyhow!("{} is not a did:web DID", did))?;
+
+    let mut segments = method_specific_id.split(':');
+    let host = segments.next().unwrap_or_default().to_lowercase();
+    // A TLD of only digits would allow IPv4 addresses.
+    let is_domain = host
+        .rsplit_once('.')
+        .is_some_and(|(_, tld)| !tld.bytes().all(|byte| byte.is_ascii_digit()));
+    if !is_domain || !is_valid_hostname(&host, config.reserved_suffixes.as_ref()) {
+        return Err(anyhow!("{} has an invalid host", did));
+    }
+    if !config.is_host_allowed(&host) {
+        return Err(anyhow!("{} has a disallowed host", did));
+    }
+
+    let path = segments.collect::<Vec<&str>>();
+    if !path.iter().all(|segment| is_valid_path_segment(segment)) {
+        return Err(anyhow!("{} has an invalid path segment", did));
+    }
+
+    if path.is_empty() {
+        Ok(format!("https://{}/.well-known/did.json", host))
+    } else {
+        Ok(format!("https://{}/{}/did.json", host, path.join("/")))
+    }
+}
+
+/// Returns true for a non-empty path segment of unreserved and
+/// percent-encoded characters that doesn't decode to a dot segment, which
+/// would move the document URL up the path.
+fn is_valid_path_segment(segment: &str) -> bool {
+    !segment.is_empty()
+        && segment
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"-._~%".contains(&byte))
+        && decode(segment).is_ok_and(|decoded| decoded != "." && decoded != "..")
+}
+
+/// Returns the URL of the DID document of a `did:plc` DID in the PLC
+/// directory at `plc_directory`.
+pub fn did_plc_url(plc_directory: &str, did: &str) -> Result<String> {
+    let identifier = did
+        .strip_prefix("did:plc:")
+        .ok_or(anyhow!("{} is not a did:plc DID", did))?;
+    if identifier.is_empty()
+        || !identifier
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+    {
+        return Err(anyhow!("{} has an invalid identifier", did));
+    }
+    Ok(format!("{}/{}", plc_directory, did))
+}
+
+/// Fetches and parses the DID document of a `did:web` or `did:plc` DID.
+/// PLC documents are fetched from the configured PLC directory.
+pub async fn query<F: HttpFetcher + ?Sized>(
+    http_fetcher: &F,
+    config: &Config,
+    did: &str,
+) -> Result<DidDocument> {
+    let url = if did.starts_with("did:plc:") {
+        did_plc_url(config.plc_directory.as_ref(), did)?
+    } else {
+        did_web_url(config, did)?
+    };
+
+    let body = http_fetcher
+        .get_text(&url, ACCEPT_DID)
+        .await
+        .context("did document get failed")?;
+    let document: DidDocument = serde_json::from_str(&body).context("did document parse failed")?;
+
+    if document.id != did {
+        return Err(anyhow!(
+            "did document id {} doesn't match {}",
+            document.id,
+            did
+        ));
+    }
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{did_plc_url, did_web_url, query, DidDocument, DEFAULT_PLC_DIRECTORY};
+    use crate::{config::Config, fetch::tests::MockFetcher};
+
+    fn config() -> Config {
+        Config::from_toml(r#"external_base = "hopper.test""#).unwrap()
+    }
+
+    const DOCUMENT: &str = r##"{
+  "@context": ["https://www.w3.org/ns/did/v1"],
+  "id": "did:web:alice.example",
+  "alsoKnownAs": ["at://alice.example"],
+  "service": [
+    {
+      "id": "#atproto_pds",
+      "type": "AtprotoPersonalDataServer",
+      "serviceEndpoint": "https://pds.example"
+    }
+  ]
+}"##;
+
+    #[test]
+    fn test_did_web_url() {
+        let config = config();
+        assert_eq!(
+            did_web_url(&config, "did:web:example.com").unwrap(),
+            "https://example.com/.well-known/did.json"
+        );
+        assert_eq!(
+            did_web_url(&config, "did:web:example.com:user:alice").unwrap(),
+            "https://example.com/user/alice/did.json"
+        );
+        assert_eq!(
+            did_web_url(&config, "did:web:example.com:user%20alice").unwrap(),
+            "https://example.com/user%20alice/did.json"
+        );
+
+        for invalid in [
+            "did:plc:abc",
+            "did:web:",
+            "did:web:example.com::alice",
+            "did:web:localhost",
+            "did:web:localhost%3A3000",
+            "did:web:example.com%3A8443",
+            "did:web:127.0.0.1",
+            "did:web:pds.internal",
+            "did:web:example.com:..:admin",
+            "did:web:example.com:%2e%2E",
+            "did:web:example.com:user?admin",
+        ] {
+            assert!(
+                did_web_url(&config, invalid).is_err(),
+                "{} should be rejected",
+                invalid
+            );
+        }
+
+        // The configured reserved suffixes and host lists apply.
+        let config = Config::from_toml(
+            r#"
+external_base = "hopper.test"
+reserved_suffixes = [".local"]
+host_denylist = ["blocked.example"]
+"#,
+        )
+        .unwrap();
+        assert!(did_web_url(&config, "did:web:pds.internal").is_ok());
+        assert!(did_web_url(&config, "did:web:blocked.example").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query() {
+        let http_fetcher =
+            MockFetcher::new([("https://alice.example/.well-known/did.json", DOCUMENT)]);
+
+        let document: DidDocument = query(&http_fetcher, &config(), "did:web:alice.example")
+            .await
+            .unwrap();
+        assert_eq!(
+            document.also_known_as,
+            vec!["at://alice.example".to_string()]
+        );
+        assert_eq!(document.pds_endpoint(), Some("https://pds.example"));
+
+        // A document served for another DID is rejected.
+        let http_fetcher =
+            MockFetcher::new([("https://bob.example/.well-known/did.json", DOCUMENT)]);
+        assert!(query(&http_fetcher, &config(), "did:web:bob.example")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_plc() {
+        let http_fetcher = MockFetcher::new([(
+            "https://plc.directory/did:plc:ewvi7nxzyoun6zhxrhs64oiz",
+            r##"{
+  "@context": [
+    "https://www.w3.org/ns/did/v1",
+    "https://w3id.org/security/multikey/v1",
+    "https://w3id.org/security/suites/secp256k1-2019/v1"
+  ],
+  "id": "did:plc:ewvi7nxzyoun6zhxrhs64oiz",
+  "alsoKnownAs": ["at://atproto.com"],
+  "verificationMethod": [
+    {
+      "id": "did:plc:ewvi7nxzyoun6zhxrhs64oiz#atproto",
+      "type": "Multikey",
+      "controller": "did:plc:ewvi7nxzyoun6zhxrhs64oiz",
+      "publicKeyMultibase": "zQ3shunBKsXixLxKtC5qeSG9E4J5RkGN57im31pcTzbNQnm5w"
+    }
+  ],
+  "service": [
+    {
+      "id": "#atproto_pds",
+      "type": "AtprotoPersonalDataServer",
+      "serviceEndpoint": "https://enoki.us-east.host.bsky.network"
+    }
+  ]
+}"##,
+        )]);
+
+        let document = query(&http_fetcher, &config(), "did:plc:ewvi7nxzyoun6zhxrhs64oiz")
+            .await
+            .unwrap();
+        assert_eq!(document.handle(), Some("atproto.com"));
+        assert_eq!(
+            document.pds_endpoint(),
+            Some("https://enoki.us-east.host.bsky.network")
+        );
+
+        assert_eq!(
+            did_plc_url(DEFAULT_PLC_DIRECTORY, "did:plc:ewvi7nxzyoun6zhxrhs64oiz").unwrap(),
+            "https://plc.directory/did:plc:ewvi7nxzyoun6zhxrhs64oiz"
+        );
+        assert!(did_plc_url(DEFAULT_PLC_DIRECTORY, "did:plc:../admin").is_err());
+        assert!(did_plc_url(DEFAULT_PLC_DIRECTORY, "did:web:example.com").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_plc_directory() {
+        let http_fetcher = MockFetcher::new([(
+            "https://plc.example/mirror/did:plc:ewvi7nxzyoun6zhxrhs64oiz",
+            r#"{ "id": "did:plc:ewvi7nxzyoun6zhxrhs64oiz" }"#,
+        )]);
+        let config = Config::from_toml(
+            r#"
+external_base = "hopper.test"
+plc_directory = "https://plc.example/mirror/"
+"#,
+        )
+        .unwrap();
+
+        let document = query(&http_fetcher, &config, "did:plc:ewvi7nxzyoun6zhxrhs64oiz")
+            .await
+            .unwrap();
+        assert_eq!(document.id, "did:plc:ewvi7nxzyoun6zhxrhs64oiz");
+        assert_eq!(
+            *http_fetcher.requests.lock().unwrap(),
+            vec![(
+                "https://plc.example/mirror/did:plc:ewvi7nxzyoun6zhxrhs64oiz".to_string(),
+                super::ACCEPT_DID.to_string()
+            )]
+        );
+    }
+}
@@ -3,6 +3,22 @@ use axum::{
     response::{IntoResponse, Response},
 };
 
+pub(crate) const ERROR_INTERNAL_SERVER_ERROR: &str =
+    "error-internal-server-error Internal Server Error";
+
+/// Broad categories of errors, used to pick the response status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The request itself was malformed, e.g. an invalid AT-URI.
+    BadRequest,
+
+    /// The request was valid but nothing could be resolved for it.
+    NotFound,
+
+    /// Anything else, including failures that aren't the client's fault.
+    Internal,
+}
+
 #[derive(Debug)]
 pub struct HopperError(pub anyhow::Error);
 
@@ -15,12 +31,40 @@ where
     }
 }
 
+impl HopperError {
+    pub fn kind(&self) -> ErrorKind {
+        let (bare, _) = expand_error(self.0.to_string());
+        match bare.as_str() {
+            "error-web-invalid-aturi" | "error-web-invalid-url" => ErrorKind::BadRequest,
+            "error-web-unsupported-aturi"
+            | "error-web-invalid-destination"
+            | "error-web-unsupported-url" => ErrorKind::NotFound,
+            _ => ErrorKind::Internal,
+        }
+    }
+}
+
+impl ErrorKind {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ErrorKind::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            ErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
 impl IntoResponse for HopperError {
     fn into_response(self) -> Response {
-        {
+        let kind = self.kind();
+        if kind == ErrorKind::Internal {
             tracing::error!(error = ?self.0, "internal server error");
-            (StatusCode::INTERNAL_SERVER_ERROR).into_response()
+        } else {
+            tracing::debug!(error = ?self.0, ?kind, "client error");
         }
+        let mut response = kind.status_code().into_response();
+        response.extensions_mut().insert(kind);
+        response
     }
 }
 
@@ -30,3 +74,30 @@ pub(crate) fn expand_error<S: Into<String>>(err: S) -> (String, String) {
     let partial = err.split(':').next().unwrap_or_default().to_string();
     (bare, partial)
 }
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use axum::response::IntoResponse;
+    use http::StatusCode;
+
+    use super::{ErrorKind, HopperError};
+
+    #[test]
+    fn test_error_kind() {
+        let err = HopperError(anyhow!("error-web-invalid-aturi Invalid AT-URI"));
+        assert_eq!(err.kind(), ErrorKind::BadRequest);
+        assert_eq!(err.into_response().status(), StatusCode::BAD_REQUEST);
+
+        let err = HopperError(anyhow!("error-web-unsupported-aturi Unsupported AT-URI"));
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert_eq!(err.into_response().status(), StatusCode::NOT_FOUND);
+
+        let err = HopperError(anyhow!("template not found"));
+        assert_eq!(err.kind(), ErrorKind::Internal);
+        assert_eq!(
+            err.into_response().status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
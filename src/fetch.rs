@@ -0,0 +1,476 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::Semaphore;
+
+use crate::{
+    config::Config,
+    model::{is_ip_literal, is_valid_hostname},
+};
+
+/// Returns true when a redirect to `url` keeps the scheme and port of
+/// `resolver_base`, and its host is a domain name that resolution may query.
+/// IP addresses are refused, as no `server` parameter can name one.
+fn is_redirect_allowed(config: &Config, resolver_base: Option<&url::Url>, url: &url::Url) -> bool {
+    let same_origin_kind = resolver_base.is_some_and(|base| {
+        url.scheme() == base.scheme() && url.port_or_known_default() == base.port_or_known_default()
+    });
+    same_origin_kind
+        && url.host_str().is_some_and(|host| {
+            let host = host.to_lowercase();
+            !is_ip_literal(&host)
+                && host
+                    .rsplit_once('.')
+                    .is_none_or(|(_, tld)| !tld.bytes().all(|byte| byte.is_ascii_digit()))
+                && is_valid_hostname(&host, config.reserved_suffixes.as_ref())
+                && config.is_host_allowed(&host)
+        })
+}
+
+/// Follows at most the configured number of redirects, and only to hosts
+/// that resolution may query, on the scheme and port host-meta is fetched
+/// from. A redirect anywhere else fails the request rather than returning
+/// the redirect response.
+pub fn redirect_policy(config: &Config) -> reqwest::redirect::Policy {
+    let config = config.clone();
+    let resolver_base = url::Url::parse(
+        &config
+            .resolver_base
+            .as_ref()
+            .replace("{host}", "resolver.invalid"),
+    )
+    .ok();
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > *config.max_redirects.as_ref() {
+            return attempt.error(format!(
+                "more than {} redirects",
+                config.max_redirects.as_ref()
+            ));
+        }
+        if !is_redirect_allowed(&config, resolver_base.as_ref(), attempt.url()) {
+            let err = format!("redirect to disallowed host {}", attempt.url());
+            return attempt.error(err);
+        }
+        attempt.follow()
+    })
+}
+
+/// Returns a client builder with the configured CA certificates, user agent,
+/// connection pool, timeouts, and redirect policy applied.
+pub fn http_client_builder(config: &Config) -> Result<reqwest::ClientBuilder> {
+    let mut client_builder = reqwest::Client::builder();
+    for ca_certificate in config.certificate_bundles.as_ref() {
+        tracing::info!("Loading CA certificate: {:?}", ca_certificate);
+        let cert = std::fs::read(ca_certificate)?;
+        let cert = reqwest::Certificate::from_pem(&cert)?;
+        client_builder = client_builder.add_root_certificate(cert);
+    }
+
+    Ok(client_builder
+        .user_agent(config.user_agent.clone())
+        .pool_max_idle_per_host(*config.pool_max_idle_per_host.as_ref())
+        .pool_idle_timeout(*config.pool_idle_timeout.as_ref())
+        .connect_timeout(*config.connect_timeout.as_ref())
+        .read_timeout(*config.read_timeout.as_ref())
+        .timeout(*config.request_timeout.as_ref())
+        .redirect(redirect_policy(config)))
+}
+
+/// Performs the HTTP requests made while resolving, so that tests can answer
+/// them with canned documents.
+#[async_trait]
+pub trait HttpFetcher: Send + Sync {
+    /// Fetches `url`, sending `accept` as the `Accept` header, and returns the
+    /// response body.
+    async fn get_text(&self, url: &str, accept: &str) -> Result<String>;
+
+    /// Requests the headers of `url` and returns the values of its `Link`
+    /// headers. Fetchers that can't see headers report none.
+    async fn get_links(&self, _url: &str) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+#[async_trait]
+impl HttpFetcher for reqwest::Client {
+    async fn get_text(&self, url: &str, accept: &str) -> Result<String> {
+        Ok(self
+            .get(url)
+            .header(reqwest::header::ACCEPT, accept)
+            .send()
+            .await?
+            .text()
+            .await?)
+    }
+
+    async fn get_links(&self, url: &str) -> Result<Vec<String>> {
+        let response = self.head(url).send().await?;
+        Ok(response
+            .headers()
+            .get_all(reqwest::header::LINK)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+/// How long a request waits for a free slot before it fails.
+const FETCH_QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Bounds the requests in flight to each host, and overall, so a burst of
+/// resolutions against one server doesn't open a connection per request.
+/// Excess requests wait in line for up to [`FETCH_QUEUE_TIMEOUT`].
+pub struct HostLimitedFetcher<F> {
+    inner: F,
+    max_per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+    global: Semaphore,
+}
+
+impl<F> HostLimitedFetcher<F> {
+    pub fn new(inner: F, max_per_host: usize, max_total: usize) -> Self {
+        Self {
+            inner,
+            max_per_host: max_per_host.min(Semaphore::MAX_PERMITS),
+            hosts: Default::default(),
+            global: Semaphore::new(max_total.min(Semaphore::MAX_PERMITS)),
+        }
+    }
+
+    fn host_semaphore(&self, host: &str) -> Arc<Semaphore> {
+        let mut hosts = self.hosts.lock().expect("hosts lock poisoned");
+        Arc::clone(
+            hosts
+                .entry(host.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_host))),
+        )
+    }
+
+    /// Drops the semaphore of `host` once no request holds or waits on it.
+    fn release_host(&self, host: &str, semaphore: Arc<Semaphore>) {
+        let mut hosts = self.hosts.lock().expect("hosts lock poisoned");
+        if Arc::strong_count(&semaphore) == 2 {
+            hosts.remove(host);
+        }
+    }
+}
+
+impl<F: HttpFetcher> HostLimitedFetcher<F> {
+    /// Runs `fetch` once a slot for the host of `url` is free.
+    async fn limited<T>(
+        &self,
+        url: &str,
+        fetch: impl std::future::Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let host = url::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_lowercase))
+            .unwrap_or_default();
+
+        let semaphore = self.host_semaphore(&host);
+        let permits = tokio::time::timeout(FETCH_QUEUE_TIMEOUT, async {
+            let host_permit = Arc::clone(&semaphore).acquire_owned().await?;
+            let global_permit = self.global.acquire().await?;
+            Ok::<_, tokio::sync::AcquireError>((host_permit, global_permit))
+        })
+        .await;
+
+        let result = match permits {
+            Ok(Ok(_permits)) => fetch.await,
+            Ok(Err(err)) => Err(err.into()),
+            Err(_) => Err(anyhow!("timed out waiting to fetch {}", url)),
+        };
+        self.release_host(&host, semaphore);
+        result
+    }
+}
+
+#[async_trait]
+impl<F: HttpFetcher> HttpFetcher for HostLimitedFetcher<F> {
+    async fn get_text(&self, url: &str, accept: &str) -> Result<String> {
+        self.limited(url, self.inner.get_text(url, accept)).await
+    }
+
+    async fn get_links(&self, url: &str) -> Result<Vec<String>> {
+        self.limited(url, self.inner.get_links(url)).await
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use anyhow::{anyhow, Result};
+    use async_trait::async_trait;
+    use futures_util::future::join_all;
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        time::Duration,
+    };
+
+    use super::{http_client_builder, is_redirect_allowed, HostLimitedFetcher, HttpFetcher};
+    use crate::config::Config;
+
+    /// Answers every request after a short delay, recording the most
+    /// requests it had in flight at once.
+    #[derive(Default)]
+    struct SlowFetcher {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl HttpFetcher for SlowFetcher {
+        async fn get_text(&self, _url: &str, _accept: &str) -> Result<String> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok("{}".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_host_limited_fetcher() {
+        let http_fetcher = HostLimitedFetcher::new(SlowFetcher::default(), 2, 64);
+
+        let results = join_all((0..16).map(|_| {
+            http_fetcher.get_text("https://links.example/.well-known/host-meta.json", "*/*")
+        }))
+        .await;
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(http_fetcher.inner.max_in_flight.load(Ordering::SeqCst), 2);
+        assert!(http_fetcher.hosts.lock().unwrap().is_empty());
+
+        // Other hosts have their own limit, within the overall one.
+        let http_fetcher = HostLimitedFetcher::new(SlowFetcher::default(), 2, 3);
+        let urls = (0..16)
+            .map(|index| format!("https://links{}.example/", index % 4))
+            .collect::<Vec<_>>();
+        join_all(urls.iter().map(|url| http_fetcher.get_text(url, "*/*"))).await;
+        assert_eq!(http_fetcher.inner.max_in_flight.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_redirect_policy() {
+        use axum::{response::Redirect, routing::get, Router};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let port = address.port();
+        let app = Router::new()
+            .route("/end", get(|| async { "{}" }))
+            .route(
+                "/allowed",
+                get(move || async move {
+                    Redirect::temporary(&format!("http://allowed.example:{}/end", port))
+                }),
+            )
+            .route(
+                "/blocked",
+                get(move || async move {
+                    Redirect::temporary(&format!("http://blocked.example:{}/end", port))
+                }),
+            )
+            .route(
+                "/chain",
+                get(move || async move {
+                    Redirect::temporary(&format!("http://allowed.example:{}/allowed", port))
+                }),
+            )
+            .route(
+                "/long",
+                get(move || async move {
+                    Redirect::temporary(&format!("http://allowed.example:{}/chain", port))
+                }),
+            )
+            .route(
+                "/ip",
+                get(move || async move {
+                    Redirect::temporary(&format!("http://127.0.0.1:{}/end", port))
+                }),
+            )
+            .route(
+                "/port",
+                get(|| async { Redirect::temporary("http://allowed.example:6379/end") }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        // Host-meta is fetched over plain http on the listener's port here,
+        // so redirects have to keep both.
+        let config = Config::from_toml(&format!(
+            r#"
+external_base = "hopper.test"
+host_denylist = ["blocked.example"]
+resolver_base = "http://{{host}}:{}"
+"#,
+            port
+        ))
+        .unwrap();
+        let http_client = http_client_builder(&config)
+            .unwrap()
+            .resolve("allowed.example", address)
+            .resolve("blocked.example", address)
+            .build()
+            .unwrap();
+        let url = |path: &str| format!("http://allowed.example:{}{}", port, path);
+
+        let body = http_client.get_text(&url("/allowed"), "*/*").await.unwrap();
+        assert_eq!(body, "{}");
+        assert!(http_client.get_text(&url("/chain"), "*/*").await.is_ok());
+
+        let err = http_client
+            .get_text(&url("/blocked"), "*/*")
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("disallowed host"));
+
+        // Internal addresses and other ports are refused even though the
+        // listener answers there.
+        for path in ["/ip", "/port"] {
+            let err = http_client.get_text(&url(path), "*/*").await.unwrap_err();
+            assert!(format!("{:#}", err).contains("disallowed host"), "{}", path);
+        }
+
+        // Three redirects exceed the default of two.
+        assert!(http_client.get_text(&url("/long"), "*/*").await.is_err());
+    }
+
+    #[test]
+    fn test_is_redirect_allowed() {
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+        let base = url::Url::parse("https://resolver.invalid").ok();
+        let allowed =
+            |url: &str| is_redirect_allowed(&config, base.as_ref(), &url::Url::parse(url).unwrap());
+
+        assert!(allowed("https://links.example/.well-known/host-meta.json"));
+        assert!(allowed("https://links.example:443/"));
+        for url in [
+            "http://links.example/",
+            "https://links.example:8443/",
+            "https://127.0.0.1/",
+            "https://169.254.169.254/latest/meta-data/",
+            "https://[::1]/",
+            "https://printer.local/",
+        ] {
+            assert!(!allowed(url), "{} should be refused", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_client_builder() {
+        use tokio::net::TcpListener;
+
+        let config = Config::from_toml(
+            r#"
+external_base = "hopper.test"
+pool_max_idle_per_host = 4
+pool_idle_timeout = 30
+connect_timeout = 250
+read_timeout = 500
+request_timeout = 5000
+"#,
+        )
+        .unwrap();
+        assert_eq!(*config.pool_max_idle_per_host.as_ref(), 4);
+        assert_eq!(*config.pool_idle_timeout.as_ref(), Duration::from_secs(30));
+        assert_eq!(*config.connect_timeout.as_ref(), Duration::from_millis(250));
+        assert_eq!(*config.read_timeout.as_ref(), Duration::from_millis(500));
+        assert_eq!(*config.request_timeout.as_ref(), Duration::from_secs(5));
+
+        // A server that accepts but never answers is given up on after the
+        // read timeout, well before the request timeout.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.push(stream);
+            }
+        });
+        let client = http_client_builder(&config).unwrap().build().unwrap();
+        let started = std::time::Instant::now();
+        let err = client
+            .get(format!("http://{}/", address))
+            .send()
+            .await
+            .unwrap_err();
+        assert!(err.is_timeout(), "{:?}", err);
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(500), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(5), "{:?}", elapsed);
+
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+        assert_eq!(*config.pool_max_idle_per_host.as_ref(), usize::MAX);
+        assert_eq!(*config.connect_timeout.as_ref(), Duration::from_secs(1));
+        assert_eq!(*config.request_timeout.as_ref(), Duration::from_secs(3));
+
+        for invalid in [
+            "connect_timeout = 0",
+            "read_timeout = 4000",
+            "pool_idle_timeout = -1",
+        ] {
+            assert!(
+                Config::from_toml(&format!("external_base = \"hopper.test\"\n{}", invalid))
+                    .is_err()
+            );
+        }
+    }
+
+    /// Answers requests from a map of URL to body, recording the URL and
+    /// `Accept` header of every request.
+    #[derive(Default)]
+    pub(crate) struct MockFetcher {
+        pub(crate) responses: HashMap<String, String>,
+        pub(crate) links: HashMap<String, Vec<String>>,
+        pub(crate) requests: Mutex<Vec<(String, String)>>,
+    }
+
+    impl MockFetcher {
+        pub(crate) fn new<const N: usize>(responses: [(&str, &str); N]) -> Self {
+            Self {
+                responses: responses
+                    .into_iter()
+                    .map(|(url, body)| (url.to_string(), body.to_string()))
+                    .collect(),
+                links: Default::default(),
+                requests: Default::default(),
+            }
+        }
+
+        /// Answers header requests for `url` with a `Link` header of `link`.
+        pub(crate) fn with_link(mut self, url: &str, link: &str) -> Self {
+            self.links
+                .entry(url.to_string())
+                .or_default()
+                .push(link.to_string());
+            self
+        }
+    }
+
+    #[async_trait]
+    impl HttpFetcher for MockFetcher {
+        async fn get_text(&self, url: &str, accept: &str) -> Result<String> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((url.to_string(), accept.to_string()));
+            self.responses
+                .get(url)
+                .cloned()
+                .ok_or(anyhow!("no response for {}", url))
+        }
+
+        async fn get_links(&self, url: &str) -> Result<Vec<String>> {
+            Ok(self.links.get(url).cloned().unwrap_or_default())
+        }
+    }
+}
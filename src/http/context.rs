@@ -1,12 +1,20 @@
+use anyhow::{anyhow, Result};
 use axum::extract::FromRef;
 use axum_template::engine::Engine;
-use moka::future::Cache;
-use std::{ops::Deref, sync::Arc};
+use serde::Serialize;
+use std::{
+    ops::Deref,
+    sync::{Arc, RwLock},
+};
 use unic_langid::LanguageIdentifier;
 
 use crate::{
-    cache::{ResolveAtUriResult, ResolveWebHostMetaResult},
+    cache::{Caches, ResolveWebHostMetaResult},
+    config::{Config, Seeds},
+    fetch::HttpFetcher,
+    http::{handle_readyz::Canary, middleware_ratelimit::RateLimiter},
     i18n::Locales,
+    webhostmeta::WebHostMeta,
 };
 
 #[cfg(feature = "reload")]
@@ -21,18 +29,31 @@ use minijinja::Environment;
 #[cfg(feature = "embed")]
 pub type AppEngine = Engine<Environment<'static>>;
 
+/// Seeded host-meta documents, keyed by hostname.
+pub(crate) type SeedEntries = Vec<(String, Arc<WebHostMeta>)>;
+
+/// A supported language, as offered by the language switcher.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct LanguageOption {
+    pub(crate) code: String,
+    pub(crate) name: String,
+    pub(crate) active: bool,
+}
+
 pub struct I18nContext {
     pub(crate) supported_languages: Vec<LanguageIdentifier>,
-    pub(crate) locales: Locales,
+    locales: RwLock<Arc<Locales>>,
 }
 
 pub struct InnerWebContext {
-    pub(crate) external_base: String,
+    pub(crate) config: Config,
     pub(crate) engine: AppEngine,
-    pub(crate) http_client: reqwest::Client,
-    pub(crate) resolve_webfinger_cache: Cache<String, ResolveWebHostMetaResult>,
-    pub(crate) resolve_aturi_cache: Cache<String, ResolveAtUriResult>,
+    pub(crate) http_client: Arc<dyn HttpFetcher>,
+    pub(crate) caches: Caches,
     pub(crate) i18n_context: I18nContext,
+    pub(crate) rate_limiter: RateLimiter,
+    pub(crate) canary: Canary,
+    seeds: RwLock<Arc<SeedEntries>>,
 }
 
 #[derive(Clone, FromRef)]
@@ -46,31 +67,415 @@ impl Deref for WebContext {
     }
 }
 
+/// Builds a [`WebContext`]. The engine and i18n context are required, the
+/// HTTP client and caches default to new instances.
+pub struct WebContextBuilder {
+    config: Config,
+    engine: Option<AppEngine>,
+    http_client: Option<Arc<dyn HttpFetcher>>,
+    caches: Option<Caches>,
+    i18n_context: Option<I18nContext>,
+}
+
+impl WebContextBuilder {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+            engine: None,
+            http_client: None,
+            caches: None,
+            i18n_context: None,
+        }
+    }
+
+    pub fn external_base(mut self, external_base: &str) -> Self {
+        self.config.external_base = external_base.to_string();
+        self
+    }
+
+    pub fn engine(mut self, engine: AppEngine) -> Self {
+        self.engine = Some(engine);
+        self
+    }
+
+    pub fn http_client(mut self, http_client: Arc<dyn HttpFetcher>) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    pub fn caches(mut self, caches: Caches) -> Self {
+        self.caches = Some(caches);
+        self
+    }
+
+    pub fn i18n_context(mut self, i18n_context: I18nContext) -> Self {
+        self.i18n_context = Some(i18n_context);
+        self
+    }
+
+    pub fn build(self) -> Result<WebContext> {
+        let config = self.config;
+        let engine = self
+            .engine
+            .ok_or(anyhow!("web context engine must be set"))?;
+        let i18n_context = self
+            .i18n_context
+            .ok_or(anyhow!("web context i18n context must be set"))?;
+
+        let rate_limiter = RateLimiter::new(
+            *config.rate_limit_requests.as_ref(),
+            *config.rate_limit_window.as_ref(),
+        );
+        let seeds = RwLock::new(Arc::new(seed_entries(&config.seeds)));
+
+        Ok(WebContext(Arc::new(InnerWebContext {
+            config,
+            engine,
+            http_client: self
+                .http_client
+                .unwrap_or_else(|| Arc::new(reqwest::Client::new())),
+            caches: self.caches.unwrap_or_default(),
+            i18n_context,
+            rate_limiter,
+            canary: Canary::default(),
+            seeds,
+        })))
+    }
+}
+
 impl WebContext {
+    pub fn builder(config: &Config) -> WebContextBuilder {
+        WebContextBuilder::new(config)
+    }
+
     pub fn new(
-        external_base: &str,
+        config: &Config,
         engine: AppEngine,
-        http_client: &reqwest::Client,
-        resolve_webfinger_cache: Cache<String, ResolveWebHostMetaResult>,
-        resolve_aturi_cache: Cache<String, ResolveAtUriResult>,
+        http_client: Arc<dyn HttpFetcher>,
+        caches: Caches,
         i18n_context: I18nContext,
     ) -> Self {
-        Self(Arc::new(InnerWebContext {
-            external_base: external_base.to_string(),
-            engine,
-            http_client: http_client.clone(),
-            resolve_webfinger_cache,
-            resolve_aturi_cache,
-            i18n_context,
-        }))
+        Self::builder(config)
+            .engine(engine)
+            .http_client(http_client)
+            .caches(caches)
+            .i18n_context(i18n_context)
+            .build()
+            .expect("all required web context fields are set")
+    }
+
+    /// The seeded host-meta documents, as of the last reload.
+    pub(crate) fn seeds(&self) -> Arc<SeedEntries> {
+        Arc::clone(&self.seeds.read().expect("seeds lock poisoned"))
+    }
+
+    /// Inserts the seeded host-meta documents into the host-meta cache so
+    /// well-known servers are never fetched.
+    pub async fn seed_webhostmeta_cache(&self) {
+        for (hostname, webhostmeta) in self.seeds().iter() {
+            self.caches
+                .webhostmeta
+                .insert(
+                    hostname.clone(),
+                    ResolveWebHostMetaResult::Found(Arc::clone(webhostmeta)),
+                )
+                .await;
+        }
+    }
+
+    /// Swaps in freshly loaded locales and seeds. Hosts that are no longer
+    /// seeded are dropped from the host-meta cache, and the new seeds are
+    /// inserted. The remaining configuration is only read at startup.
+    pub async fn reload(&self, locales: Locales, seeds: &Seeds) {
+        self.i18n_context.set_locales(locales);
+
+        let previous = std::mem::replace(
+            &mut *self.seeds.write().expect("seeds lock poisoned"),
+            Arc::new(seed_entries(seeds)),
+        );
+        for (hostname, _) in previous.iter() {
+            if !seeds.as_ref().contains_key(hostname) {
+                self.caches.webhostmeta.invalidate(hostname).await;
+            }
+        }
+        self.seed_webhostmeta_cache().await;
     }
 }
 
+fn seed_entries(seeds: &Seeds) -> SeedEntries {
+    seeds
+        .as_ref()
+        .iter()
+        .map(|(hostname, webhostmeta)| (hostname.clone(), Arc::new(webhostmeta.clone())))
+        .collect()
+}
+
 impl I18nContext {
     pub fn new(supported_languages: Vec<LanguageIdentifier>, locales: Locales) -> Self {
         Self {
             supported_languages,
-            locales,
+            locales: RwLock::new(Arc::new(locales)),
         }
     }
+
+    /// The loaded locales, as of the last reload.
+    pub(crate) fn locales(&self) -> Arc<Locales> {
+        Arc::clone(&self.locales.read().expect("locales lock poisoned"))
+    }
+
+    /// The supported languages in their own names, marking `current` active.
+    pub(crate) fn language_options(&self, current: &LanguageIdentifier) -> Vec<LanguageOption> {
+        let locales = self.locales();
+        self.supported_languages
+            .iter()
+            .map(|language| LanguageOption {
+                code: language.to_string().to_lowercase(),
+                name: locales.language_name(language),
+                active: language == current,
+            })
+            .collect()
+    }
+
+    fn set_locales(&self, locales: Locales) {
+        *self.locales.write().expect("locales lock poisoned") = Arc::new(locales);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::Arc;
+
+    use unic_langid::LanguageIdentifier;
+
+    use super::{AppEngine, I18nContext, LanguageOption, WebContext};
+    use crate::{
+        cache::{Caches, ResolveWebHostMetaResult},
+        config::Config,
+        fetch::HttpFetcher,
+        i18n::Locales,
+        webhostmeta::{Link, WebHostMeta},
+    };
+
+    #[tokio::test]
+    async fn test_seed_file() {
+        let path = std::env::temp_dir().join(format!("hopper-seeds-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{
+  "links.example": {
+    "links": [
+      {
+        "rel": "http://hopper.at/rel/link",
+        "template": "https://links.example/{identity}/{rkey}",
+        "properties": { "http://hopper.at/ns/collection": "example.links.entry" }
+      }
+    ]
+  }
+}"#,
+        )
+        .unwrap();
+
+        let web_context = web_context(&format!(
+            "external_base = \"hopper.test\"\nseed_file = \"{}\"",
+            path.display()
+        ));
+        std::fs::remove_file(&path).unwrap();
+        web_context.seed_webhostmeta_cache().await;
+
+        assert_eq!(
+            web_context.caches.webhostmeta.get("links.example").await,
+            Some(ResolveWebHostMetaResult::Found(Arc::new(WebHostMeta::new(
+                vec![Link::new(
+                    "https://links.example/{identity}/{rkey}",
+                    Some("example.links.entry"),
+                )]
+            ))))
+        );
+        // A seed file replaces the built-in seeds.
+        assert!(web_context
+            .caches
+            .webhostmeta
+            .get("bsky.app")
+            .await
+            .is_none());
+    }
+
+    #[test]
+    fn test_language_options() {
+        let en_us: LanguageIdentifier = "en-us".parse().unwrap();
+        let es: LanguageIdentifier = "es".parse().unwrap();
+        let mut locales = Locales::new(vec![en_us.clone(), es.clone()], en_us.clone());
+        locales
+            .add_bundle(en_us.clone(), "language-name = English (US)".to_string())
+            .unwrap();
+        let i18n_context = I18nContext::new(vec![en_us, es.clone()], locales);
+
+        assert_eq!(
+            i18n_context.language_options(&es),
+            vec![
+                LanguageOption {
+                    code: "en-us".to_string(),
+                    name: "English (US)".to_string(),
+                    active: false,
+                },
+                // Without a language-name message the code is shown.
+                LanguageOption {
+                    code: "es".to_string(),
+                    name: "es".to_string(),
+                    active: true,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_builder() {
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+        let (engine, i18n_context) = engine_and_i18n(&config);
+        let caches = Caches::new();
+
+        let web_context = WebContext::builder(&config)
+            .external_base("hopper.example")
+            .engine(engine)
+            .caches(caches.clone())
+            .i18n_context(i18n_context)
+            .build()
+            .unwrap();
+
+        assert_eq!(web_context.config.external_base, "hopper.example");
+        assert_eq!(
+            web_context.i18n_context.supported_languages,
+            config.languages.as_ref().clone()
+        );
+        assert_eq!(web_context.seeds().len(), config.seeds.as_ref().len());
+
+        // The context shares the caches it was given.
+        web_context.seed_webhostmeta_cache().await;
+        assert!(caches.webhostmeta.get("bsky.app").await.is_some());
+
+        assert!(WebContext::builder(&config).build().is_err());
+    }
+
+    #[cfg(feature = "reload")]
+    #[tokio::test]
+    async fn test_reload() {
+        use std::str::FromStr;
+        use unic_langid::LanguageIdentifier;
+
+        use crate::{config::Seeds, i18n::reload::populate_locale_from};
+
+        let en_us = LanguageIdentifier::from_str("en-us").unwrap();
+        let dir = std::env::temp_dir().join(format!("hopper-reload-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("en-us")).unwrap();
+        let load_locales = |message: &str| {
+            std::fs::write(
+                dir.join("en-us").join("errors.ftl"),
+                format!("error-web-invalid-aturi = {}", message),
+            )
+            .unwrap();
+            let mut locales = Locales::new(vec![en_us.clone()], en_us.clone());
+            populate_locale_from(&dir, &vec![en_us.clone()], &mut locales).unwrap();
+            locales
+        };
+
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+        let (engine, _) = engine_and_i18n(&config);
+        let web_context = WebContext::builder(&config)
+            .engine(engine)
+            .i18n_context(I18nContext::new(
+                vec![en_us.clone()],
+                load_locales("The AT-URI is not valid."),
+            ))
+            .build()
+            .unwrap();
+        web_context.seed_webhostmeta_cache().await;
+
+        let seed_file = dir.join("seeds.json");
+        std::fs::write(
+            &seed_file,
+            r#"{ "links.example": { "links": [ { "rel": "http://hopper.at/rel/link", "template": "https://links.example/{identity}" } ] } }"#,
+        )
+        .unwrap();
+        let seeds: Seeds = seed_file.display().to_string().try_into().unwrap();
+
+        web_context
+            .reload(load_locales("That AT-URI doesn't look right."), &seeds)
+            .await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            web_context.i18n_context.locales().format_error(
+                &en_us,
+                "error-web-invalid-aturi",
+                "error-web-invalid-aturi Invalid AT-URI"
+            ),
+            "That AT-URI doesn't look right."
+        );
+        assert_eq!(web_context.seeds().len(), 1);
+        assert!(web_context
+            .caches
+            .webhostmeta
+            .get("links.example")
+            .await
+            .is_some());
+        // Hosts that are no longer seeded are fetched again.
+        assert!(web_context
+            .caches
+            .webhostmeta
+            .get("bsky.app")
+            .await
+            .is_none());
+    }
+
+    /// Builds a context for exercising the router in tests.
+    pub(crate) fn web_context(config_toml: &str) -> WebContext {
+        let config = Config::from_toml(config_toml).unwrap();
+        let (engine, i18n_context) = engine_and_i18n(&config);
+
+        WebContext::builder(&config)
+            .engine(engine)
+            .i18n_context(i18n_context)
+            .build()
+            .unwrap()
+    }
+
+    /// Builds a context whose outbound requests go to `http_client`.
+    pub(crate) fn web_context_with_fetcher(
+        config_toml: &str,
+        http_client: Arc<dyn HttpFetcher>,
+    ) -> WebContext {
+        let config = Config::from_toml(config_toml).unwrap();
+        let (engine, i18n_context) = engine_and_i18n(&config);
+
+        WebContext::builder(&config)
+            .engine(engine)
+            .http_client(http_client)
+            .i18n_context(i18n_context)
+            .build()
+            .unwrap()
+    }
+
+    fn engine_and_i18n(config: &Config) -> (AppEngine, I18nContext) {
+        let supported_languages = config.languages.as_ref().clone();
+
+        let mut locales = Locales::new(supported_languages.clone(), supported_languages[0].clone());
+
+        #[cfg(feature = "reload")]
+        crate::i18n::reload::populate_locale(&supported_languages, &mut locales).unwrap();
+        #[cfg(feature = "reload")]
+        let engine = AppEngine::from(crate::http::templates::reload_env::build_env(
+            &config.external_base,
+            &config.version,
+        ));
+
+        #[cfg(feature = "embed")]
+        crate::i18n::embed::populate_locale(&supported_languages, &mut locales).unwrap();
+        #[cfg(feature = "embed")]
+        let engine = AppEngine::from(crate::http::templates::embed_env::build_env(
+            config.external_base.clone(),
+            config.version.clone(),
+        ));
+
+        (engine, I18nContext::new(supported_languages, locales))
+    }
 }
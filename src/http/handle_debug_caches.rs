@@ -0,0 +1,16 @@
+use axum::{response::IntoResponse, Json};
+use serde_json::json;
+use std::sync::atomic::Ordering;
+
+use crate::cache::{ATURI_EVICTIONS, WEBHOSTMETA_EVICTIONS};
+
+/// Returns how many entries each in-process cache has evicted to stay within
+/// its capacity, to help size `HOPPER_HOST_META_CACHE_CAPACITY` and
+/// `HOPPER_ATURI_CACHE_CAPACITY`. It is an admin route, guarded by
+/// [`admin_auth`](crate::http::middleware_admin::admin_auth).
+pub(crate) async fn handle_debug_caches() -> impl IntoResponse {
+    Json(json!({
+        "webhostmeta": { "evictions": WEBHOSTMETA_EVICTIONS.load(Ordering::Relaxed) },
+        "aturi": { "evictions": ATURI_EVICTIONS.load(Ordering::Relaxed) },
+    }))
+}
@@ -0,0 +1,42 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::Query;
+use serde::Deserialize;
+
+use crate::{
+    errors::HopperError,
+    http::context::WebContext,
+    resolve::{parse_servers, trace},
+};
+
+#[derive(Deserialize)]
+pub(crate) struct DebugResolve {
+    aturi: String,
+    server: Option<String>,
+}
+
+/// Returns the resolution trace of an AT-URI as JSON. It is an admin route,
+/// guarded by [`admin_auth`](crate::http::middleware_admin::admin_auth).
+pub(crate) async fn handle_debug_resolve(
+    State(web_context): State<WebContext>,
+    Query(debug_resolve): Query<DebugResolve>,
+) -> Result<Response, HopperError> {
+    let servers = parse_servers(
+        &debug_resolve.server.unwrap_or_default(),
+        *web_context.config.max_servers.as_ref(),
+        web_context.config.reserved_suffixes.as_ref(),
+    );
+
+    let trace = trace(
+        web_context.http_client.as_ref(),
+        &web_context.config,
+        &servers,
+        &debug_resolve.aturi,
+    )
+    .await?;
+
+    Ok(Json(trace).into_response())
+}
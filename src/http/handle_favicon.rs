@@ -0,0 +1,45 @@
+use axum::{
+    extract::State,
+    http::header::{CACHE_CONTROL, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+
+use crate::{errors::HopperError, http::context::WebContext};
+
+const FAVICON_CONTENT_TYPE: &str = "image/x-icon";
+
+const FAVICON_CACHE_CONTROL: &str = "public, max-age=604800";
+
+#[cfg(feature = "embed")]
+static FAVICON: &[u8] = include_bytes!("../../static/favicon.ico");
+
+/// Serves the configured icon, falling back to the bundled `favicon.ico`.
+pub(crate) async fn handle_favicon(
+    State(web_context): State<WebContext>,
+) -> Result<Response, HopperError> {
+    if let Some((content_type, content)) = web_context.config.favicon.as_ref() {
+        return Ok((
+            [
+                (CONTENT_TYPE, *content_type),
+                (CACHE_CONTROL, FAVICON_CACHE_CONTROL),
+            ],
+            content.clone(),
+        )
+            .into_response());
+    }
+
+    #[cfg(feature = "embed")]
+    let content = FAVICON.to_vec();
+
+    #[cfg(feature = "reload")]
+    let content = tokio::fs::read("static/favicon.ico").await?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, FAVICON_CONTENT_TYPE),
+            (CACHE_CONTROL, FAVICON_CACHE_CONTROL),
+        ],
+        content,
+    )
+        .into_response())
+}
@@ -0,0 +1,14 @@
+use axum::{extract::State, response::IntoResponse, Json};
+
+use crate::{http::context::WebContext, webhostmeta::WebHostMeta};
+
+/// Publishes the link templates hopper resolves with, built from the seeded
+/// host-meta documents.
+pub(crate) async fn handle_host_meta(State(web_context): State<WebContext>) -> impl IntoResponse {
+    let links = web_context
+        .seeds()
+        .iter()
+        .flat_map(|(_, webhostmeta)| webhostmeta.links.iter().cloned())
+        .collect();
+    Json(WebHostMeta::new(links))
+}
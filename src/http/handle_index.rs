@@ -6,35 +6,121 @@ use axum::{
 use axum_extra::extract::Query;
 use axum_htmx::HxRequest;
 use axum_template::RenderHtml;
-use http::StatusCode;
+use fluent::FluentArgs;
+use http::{
+    header::{CACHE_CONTROL, LINK},
+    StatusCode,
+};
 use minijinja::context as template_context;
 use ordermap::OrderSet;
 use serde::Deserialize;
+use std::time::Instant;
 
 use crate::{
-    cache::aturi_cached,
+    config::Config,
     errors::{expand_error, HopperError},
-    http::{context::WebContext, middleware_i18n::Language},
-    model::validate_aturi,
+    http::{
+        context::WebContext, middleware_client_ip::ClientIp, middleware_i18n::Language,
+        middleware_security::CspNonce,
+    },
+    resolve::{parse_servers, resolve, Resolution},
 };
 
-pub(crate) const ERROR_INVALID_AT_URI: &str = "error-web-invalid-aturi Invalid AT-URI";
+/// Cached destinations stay valid for the lifetime of the AT-URI cache entry.
+const CACHE_CONTROL_CACHED: &str = "public, max-age=1800";
+
+/// Fresh resolutions and previews are not stored, so intermediaries don't
+/// keep a destination that hopper itself hasn't cached yet.
+const CACHE_CONTROL_FRESH: &str = "no-store";
 
 #[derive(Deserialize)]
 pub(crate) struct Destination {
-    aturi: Option<String>,
-    server: Option<String>,
+    pub(crate) aturi: Option<String>,
+    pub(crate) server: Option<String>,
+    pub(crate) preview: Option<String>,
+}
+
+impl Destination {
+    /// Preview mode shows where an AT-URI resolves instead of redirecting.
+    fn is_preview(&self) -> bool {
+        matches!(self.preview.as_deref(), Some("1" | "true"))
+    }
+}
+
+/// Returns the servers an AT-URI was tried against, for the unsupported
+/// error: disallowed servers are left out, and at most
+/// `max_error_servers` are named, with how many more were tried.
+fn tried_servers<'a>(config: &Config, servers: &'a [String]) -> (Vec<&'a str>, usize) {
+    let mut tried = servers
+        .iter()
+        .map(String::as_str)
+        .filter(|server| config.is_host_allowed(server))
+        .collect::<OrderSet<&str>>();
+    let more = tried
+        .len()
+        .saturating_sub(*config.max_error_servers.as_ref());
+    tried.truncate(*config.max_error_servers.as_ref());
+    (tried.into_iter().collect(), more)
+}
+
+/// Emits the access log line of a resolution, under the `hopper::access`
+/// target so it can be filtered separately from request spans.
+fn log_resolution(config: &Config, aturi: &str, resolution: &Result<Resolution>, started: Instant) {
+    let aturi = if *config.access_log_aturi.as_ref() {
+        aturi
+    } else {
+        "redacted"
+    };
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    match resolution {
+        Ok(resolution) => {
+            let destination_host = url::Url::parse(&resolution.destination)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_default();
+            let outcome = if resolution.is_cached() {
+                "cached"
+            } else {
+                "resolved"
+            };
+            tracing::info!(
+                target: "hopper::access",
+                aturi,
+                matched_server = resolution.matched_server.as_deref().unwrap_or_default(),
+                destination_host,
+                outcome,
+                latency_ms,
+                "resolution completed"
+            );
+        }
+        Err(_) => {
+            tracing::info!(
+                target: "hopper::access",
+                aturi,
+                matched_server = "",
+                destination_host = "",
+                outcome = "error",
+                latency_ms,
+                "resolution completed"
+            );
+        }
+    }
 }
 
 pub(crate) async fn handle_index(
     State(web_context): State<WebContext>,
     HxRequest(hx_request): HxRequest,
     Language(language): Language,
+    ClientIp(client_ip): ClientIp,
+    CspNonce(csp_nonce): CspNonce,
     Query(destination): Query<Destination>,
 ) -> Result<impl IntoResponse, HopperError> {
     let default_context = template_context! {
         language => language.to_string(),
-        canonical_url => format!("https://{}/", web_context.external_base),
+        languages => web_context.i18n_context.language_options(&language),
+        csp_nonce,
+        canonical_url => format!("https://{}/", web_context.config.external_base),
     };
 
     let template_suffix = if hx_request {
@@ -43,58 +129,51 @@ pub(crate) async fn handle_index(
         format!("{}.html", language.to_string().to_lowercase())
     };
 
-    if let Some(aturi_str) = destination.aturi {
-        let aturi = validate_aturi(&aturi_str);
-        if aturi.is_none() {
-            tracing::debug!(error = ERROR_INVALID_AT_URI, "error encountered");
-            let (err_bare, err_partial) = expand_error(ERROR_INVALID_AT_URI);
+    let preview = destination.is_preview();
 
-            let error_message =
-                web_context
-                    .i18n_context
-                    .locales
-                    .format_error(&language, &err_bare, &err_partial);
-
-            return Ok(RenderHtml(
-                format!("index.{}", template_suffix),
-                web_context.engine.clone(),
-                template_context! { ..default_context, ..template_context! {
-                    handle_error => true,
-                    aturi_value => aturi_str,
-                    aturi_error => error_message,
-                }},
-            )
-            .into_response());
-        }
-
-        let aturi = aturi.unwrap();
-
-        let servers = parse_servers(&destination.server.unwrap_or_default());
-
-        let destination = aturi_cached(
-            &web_context.http_client,
-            &web_context.resolve_webfinger_cache,
-            &web_context.resolve_aturi_cache,
+    if let Some(aturi_str) = destination.aturi {
+        tracing::debug!(%client_ip, aturi = aturi_str, "resolving AT-URI");
+
+        let servers = parse_servers(
+            &destination.server.unwrap_or_default(),
+            *web_context.config.max_servers.as_ref(),
+            web_context.config.reserved_suffixes.as_ref(),
+        );
+
+        let started = Instant::now();
+        let destination = resolve(
+            web_context.http_client.as_ref(),
+            &web_context.caches,
+            &web_context.config,
             &servers,
             &aturi_str,
-            &aturi,
         )
         .await;
+        log_resolution(&web_context.config, &aturi_str, &destination, started);
 
         if let Err(err) = destination {
             tracing::debug!(error = ?err, "error encountered");
             let (err_bare, err_partial) = expand_error(err.to_string());
 
-            let error_message =
-                web_context
-                    .i18n_context
-                    .locales
-                    .format_error(&language, &err_bare, &err_partial);
+            let mut error_args = FluentArgs::new();
+            error_args.set("aturi", aturi_str.clone());
+            let (tried, more) = tried_servers(&web_context.config, &servers);
+            error_args.set("tried", tried.len());
+            error_args.set("servers", tried.join(", "));
+            error_args.set("more", more);
+
+            let error_message = web_context.i18n_context.locales().format_error_with(
+                &language,
+                &err_bare,
+                &err_partial,
+                &error_args,
+            );
 
             return Ok(RenderHtml(
                 format!("index.{}", template_suffix),
                 web_context.engine.clone(),
                 template_context! { ..default_context, ..template_context! {
+                    preview,
                     handle_error => true,
                     aturi_value => aturi_str,
                     aturi_error => error_message,
@@ -103,13 +182,44 @@ pub(crate) async fn handle_index(
             .into_response());
         }
 
-        let destination = destination.unwrap();
+        let resolution = destination.unwrap();
+        let cache_control = if resolution.is_cached() && !preview {
+            CACHE_CONTROL_CACHED
+        } else {
+            CACHE_CONTROL_FRESH
+        };
+        let destination = resolution.destination;
+
+        if preview {
+            // Lets unfurlers follow through to the destination.
+            let link = format!("<{}>; rel=\"canonical\"", destination);
+            return Ok((
+                [(LINK, link), (CACHE_CONTROL, cache_control.to_string())],
+                RenderHtml(
+                    format!("index.{}", template_suffix),
+                    web_context.engine.clone(),
+                    template_context! { ..default_context, ..template_context! {
+                        preview,
+                        aturi_value => aturi_str,
+                        destination,
+                    }},
+                ),
+            )
+                .into_response());
+        }
 
         if hx_request {
-            return Ok((StatusCode::OK, [("HX-Redirect", destination)]).into_response());
+            return Ok((
+                StatusCode::OK,
+                [
+                    ("HX-Redirect", destination.as_str()),
+                    (CACHE_CONTROL.as_str(), cache_control),
+                ],
+            )
+                .into_response());
         }
 
-        return Ok(Redirect::to(&destination).into_response());
+        return Ok(([(CACHE_CONTROL, cache_control)], Redirect::to(&destination)).into_response());
     }
 
     Ok(RenderHtml(
@@ -119,20 +229,3 @@ pub(crate) async fn handle_index(
     )
     .into_response())
 }
-
-fn parse_servers(value: &str) -> Vec<String> {
-    let mut values = value
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .collect::<OrderSet<String>>();
-
-    values.extend(vec![
-        "smokesignal.events".into(),
-        "frontpage.fyi".into(),
-        "whtwnd.com".into(),
-        "bsky.app".into(),
-    ]);
-
-    Vec::from_iter(values)
-}
@@ -0,0 +1,50 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect},
+    Form,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use serde::Deserialize;
+use unic_langid::LanguageIdentifier;
+
+use crate::http::{context::WebContext, middleware_i18n::COOKIE_LANG};
+
+/// How long a language chosen with the switcher is remembered.
+const LANG_COOKIE_MAX_AGE: cookie::time::Duration = cookie::time::Duration::days(365);
+
+#[derive(Deserialize)]
+pub(crate) struct LanguageForm {
+    lang: String,
+}
+
+/// Stores the language picked in the language switcher in the `lang` cookie
+/// and returns to the home page. Unsupported languages are ignored.
+pub(crate) async fn handle_lang(
+    State(web_context): State<WebContext>,
+    jar: CookieJar,
+    Form(form): Form<LanguageForm>,
+) -> impl IntoResponse {
+    let language = form
+        .lang
+        .parse::<LanguageIdentifier>()
+        .ok()
+        .and_then(|value| {
+            web_context
+                .i18n_context
+                .supported_languages
+                .iter()
+                .find(|language| language.matches(&value, true, false))
+        });
+
+    let jar = match language {
+        Some(language) => jar.add(
+            Cookie::build((COOKIE_LANG, language.to_string().to_lowercase()))
+                .path("/")
+                .same_site(SameSite::Lax)
+                .max_age(LANG_COOKIE_MAX_AGE),
+        ),
+        None => jar,
+    };
+
+    (jar, Redirect::to("/"))
+}
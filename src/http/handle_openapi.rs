@@ -0,0 +1,161 @@
+use axum::{extract::State, response::IntoResponse, Json};
+use serde_json::{json, Value};
+
+use crate::http::context::WebContext;
+
+/// Describes the JSON endpoints in an OpenAPI 3 document. It is written by
+/// hand, so a change to the routes or responses of `/reverse`, `/readyz` or
+/// `/version` must be reflected here.
+pub(crate) fn openapi_document(version: &str) -> Value {
+    let error = |description: &str| json!({ "description": description });
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "hopper",
+            "description": "Resolves AT-URIs to web URLs through host-meta link templates.",
+            "version": version,
+        },
+        "paths": {
+            "/reverse": {
+                "get": {
+                    "operationId": "reverse",
+                    "summary": "Find the AT-URI that resolves to a web URL.",
+                    "parameters": [{
+                        "name": "url",
+                        "in": "query",
+                        "required": true,
+                        "description": "An https URL on a server with host-meta link templates.",
+                        "schema": { "type": "string", "format": "uri" },
+                    }],
+                    "responses": {
+                        "200": {
+                            "description": "The URL matched a link template.",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/ReverseResult" },
+                                },
+                            },
+                        },
+                        "400": error("The URL is not a valid https URL."),
+                        "404": error("No link template of the server produces the URL."),
+                        "429": error("Too many requests. Retry-After gives the seconds to wait."),
+                    },
+                },
+            },
+            "/readyz": {
+                "get": {
+                    "operationId": "readyz",
+                    "summary": "Report whether hopper is ready to serve.",
+                    "responses": {
+                        "200": {
+                            "description": "Ready.",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/Readiness" },
+                                },
+                            },
+                        },
+                        "503": {
+                            "description": "The readiness canary is unreachable. Retry-After gives the seconds until it is fetched again.",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/Readiness" },
+                                },
+                            },
+                        },
+                    },
+                },
+            },
+            "/version": {
+                "get": {
+                    "operationId": "version",
+                    "summary": "Report the running version.",
+                    "responses": {
+                        "200": {
+                            "description": "The version.",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/Version" },
+                                },
+                            },
+                        },
+                    },
+                },
+            },
+        },
+        "components": {
+            "schemas": {
+                "ReverseResult": {
+                    "type": "object",
+                    "required": ["url", "aturi"],
+                    "properties": {
+                        "url": { "type": "string", "description": "The URL as requested." },
+                        "aturi": { "type": "string", "description": "The AT-URI that resolves to the URL." },
+                    },
+                },
+                "Readiness": {
+                    "type": "object",
+                    "required": ["status"],
+                    "properties": {
+                        "status": { "type": "string", "enum": ["ready", "degraded"] },
+                        "canary": {
+                            "type": "string",
+                            "enum": ["reachable", "unreachable"],
+                            "description": "Present when a readiness canary is configured.",
+                        },
+                    },
+                },
+                "Version": {
+                    "type": "object",
+                    "required": ["version"],
+                    "properties": {
+                        "version": { "type": "string" },
+                    },
+                },
+            },
+        },
+    })
+}
+
+pub(crate) async fn handle_openapi(State(web_context): State<WebContext>) -> impl IntoResponse {
+    Json(openapi_document(&web_context.config.version))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http::{Request, StatusCode};
+    use openapiv3::OpenAPI;
+    use tower::ServiceExt;
+
+    use crate::http::{context::tests::web_context, server::build_router};
+
+    #[tokio::test]
+    async fn test_openapi() {
+        let app = build_router(web_context("external_base = \"hopper.test\""));
+
+        let response = app
+            .oneshot(
+                Request::get("/api/openapi.json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let document: OpenAPI = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(
+            document.paths.paths.keys().collect::<Vec<_>>(),
+            ["/readyz", "/reverse", "/version"]
+        );
+        let schemas = &document.components.unwrap().schemas;
+        for schema in ["ReverseResult", "Readiness", "Version"] {
+            assert!(schemas.contains_key(schema), "{}", schema);
+        }
+    }
+}
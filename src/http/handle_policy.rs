@@ -1,26 +1,31 @@
 use anyhow::Result;
-use axum::{extract::State, response::IntoResponse};
-use axum_template::RenderHtml;
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse},
+};
 use minijinja::context as template_context;
 
 use crate::{
     errors::HopperError,
-    http::{context::WebContext, middleware_i18n::Language},
+    http::{
+        context::WebContext, middleware_i18n::Language, middleware_security::CspNonce,
+        templates::render_localized,
+    },
 };
 
 pub async fn handle_policy(
     State(web_context): State<WebContext>,
     Language(language): Language,
+    CspNonce(csp_nonce): CspNonce,
 ) -> Result<impl IntoResponse, HopperError> {
     let default_context = template_context! {
         language => language.to_string(),
-        canonical_url => format!("https://{}/policy", web_context.external_base),
+        languages => web_context.i18n_context.language_options(&language),
+        csp_nonce,
+        canonical_url => format!("https://{}/policy", web_context.config.external_base),
     };
 
-    Ok(RenderHtml(
-        "policy.en-us.html",
-        web_context.engine.clone(),
-        default_context,
-    )
-    .into_response())
+    let rendered = render_localized(&web_context.engine, "policy", &language, default_context)?;
+
+    Ok(Html(rendered))
 }
@@ -0,0 +1,74 @@
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::header::{CACHE_CONTROL, CONTENT_TYPE},
+    response::IntoResponse,
+};
+use axum_extra::extract::Query;
+use qrcode::{Color, QrCode};
+
+use crate::{
+    errors::HopperError,
+    http::{context::WebContext, handle_index::Destination},
+    resolve::{parse_servers, resolve},
+};
+
+/// Pixels per QR module.
+const MODULE_SIZE: usize = 8;
+
+/// Modules of light border around the code, as required by the QR spec.
+const QUIET_ZONE: usize = 4;
+
+pub(crate) async fn handle_qr(
+    State(web_context): State<WebContext>,
+    Query(destination): Query<Destination>,
+) -> Result<impl IntoResponse, HopperError> {
+    let servers = parse_servers(
+        &destination.server.unwrap_or_default(),
+        *web_context.config.max_servers.as_ref(),
+        web_context.config.reserved_suffixes.as_ref(),
+    );
+
+    let resolution = resolve(
+        web_context.http_client.as_ref(),
+        &web_context.caches,
+        &web_context.config,
+        &servers,
+        &destination.aturi.unwrap_or_default(),
+    )
+    .await?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, "image/png"),
+            (CACHE_CONTROL, "public, max-age=1800"),
+        ],
+        render_png(&resolution.destination)?,
+    ))
+}
+
+fn render_png(value: &str) -> Result<Vec<u8>> {
+    let code = QrCode::new(value.as_bytes())?;
+    let modules = code.width();
+    let colors = code.to_colors();
+
+    let size = (modules + QUIET_ZONE * 2) * MODULE_SIZE;
+    let mut pixels = vec![u8::MAX; size * size];
+    for (index, color) in colors.iter().enumerate() {
+        if *color != Color::Dark {
+            continue;
+        }
+        let left = (index % modules + QUIET_ZONE) * MODULE_SIZE;
+        let top = (index / modules + QUIET_ZONE) * MODULE_SIZE;
+        for row in top..top + MODULE_SIZE {
+            pixels[row * size + left..row * size + left + MODULE_SIZE].fill(0);
+        }
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, size as u32, size as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok(png)
+}
@@ -0,0 +1,176 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::{
+    config::Config,
+    fetch::HttpFetcher,
+    http::{context::WebContext, retry_after::with_retry_after},
+    webhostmeta::query,
+};
+
+/// The last outcome of fetching the readiness canary's host-meta. Probes
+/// share it, so the canary is fetched at most once per
+/// `readiness_canary_ttl` however often `/readyz` is polled.
+#[derive(Default)]
+pub(crate) struct Canary {
+    last: Mutex<Option<(Instant, bool)>>,
+}
+
+impl Canary {
+    /// Returns whether the canary host-meta could be fetched and how long
+    /// until it is fetched again, or `None` when no canary is configured.
+    /// Probes arriving while a fetch is in flight wait for its outcome rather
+    /// than starting another.
+    pub(crate) async fn reachable(
+        &self,
+        http_client: &dyn HttpFetcher,
+        config: &Config,
+    ) -> Option<(bool, Duration)> {
+        let hostname = config.readiness_canary.as_ref().as_deref()?;
+        let ttl = *config.readiness_canary_ttl.as_ref();
+
+        let mut last = self.last.lock().await;
+        if let Some((checked, reachable)) = *last {
+            if let Some(remaining) = ttl.checked_sub(checked.elapsed()) {
+                return Some((reachable, remaining));
+            }
+        }
+
+        let fetched = tokio::time::timeout(
+            *config.server_timeout.as_ref(),
+            query(
+                http_client,
+                config.resolver_base.as_ref(),
+                config.host_meta_path.as_ref(),
+                hostname,
+            ),
+        )
+        .await;
+        let reachable = match fetched {
+            Ok(Ok(_)) => true,
+            Ok(Err(err)) => {
+                tracing::warn!(hostname, error = ?err, "readiness canary fetch failed");
+                false
+            }
+            Err(_) => {
+                tracing::warn!(hostname, "readiness canary fetch timed out");
+                false
+            }
+        };
+        *last = Some((Instant::now(), reachable));
+        Some((reachable, ttl))
+    }
+}
+
+/// Reports whether hopper is ready to serve. With a readiness canary
+/// configured, an unreachable canary reports `degraded` with `503 Service
+/// Unavailable`, to be retried once the canary is fetched again.
+pub(crate) async fn handle_readyz(State(web_context): State<WebContext>) -> Response {
+    let canary = web_context
+        .canary
+        .reachable(web_context.http_client.as_ref(), &web_context.config)
+        .await;
+
+    match canary {
+        None => Json(json!({ "status": "ready" })).into_response(),
+        Some((true, _)) => {
+            Json(json!({ "status": "ready", "canary": "reachable" })).into_response()
+        }
+        Some((false, recheck)) => with_retry_after(
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "status": "degraded", "canary": "unreachable" })),
+            )
+                .into_response(),
+            recheck,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use http::{header::RETRY_AFTER, Request, StatusCode};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    use crate::{
+        fetch::tests::MockFetcher,
+        http::{context::tests::web_context_with_fetcher, server::build_router},
+    };
+
+    const CANARY_CONFIG: &str =
+        "external_base = \"hopper.test\"\nreadiness_canary = \"canary.example\"";
+
+    /// Probes `/readyz`, returning the status, the `Retry-After` seconds, and
+    /// the body.
+    async fn readyz(app: &axum::Router) -> (StatusCode, Option<u64>, serde_json::Value) {
+        let response = app
+            .clone()
+            .oneshot(Request::get("/readyz").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .map(|value| value.to_str().unwrap().parse::<u64>().unwrap());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, retry_after, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_readyz_canary_unreachable() {
+        let http_fetcher = Arc::new(MockFetcher::new([]));
+        let app = build_router(web_context_with_fetcher(
+            CANARY_CONFIG,
+            http_fetcher.clone(),
+        ));
+
+        let (status, retry_after, body) = readyz(&app).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(retry_after, Some(30));
+        assert_eq!(body["status"], "degraded");
+
+        // The failed fetch answers probes until it expires, which is when
+        // clients are asked to retry.
+        let (status, retry_after, _) = readyz(&app).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(retry_after.is_some_and(|seconds| (1..=30).contains(&seconds)));
+        assert_eq!(http_fetcher.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_readyz() {
+        let http_fetcher = Arc::new(MockFetcher::new([(
+            "https://canary.example/.well-known/host-meta.json",
+            "{}",
+        )]));
+        let app = build_router(web_context_with_fetcher(CANARY_CONFIG, http_fetcher));
+        let (status, retry_after, body) = readyz(&app).await;
+        assert_eq!(retry_after, None);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["canary"], "reachable");
+
+        // Without a canary nothing is fetched.
+        let http_fetcher = Arc::new(MockFetcher::new([]));
+        let app = build_router(web_context_with_fetcher(
+            "external_base = \"hopper.test\"",
+            http_fetcher.clone(),
+        ));
+        let (status, retry_after, body) = readyz(&app).await;
+        assert_eq!(retry_after, None);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ready");
+        assert!(http_fetcher.requests.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,41 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::Query;
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::HopperError, http::context::WebContext, resolve::reverse};
+
+#[derive(Deserialize)]
+pub(crate) struct Reverse {
+    url: String,
+}
+
+#[derive(Serialize)]
+struct ReverseResult {
+    url: String,
+    aturi: String,
+}
+
+/// Returns the AT-URI that resolves to a web URL as JSON, found by matching
+/// the URL against the link templates of the server it is on.
+pub(crate) async fn handle_reverse(
+    State(web_context): State<WebContext>,
+    Query(reverse_query): Query<Reverse>,
+) -> Result<Response, HopperError> {
+    let aturi = reverse(
+        web_context.http_client.as_ref(),
+        &web_context.caches,
+        &web_context.config,
+        &reverse_query.url,
+    )
+    .await?;
+
+    Ok(Json(ReverseResult {
+        url: reverse_query.url,
+        aturi,
+    })
+    .into_response())
+}
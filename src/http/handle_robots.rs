@@ -0,0 +1,21 @@
+use anyhow::Result;
+use axum::{extract::State, response::IntoResponse};
+use axum_template::Render;
+use minijinja::context as template_context;
+
+use crate::{errors::HopperError, http::context::WebContext};
+
+pub(crate) async fn handle_robots(
+    State(web_context): State<WebContext>,
+) -> Result<impl IntoResponse, HopperError> {
+    if let Some(robots_txt) = web_context.config.robots_txt.as_ref() {
+        return Ok(robots_txt.clone().into_response());
+    }
+
+    Ok(Render(
+        "robots.txt",
+        web_context.engine.clone(),
+        template_context! {},
+    )
+    .into_response())
+}
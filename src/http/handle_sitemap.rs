@@ -0,0 +1,41 @@
+use axum::{
+    extract::State,
+    http::header::CONTENT_TYPE,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+use crate::http::context::WebContext;
+
+/// Lists the canonical URL of every page in every supported language. The
+/// page paths are the ones `build_router` registers as pages.
+pub(crate) async fn handle_sitemap(
+    State(web_context): State<WebContext>,
+    pages: Arc<[&'static str]>,
+) -> Response {
+    let mut sitemap = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for page in pages.iter() {
+        for language in &web_context.i18n_context.supported_languages {
+            sitemap.push_str(&format!(
+                "  <url><loc>https://{}{}?lang={}</loc></url>\n",
+                escape_xml(&web_context.config.external_base),
+                page,
+                language.to_string().to_lowercase()
+            ));
+        }
+    }
+    sitemap.push_str("</urlset>\n");
+
+    ([(CONTENT_TYPE, "application/xml")], sitemap).into_response()
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -1,28 +1,63 @@
 use anyhow::Result;
-use axum::{extract::State, response::IntoResponse};
-use axum_template::RenderHtml;
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse},
+};
 use minijinja::context as template_context;
 
 use crate::{
     errors::HopperError,
-    http::{context::WebContext, middleware_i18n::Language},
+    http::{
+        context::WebContext, middleware_i18n::Language, middleware_security::CspNonce,
+        templates::render_localized,
+    },
 };
 
 pub async fn handle_spec(
     State(web_context): State<WebContext>,
     Language(language): Language,
+    CspNonce(csp_nonce): CspNonce,
 ) -> Result<impl IntoResponse, HopperError> {
     let default_context = template_context! {
         language => language.to_string(),
-        canonical_url => format!("https://{}/spec", web_context.external_base),
+        languages => web_context.i18n_context.language_options(&language),
+        csp_nonce,
+        canonical_url => format!("https://{}/spec", web_context.config.external_base),
+    };
+
+    let rendered = render_localized(&web_context.engine, "spec", &language, default_context)?;
+
+    Ok(Html(rendered))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{extract::State, response::IntoResponse};
+    use http::StatusCode;
+
+    use super::handle_spec;
+    use crate::http::{
+        context::tests::web_context, middleware_i18n::Language, middleware_security::CspNonce,
     };
 
-    let render_template = format!("spec.{}.html", language.to_string().to_lowercase());
+    #[tokio::test]
+    async fn test_spec_falls_back_to_default_language() {
+        let web_context = web_context(r#"external_base = "hopper.test""#);
+
+        let response = handle_spec(
+            State(web_context),
+            Language("es".parse().unwrap()),
+            CspNonce("abc".to_string()),
+        )
+        .await
+        .unwrap()
+        .into_response();
 
-    Ok(RenderHtml(
-        &render_template,
-        web_context.engine.clone(),
-        default_context,
-    )
-    .into_response())
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("<title>Hopper Spec</title>"));
+    }
 }
@@ -0,0 +1,140 @@
+use axum::{
+    extract::Request,
+    http::{
+        header::{CACHE_CONTROL, CONTENT_TYPE},
+        StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+use headers::{ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, LastModified};
+use rust_embed::Embed;
+use std::time::{Duration, UNIX_EPOCH};
+
+const STATIC_CACHE_CONTROL: &str = "public, max-age=3600";
+
+#[derive(Embed)]
+#[folder = "static/"]
+struct StaticAssets;
+
+/// Serves files embedded from `static/` with an `ETag` derived from their
+/// content hash and a `Last-Modified` time, answering matching conditional
+/// requests with `304 Not Modified`.
+pub(crate) async fn handle_embedded_static(request: Request) -> Response {
+    let path = request.uri().path().trim_start_matches('/');
+    let Some(asset) = StaticAssets::get(path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let etag = asset
+        .metadata
+        .sha256_hash()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    let etag = format!("\"{}\"", etag)
+        .parse::<ETag>()
+        .expect("hex digests are valid entity tags");
+    let last_modified = asset
+        .metadata
+        .last_modified()
+        .map(|seconds| LastModified::from(UNIX_EPOCH + Duration::from_secs(seconds)));
+
+    // If-Modified-Since is only considered when If-None-Match is absent.
+    let not_modified = match request.headers().typed_get::<IfNoneMatch>() {
+        Some(if_none_match) => !if_none_match.precondition_passes(&etag),
+        None => match (
+            request.headers().typed_get::<IfModifiedSince>(),
+            &last_modified,
+        ) {
+            (Some(if_modified_since), Some(last_modified)) => {
+                !if_modified_since.is_modified((*last_modified).into())
+            }
+            _ => false,
+        },
+    };
+
+    let mut response = if not_modified {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        (
+            [
+                (CONTENT_TYPE, asset.metadata.mimetype()),
+                (CACHE_CONTROL, STATIC_CACHE_CONTROL),
+            ],
+            asset.data.into_owned(),
+        )
+            .into_response()
+    };
+
+    let headers = response.headers_mut();
+    headers.typed_insert(etag);
+    if let Some(last_modified) = last_modified {
+        headers.typed_insert(last_modified);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, Router};
+    use http::{
+        header::{CONTENT_TYPE, ETAG, IF_NONE_MATCH, LAST_MODIFIED},
+        Request, StatusCode,
+    };
+    use tower::ServiceExt;
+
+    use super::handle_embedded_static;
+
+    #[tokio::test]
+    async fn test_embedded_static_not_modified() {
+        let app = Router::new().fallback(handle_embedded_static);
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/htmx.js").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "text/javascript"
+        );
+        assert!(response.headers().contains_key(LAST_MODIFIED));
+        let etag = response.headers().get(ETAG).unwrap().clone();
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/htmx.js")
+                    .header(IF_NONE_MATCH, etag.clone())
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get(ETAG).unwrap(), &etag);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/htmx.js")
+                    .header(IF_NONE_MATCH, "\"stale\"")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .oneshot(Request::get("/missing.js").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,8 @@
+use axum::{extract::State, response::IntoResponse, Json};
+use serde_json::json;
+
+use crate::http::context::WebContext;
+
+pub(crate) async fn handle_version(State(web_context): State<WebContext>) -> impl IntoResponse {
+    Json(json!({ "version": web_context.config.version }))
+}
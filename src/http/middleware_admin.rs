@@ -0,0 +1,58 @@
+use axum::{
+    extract::{Request, State},
+    http::{
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        HeaderMap, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::http::{context::WebContext, middleware_client_ip::ClientIp};
+
+/// Guards the admin routes. They don't exist unless an admin token or an
+/// admin allowlist is configured. When both are configured, a request must
+/// come from an allowed network and carry the token.
+pub(crate) async fn admin_auth(
+    State(web_context): State<WebContext>,
+    ClientIp(client_ip): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    let admin_token = web_context.config.admin_token.as_ref();
+    let admin_allowlist = web_context.config.admin_allowlist.as_ref();
+
+    if admin_token.is_none() && admin_allowlist.is_empty() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    if !admin_allowlist.is_empty() && !admin_allowlist.iter().any(|net| net.contains(&client_ip)) {
+        tracing::debug!(%client_ip, "admin request from a disallowed network");
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    if let Some(admin_token) = admin_token {
+        if !bearer_token(request.headers()).is_some_and(|token| token_matches(token, admin_token)) {
+            return (StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, "Bearer")]).into_response();
+        }
+    }
+
+    next.run(request).await
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Compares tokens in time that only depends on their lengths.
+fn token_matches(token: &str, expected: &str) -> bool {
+    token.len() == expected.len()
+        && token
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
@@ -0,0 +1,188 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::request::Parts,
+};
+use http::{Extensions, HeaderMap};
+use ipnet::IpNet;
+use std::{
+    convert::Infallible,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+};
+
+use crate::http::context::WebContext;
+
+/// The address of the client that made the request, taking trusted reverse
+/// proxies into account.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ClientIp(pub(crate) IpAddr);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    WebContext: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, context: &S) -> Result<Self, Self::Rejection> {
+        let web_context = WebContext::from_ref(context);
+        Ok(Self(client_ip(
+            &parts.extensions,
+            &parts.headers,
+            web_context.config.trusted_proxies.as_ref(),
+        )))
+    }
+}
+
+/// Returns the peer address of the connection, or the unspecified address
+/// when the connection has no peer address (e.g. unix sockets).
+pub(crate) fn peer_ip(extensions: &Extensions) -> IpAddr {
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+pub(crate) fn client_ip(
+    extensions: &Extensions,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpNet],
+) -> IpAddr {
+    resolve_client_ip(peer_ip(extensions), headers, trusted_proxies)
+}
+
+/// Resolves the client address for a request received from `peer`.
+///
+/// Forwarding headers are only consulted when the peer is a trusted proxy.
+/// The forwarded chain is walked from right to left, skipping trusted proxies,
+/// and the first untrusted address is the client. Entries to the left of it
+/// were supplied by the client and can't be trusted.
+pub(crate) fn resolve_client_ip(
+    peer: IpAddr,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpNet],
+) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+
+    if !is_trusted(&peer) {
+        return peer;
+    }
+
+    let mut client = peer;
+    for hop in forwarded_chain(headers).into_iter().rev() {
+        client = hop;
+        if !is_trusted(&hop) {
+            break;
+        }
+    }
+    client
+}
+
+/// Returns the forwarded-for addresses from the `Forwarded` header, or from
+/// `X-Forwarded-For` when `Forwarded` is absent, in header order.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<IpAddr> {
+    let forwarded = headers
+        .get_all("forwarded")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                if !key.trim().eq_ignore_ascii_case("for") {
+                    return None;
+                }
+                parse_node(value)
+            })
+        })
+        .collect::<Vec<IpAddr>>();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(parse_node)
+        .collect()
+}
+
+fn parse_node(value: &str) -> Option<IpAddr> {
+    let value = value.trim().trim_matches('"');
+    if let Some(bracketed) = value.strip_prefix('[') {
+        return bracketed.split(']').next()?.parse::<IpAddr>().ok();
+    }
+    value
+        .parse::<IpAddr>()
+        .ok()
+        .or_else(|| value.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{HeaderMap, HeaderValue};
+    use ipnet::IpNet;
+    use std::net::IpAddr;
+
+    use super::resolve_client_ip;
+
+    fn headers(name: &'static str, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn test_untrusted_peer_ignores_forwarded_for() {
+        let trusted: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+        let peer: IpAddr = "203.0.113.9".parse().unwrap();
+
+        assert_eq!(
+            resolve_client_ip(peer, &headers("x-forwarded-for", "198.51.100.1"), &trusted),
+            peer
+        );
+        assert_eq!(
+            resolve_client_ip(peer, &headers("x-forwarded-for", "198.51.100.1"), &[]),
+            peer
+        );
+    }
+
+    #[test]
+    fn test_trusted_proxy_forwarded_for() {
+        let trusted: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+        let peer: IpAddr = "10.0.0.2".parse().unwrap();
+
+        assert_eq!(
+            resolve_client_ip(peer, &headers("x-forwarded-for", "198.51.100.1"), &trusted),
+            "198.51.100.1".parse::<IpAddr>().unwrap()
+        );
+
+        // The left-most entry was supplied by the client and is ignored.
+        assert_eq!(
+            resolve_client_ip(
+                peer,
+                &headers("x-forwarded-for", "192.0.2.66, 198.51.100.1, 10.0.0.7"),
+                &trusted
+            ),
+            "198.51.100.1".parse::<IpAddr>().unwrap()
+        );
+
+        assert_eq!(
+            resolve_client_ip(
+                peer,
+                &headers(
+                    "forwarded",
+                    "for=192.0.2.60;proto=https, for=\"[2001:db8::17]:4711\""
+                ),
+                &trusted
+            ),
+            "2001:db8::17".parse::<IpAddr>().unwrap()
+        );
+
+        assert_eq!(resolve_client_ip(peer, &HeaderMap::new(), &trusted), peer);
+    }
+}
@@ -0,0 +1,114 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+use axum_template::TemplateEngine;
+use http::header::ACCEPT;
+use minijinja::context as template_context;
+
+use crate::{
+    errors::{expand_error, ErrorKind, ERROR_INTERNAL_SERVER_ERROR},
+    http::{context::WebContext, middleware_i18n::Language},
+};
+
+/// Replaces the empty body of internal errors raised by handlers with a
+/// localized error page, or a localized plain text message for clients that
+/// don't accept HTML.
+pub(crate) async fn error_page(
+    State(web_context): State<WebContext>,
+    Language(language): Language,
+    request: Request,
+    next: Next,
+) -> Response {
+    let accepts_html = request
+        .headers()
+        .get(ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("text/html"));
+
+    let response = next.run(request).await;
+
+    if response.extensions().get::<ErrorKind>() != Some(&ErrorKind::Internal) {
+        return response;
+    }
+
+    let (err_bare, err_partial) = expand_error(ERROR_INTERNAL_SERVER_ERROR);
+    let error_message =
+        web_context
+            .i18n_context
+            .locales()
+            .format_error(&language, &err_bare, &err_partial);
+
+    let status = response.status();
+
+    if accepts_html {
+        let rendered = web_context.engine.render(
+            &format!("error.{}.html", language.to_string().to_lowercase()),
+            template_context! {
+                language => language.to_string(),
+                error_message => error_message.clone(),
+            },
+        );
+        match rendered {
+            Ok(body) => return (status, Html(body)).into_response(),
+            Err(err) => tracing::warn!(error = ?err, "rendering error page failed"),
+        }
+    }
+
+    (status, error_message).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use http::{header::ACCEPT, Request, StatusCode};
+    use tower::ServiceExt;
+
+    use super::error_page;
+    use crate::{errors::HopperError, http::context::tests::web_context};
+
+    #[tokio::test]
+    async fn test_error_page() {
+        let web_context = web_context(r#"external_base = "hopper.test""#);
+        let app = Router::new()
+            .route(
+                "/",
+                get(|| async { Err::<(), HopperError>(HopperError(anyhow!("boom"))) }),
+            )
+            .layer(middleware::from_fn_with_state(
+                web_context.clone(),
+                error_page,
+            ))
+            .with_state(web_context);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/")
+                    .header(ACCEPT, "text/html")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("<html"));
+        assert!(body.contains("Internal Server Error"));
+
+        let response = app
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "Internal Server Error");
+    }
+}
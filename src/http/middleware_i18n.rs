@@ -14,6 +14,8 @@ use crate::{http::context::WebContext, i18n::errors::I18nError};
 
 pub(crate) const COOKIE_LANG: &str = "lang";
 
+pub(crate) const DEFAULT_LANGUAGE: &str = "en-us";
+
 #[derive(Clone)]
 struct AcceptedLanguage {
     value: String,
@@ -60,8 +62,14 @@ impl FromStr for AcceptedLanguage {
             return Err(I18nError::InvalidLanguage());
         }
 
-        let quality = if let Some(quality) = quality.and_then(|q| q.strip_prefix("q=")) {
-            quality.parse::<f32>().unwrap_or(0.0)
+        // A malformed q-value is treated like an absent one (1.0) rather than
+        // ranking the entry below genuinely low-quality entries.
+        let quality = if let Some(quality) = quality.and_then(|q| q.trim().strip_prefix("q=")) {
+            quality
+                .parse::<f32>()
+                .ok()
+                .filter(|quality| (0.0..=1.0).contains(quality))
+                .unwrap_or(1.0)
         } else {
             1.0
         };
@@ -122,32 +130,120 @@ where
             }
         }
 
-        let accept_languages = &mut parts
+        if let Some(language) = parts
             .headers
             .get("accept-language")
             .and_then(|header| header.to_str().ok())
-            .map(|header| {
-                header
-                    .split(',')
-                    .filter_map(|lang| lang.parse::<AcceptedLanguage>().ok())
-                    .collect::<Vec<AcceptedLanguage>>()
+            .and_then(|header| {
+                negotiate_accept_language(header, &web_context.i18n_context.supported_languages)
             })
-            .unwrap_or_default();
+        {
+            return Ok(Self(language));
+        }
 
-        accept_languages.sort();
+        Ok(Self(fallback_language(
+            &web_context.i18n_context.supported_languages,
+        )))
+    }
+}
 
-        for accept_language in accept_languages {
-            if let Ok(value) = accept_language.value.parse::<LanguageIdentifier>() {
-                for lang in &web_context.i18n_context.supported_languages {
-                    if lang.matches(&value, true, false) {
-                        return Ok(Self(lang.clone()));
-                    }
+/// Selects a supported language from an `Accept-Language` header value.
+///
+/// Entries are considered from highest to lowest quality and entries with a
+/// quality of 0 are ignored. The `*` wildcard only selects the fallback
+/// language when no other entry matches.
+pub(crate) fn negotiate_accept_language(
+    header: &str,
+    supported_languages: &[LanguageIdentifier],
+) -> Option<LanguageIdentifier> {
+    let mut accept_languages = header
+        .split(',')
+        .filter_map(|lang| lang.parse::<AcceptedLanguage>().ok())
+        .filter(|lang| lang.quality > 0.0)
+        .collect::<Vec<AcceptedLanguage>>();
+
+    accept_languages.sort_by(|a, b| b.cmp(a));
+
+    let mut wildcard = false;
+    for accept_language in &accept_languages {
+        if accept_language.value == "*" {
+            wildcard = true;
+            continue;
+        }
+        if let Ok(value) = accept_language.value.parse::<LanguageIdentifier>() {
+            for lang in supported_languages {
+                if lang.matches(&value, true, false) {
+                    return Some(lang.clone());
                 }
             }
         }
+    }
+
+    if wildcard {
+        return Some(fallback_language(supported_languages));
+    }
+
+    None
+}
+
+/// Returns the first supported language, or the built-in default when the
+/// supported language list is empty.
+pub(crate) fn fallback_language(supported_languages: &[LanguageIdentifier]) -> LanguageIdentifier {
+    if let Some(language) = supported_languages.first() {
+        return language.clone();
+    }
+
+    tracing::warn!(
+        default_language = DEFAULT_LANGUAGE,
+        "no supported languages configured, using default language"
+    );
+    DEFAULT_LANGUAGE.parse().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use unic_langid::LanguageIdentifier;
+
+    use super::{fallback_language, negotiate_accept_language};
+
+    #[test]
+    fn test_fallback_language() {
+        let en_us = LanguageIdentifier::from_str("en-us").unwrap();
+        let fr_fr = LanguageIdentifier::from_str("fr-fr").unwrap();
+
+        assert_eq!(fallback_language(&[]), en_us);
+        assert_eq!(fallback_language(&[fr_fr.clone(), en_us]), fr_fr);
+    }
 
-        Ok(Self(
-            web_context.i18n_context.supported_languages[0].clone(),
-        ))
+    #[test]
+    fn test_negotiate_accept_language() {
+        let en_us = LanguageIdentifier::from_str("en-us").unwrap();
+        let fr_fr = LanguageIdentifier::from_str("fr-fr").unwrap();
+        let supported = vec![en_us.clone(), fr_fr.clone()];
+
+        assert_eq!(
+            negotiate_accept_language("*", &supported),
+            Some(en_us.clone())
+        );
+        assert_eq!(
+            negotiate_accept_language("*;q=0.9, fr-FR;q=0.5", &supported),
+            Some(fr_fr.clone())
+        );
+        assert_eq!(negotiate_accept_language("de-DE", &supported), None);
+
+        assert_eq!(
+            negotiate_accept_language("fr-FR;q=0.5, en-US;q=bad", &supported),
+            Some(en_us.clone())
+        );
+
+        assert_eq!(
+            negotiate_accept_language("en-US;q=0.4, fr-FR;q=0.8, de-DE", &supported),
+            Some(fr_fr.clone())
+        );
+        assert_eq!(
+            negotiate_accept_language("fr-FR;q=0, en-US;q=0.1", &supported),
+            Some(en_us)
+        );
     }
 }
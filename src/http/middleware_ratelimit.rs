@@ -0,0 +1,109 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http::StatusCode;
+use moka::future::Cache;
+use std::{
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::http::{
+    context::WebContext, middleware_client_ip::client_ip, retry_after::with_retry_after,
+};
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// A token bucket per client IP. Each bucket holds up to `requests` tokens and
+/// refills at `requests` per `window`. Idle buckets are dropped once they
+/// would have refilled completely.
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_second: f64,
+    buckets: Cache<IpAddr, Arc<Mutex<Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(requests: u32, window: Duration) -> Self {
+        Self {
+            capacity: requests as f64,
+            refill_per_second: requests as f64 / window.as_secs_f64(),
+            buckets: Cache::builder()
+                .max_capacity(1024 * 100)
+                .time_to_idle(window)
+                .build(),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.capacity > 0.0
+    }
+
+    /// Takes a token for the client, returning how long to wait before
+    /// retrying when the bucket is empty.
+    pub(crate) async fn check(&self, client_ip: IpAddr) -> Result<(), Duration> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        let capacity = self.capacity;
+        let bucket = self
+            .buckets
+            .get_with(client_ip, async move {
+                Arc::new(Mutex::new(Bucket {
+                    tokens: capacity,
+                    updated: Instant::now(),
+                }))
+            })
+            .await;
+
+        let mut bucket = bucket.lock().expect("rate limit bucket lock poisoned");
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        Err(Duration::from_secs_f64(
+            (1.0 - bucket.tokens) / self.refill_per_second,
+        ))
+    }
+}
+
+/// Rate limits requests that resolve an AT-URI or reverse a URL. Requests
+/// without an `aturi` or `url` query parameter are passed through.
+pub(crate) async fn rate_limit(
+    State(web_context): State<WebContext>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_resolution = request.uri().query().is_some_and(|query| {
+        url::form_urlencoded::parse(query.as_bytes()).any(|(key, _)| key == "aturi" || key == "url")
+    });
+    if !is_resolution {
+        return next.run(request).await;
+    }
+
+    let client_ip = client_ip(
+        request.extensions(),
+        request.headers(),
+        web_context.config.trusted_proxies.as_ref(),
+    );
+
+    if let Err(retry_after) = web_context.rate_limiter.check(client_ip).await {
+        tracing::debug!(%client_ip, "rate limit exceeded");
+        return with_retry_after(StatusCode::TOO_MANY_REQUESTS.into_response(), retry_after);
+    }
+
+    next.run(request).await
+}
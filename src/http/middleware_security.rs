@@ -0,0 +1,132 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::request::Parts,
+    middleware::Next,
+    response::Response,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use http::{
+    header::{
+        CONTENT_SECURITY_POLICY, CONTENT_TYPE, REFERRER_POLICY, X_CONTENT_TYPE_OPTIONS,
+        X_FRAME_OPTIONS,
+    },
+    HeaderValue,
+};
+
+use rand::Rng;
+use std::convert::Infallible;
+
+use crate::http::context::WebContext;
+
+/// A random value generated for each request, allowing inline elements that
+/// carry it as their `nonce` attribute under the Content-Security-Policy.
+#[derive(Clone, Debug)]
+pub(crate) struct CspNonce(pub(crate) String);
+
+impl CspNonce {
+    fn generate() -> Self {
+        // URL-safe characters aren't escaped when rendered into templates.
+        Self(URL_SAFE_NO_PAD.encode(rand::thread_rng().gen::<[u8; 16]>()))
+    }
+}
+
+/// Reads the nonce set by [`security_headers`]. Outside of it a fresh nonce is
+/// generated, which no policy refers to.
+#[async_trait]
+impl<S> FromRequestParts<S> for CspNonce
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<CspNonce>()
+            .cloned()
+            .unwrap_or_else(CspNonce::generate))
+    }
+}
+
+/// Adds the nonce to the `script-src` directive, and to `style-src` unless it
+/// allows `'unsafe-inline'`, which browsers ignore once a nonce is present.
+fn with_nonce(policy: &str, nonce: &CspNonce) -> String {
+    policy
+        .split(';')
+        .map(|directive| {
+            let directive = directive.trim();
+            let name = directive.split_whitespace().next().unwrap_or_default();
+            let applies = name == "script-src"
+                || (name == "style-src" && !directive.contains("'unsafe-inline'"));
+            if applies {
+                format!("{} 'nonce-{}'", directive, nonce.0)
+            } else {
+                directive.to_string()
+            }
+        })
+        .filter(|directive| !directive.is_empty())
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// Adds security headers to HTML responses. Other responses, such as static
+/// assets and JSON, are passed through unchanged.
+pub(crate) async fn security_headers(
+    State(web_context): State<WebContext>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let nonce = CspNonce::generate();
+    request.extensions_mut().insert(nonce.clone());
+
+    let mut response = next.run(request).await;
+
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    if !is_html {
+        return response;
+    }
+
+    let headers = response.headers_mut();
+    let policy = web_context.config.content_security_policy.as_ref();
+    let policy = policy
+        .to_str()
+        .ok()
+        .and_then(|policy| HeaderValue::from_str(&with_nonce(policy, &nonce)).ok())
+        .unwrap_or_else(|| policy.clone());
+    headers.insert(CONTENT_SECURITY_POLICY, policy);
+    headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    headers.insert(
+        REFERRER_POLICY,
+        HeaderValue::from_static("strict-origin-when-cross-origin"),
+    );
+    headers.insert(X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{with_nonce, CspNonce};
+    use crate::config::DEFAULT_CONTENT_SECURITY_POLICY;
+
+    #[test]
+    fn test_with_nonce() {
+        let nonce = CspNonce("abc".to_string());
+        let policy = with_nonce(DEFAULT_CONTENT_SECURITY_POLICY, &nonce);
+        assert!(policy.contains("script-src 'self' 'nonce-abc';"));
+        // A nonce would disable 'unsafe-inline', which htmx relies on.
+        assert!(policy.contains("style-src 'self' 'unsafe-inline';"));
+
+        assert_eq!(
+            with_nonce("default-src 'self'; style-src 'self';", &nonce),
+            "default-src 'self'; style-src 'self' 'nonce-abc'"
+        );
+
+        assert_ne!(CspNonce::generate().0, CspNonce::generate().0);
+    }
+}
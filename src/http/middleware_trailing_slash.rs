@@ -0,0 +1,35 @@
+use axum::{
+    extract::Request,
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+
+/// Redirects paths with a trailing slash or repeated slashes, such as
+/// `/spec/` or `//`, to their canonical form with a 308.
+///
+/// A redirect is used rather than an internal rewrite so that each page has
+/// a single URL for caches and search engines, and because it works as a
+/// router layer: a rewrite would have to run before routing. Paths under
+/// `/static/` are left untouched. The canonical path always starts with a
+/// single slash, so `//example.com/` cannot become an off-site redirect.
+pub(crate) async fn trailing_slash(request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    if path == "/" || path.starts_with("/static/") || (!path.ends_with('/') && !path.contains("//"))
+    {
+        return next.run(request).await;
+    }
+
+    let mut location = format!(
+        "/{}",
+        path.split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<&str>>()
+            .join("/")
+    );
+    if let Some(query) = request.uri().query() {
+        location.push('?');
+        location.push_str(query);
+    }
+
+    Redirect::permanent(&location).into_response()
+}
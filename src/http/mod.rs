@@ -1,7 +1,28 @@
 pub mod context;
+pub(crate) mod handle_debug_caches;
+pub(crate) mod handle_debug_resolve;
+pub(crate) mod handle_favicon;
+pub(crate) mod handle_host_meta;
 pub(crate) mod handle_index;
+pub(crate) mod handle_lang;
+pub(crate) mod handle_openapi;
 pub(crate) mod handle_policy;
+pub(crate) mod handle_qr;
+pub(crate) mod handle_readyz;
+pub(crate) mod handle_reverse;
+pub(crate) mod handle_robots;
+pub(crate) mod handle_sitemap;
 pub(crate) mod handle_spec;
+#[cfg(any(feature = "embed", test))]
+pub(crate) mod handle_static;
+pub(crate) mod handle_version;
+pub(crate) mod middleware_admin;
+pub(crate) mod middleware_client_ip;
+pub(crate) mod middleware_error;
 pub(crate) mod middleware_i18n;
+pub(crate) mod middleware_ratelimit;
+pub(crate) mod middleware_security;
+pub(crate) mod middleware_trailing_slash;
+pub(crate) mod retry_after;
 pub mod server;
 pub mod templates;
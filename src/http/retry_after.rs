@@ -0,0 +1,35 @@
+use axum::response::Response;
+use http::{header::RETRY_AFTER, HeaderValue};
+use std::time::Duration;
+
+/// Sets the `Retry-After` header of a `429` or `503` response to `delay` in
+/// whole seconds, rounded up and at least one so clients never retry
+/// immediately.
+pub(crate) fn with_retry_after(mut response: Response, delay: Duration) -> Response {
+    let seconds = delay.as_secs_f64().ceil().max(1.0) as u64;
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(seconds));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::response::IntoResponse;
+    use http::{header::RETRY_AFTER, StatusCode};
+    use std::time::Duration;
+
+    use super::with_retry_after;
+
+    #[test]
+    fn test_with_retry_after() {
+        for (delay, expected) in [
+            (Duration::from_secs(30), "30"),
+            (Duration::from_millis(1500), "2"),
+            (Duration::ZERO, "1"),
+        ] {
+            let response = with_retry_after(StatusCode::SERVICE_UNAVAILABLE.into_response(), delay);
+            assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), expected);
+        }
+    }
+}
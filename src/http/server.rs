@@ -1,55 +1,1251 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
-#[cfg(feature = "embed")]
-use std::convert::Infallible;
-
-use axum::{http::HeaderValue, routing::get, Router};
-
-#[cfg(feature = "embed")]
-use axum::{body::Body, extract::Request, response::Response};
+use anyhow::{Context, Result};
+use axum::{
+    http::HeaderValue,
+    middleware,
+    routing::{get, post},
+    Router,
+};
 
 use axum_htmx::AutoVaryLayer;
 use http::{
     header::{ACCEPT, ACCEPT_LANGUAGE},
     Method,
 };
-use tower_http::cors::CorsLayer;
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder,
+};
+use tokio::net::UnixListener;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tower::Service;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::timeout::TimeoutLayer;
 use tower_http::trace::TraceLayer;
 
 #[cfg(feature = "reload")]
 use tower_http::services::ServeDir;
 
+use crate::config::CorsOrigins;
 use crate::http::{
-    context::WebContext, handle_index::handle_index, handle_policy::handle_policy,
-    handle_spec::handle_spec,
+    context::WebContext, handle_debug_caches::handle_debug_caches,
+    handle_debug_resolve::handle_debug_resolve, handle_favicon::handle_favicon,
+    handle_host_meta::handle_host_meta, handle_index::handle_index, handle_lang::handle_lang,
+    handle_openapi::handle_openapi, handle_policy::handle_policy, handle_qr::handle_qr,
+    handle_readyz::handle_readyz, handle_reverse::handle_reverse, handle_robots::handle_robots,
+    handle_sitemap::handle_sitemap, handle_spec::handle_spec, handle_version::handle_version,
+    middleware_admin::admin_auth, middleware_client_ip::client_ip, middleware_error::error_page,
+    middleware_ratelimit::rate_limit, middleware_security::security_headers,
+    middleware_trailing_slash::trailing_slash,
 };
 
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The https origin of `external_base`, which cross-origin requests are
+/// allowed from.
+pub fn cors_origin(external_base: &str) -> Result<HeaderValue> {
+    format!("https://{}", external_base)
+        .parse::<HeaderValue>()
+        .with_context(|| {
+            format!(
+                "EXTERNAL_BASE {:?} is not a valid CORS origin",
+                external_base
+            )
+        })
+}
+
 pub fn build_router(web_context: WebContext) -> Router {
+    let trusted_proxies = web_context.config.trusted_proxies.as_ref().clone();
+
+    // An unusable external base only disables cross-origin requests, as
+    // `Config::validate` already reports it at startup.
+    let allow_origin = match &web_context.config.cors_origins {
+        CorsOrigins::ExternalBase => match cors_origin(&web_context.config.external_base) {
+            Ok(origin) => AllowOrigin::exact(origin),
+            Err(err) => {
+                tracing::warn!("{:#}, cross-origin requests are not allowed", err);
+                AllowOrigin::list([])
+            }
+        },
+        CorsOrigins::Any => AllowOrigin::any(),
+        CorsOrigins::List(origins) => AllowOrigin::list(origins.clone()),
+    };
+
     #[cfg(feature = "reload")]
     let serve_dir = ServeDir::new("static");
 
     #[cfg(feature = "embed")]
-    let serve_dir = tower::service_fn(|_request: Request| async {
-        Ok::<_, Infallible>(Response::new(Body::empty()))
-    });
-
-    Router::new()
-        .route("/", get(handle_index))
-        .route("/spec", get(handle_spec))
-        .route("/policy", get(handle_policy))
+    let serve_dir = axum::handler::HandlerWithoutStateExt::into_service(
+        crate::http::handle_static::handle_embedded_static,
+    );
+
+    // Pages are the routes listed in the sitemap. `get` routes also answer
+    // HEAD with the GET status and headers, such as a redirect's Location,
+    // and no body, which link checkers rely on.
+    let pages = [
+        (
+            "/",
+            get(handle_index).route_layer(middleware::from_fn_with_state(
+                web_context.clone(),
+                rate_limit,
+            )),
+        ),
+        ("/spec", get(handle_spec)),
+        ("/policy", get(handle_policy)),
+    ];
+    let page_paths: Arc<[&'static str]> = pages.iter().map(|(path, _)| *path).collect();
+
+    let router = pages
+        .into_iter()
+        .fold(Router::new(), |router, (path, method_router)| {
+            router.route(path, method_router)
+        });
+
+    // Admin routes live under /debug and share a single guard.
+    let admin_router = Router::new()
+        .route("/debug/caches", get(handle_debug_caches))
+        .route("/debug/resolve", get(handle_debug_resolve))
+        .route_layer(middleware::from_fn_with_state(
+            web_context.clone(),
+            admin_auth,
+        ));
+
+    router
+        .route(
+            "/sitemap.xml",
+            get(move |state| handle_sitemap(state, page_paths.clone())),
+        )
+        .route(
+            "/qr",
+            get(handle_qr).route_layer(middleware::from_fn_with_state(
+                web_context.clone(),
+                rate_limit,
+            )),
+        )
+        .route(
+            "/reverse",
+            get(handle_reverse).route_layer(middleware::from_fn_with_state(
+                web_context.clone(),
+                rate_limit,
+            )),
+        )
+        .route("/lang", post(handle_lang))
+        .route("/robots.txt", get(handle_robots))
+        .route("/favicon.ico", get(handle_favicon))
+        .merge(admin_router)
+        .route("/version", get(handle_version))
+        .route("/readyz", get(handle_readyz))
+        .route("/api/openapi.json", get(handle_openapi))
+        .route("/.well-known/host-meta.json", get(handle_host_meta))
         .nest_service("/static", serve_dir.clone())
         .fallback_service(serve_dir)
+        .layer(middleware::from_fn(trailing_slash))
+        .layer(middleware::from_fn_with_state(
+            web_context.clone(),
+            error_page,
+        ))
+        .layer(middleware::from_fn_with_state(
+            web_context.clone(),
+            security_headers,
+        ))
         .layer((
-            TraceLayer::new_for_http(),
+            SetRequestIdLayer::x_request_id(MakeRequestUuid),
+            TraceLayer::new_for_http().make_span_with(
+                move |request: &http::Request<axum::body::Body>| {
+                    let request_id = request
+                        .headers()
+                        .get(REQUEST_ID_HEADER)
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default();
+                    let client_ip =
+                        client_ip(request.extensions(), request.headers(), &trusted_proxies);
+                    tracing::debug_span!(
+                        "request",
+                        method = %request.method(),
+                        uri = %request.uri(),
+                        version = ?request.version(),
+                        request_id,
+                        %client_ip,
+                    )
+                },
+            ),
+            PropagateRequestIdLayer::x_request_id(),
             TimeoutLayer::new(Duration::from_secs(10)),
         ))
         .layer(
             CorsLayer::new()
-                .allow_origin(web_context.external_base.parse::<HeaderValue>().unwrap())
+                .allow_origin(allow_origin)
                 .allow_methods([Method::GET])
                 .allow_headers([ACCEPT_LANGUAGE, ACCEPT]),
         )
         .layer(AutoVaryLayer)
         .with_state(web_context.clone())
 }
+
+/// Serves the router on a unix domain socket until the token is cancelled.
+///
+/// `axum::serve` only accepts TCP listeners, so connections are driven with
+/// hyper directly. In-flight connections are shut down gracefully on
+/// cancellation, and this returns once they have finished.
+pub async fn serve_unix(
+    listener: UnixListener,
+    app: Router,
+    token: CancellationToken,
+) -> Result<()> {
+    let mut make_service = app.into_make_service();
+    let connections = TaskTracker::new();
+
+    loop {
+        let (socket, _remote_addr) = tokio::select! {
+            result = listener.accept() => result?,
+            () = token.cancelled() => break,
+        };
+
+        let tower_service = make_service.call(&socket).await?;
+        let connection_token = token.clone();
+
+        connections.spawn(async move {
+            let socket = TokioIo::new(socket);
+            let hyper_service =
+                hyper::service::service_fn(move |request: hyper::Request<Incoming>| {
+                    tower_service
+                        .clone()
+                        .call(request.map(axum::body::Body::new))
+                });
+
+            let builder = Builder::new(TokioExecutor::new());
+            let connection = builder.serve_connection_with_upgrades(socket, hyper_service);
+            tokio::pin!(connection);
+
+            let result = tokio::select! {
+                result = connection.as_mut() => result,
+                () = connection_token.cancelled() => {
+                    connection.as_mut().graceful_shutdown();
+                    connection.await
+                }
+            };
+            if let Err(err) = result {
+                tracing::debug!(error = ?err, "unix socket connection failed");
+            }
+        });
+    }
+
+    connections.close();
+    connections.wait().await;
+    Ok(())
+}
+
+/// Waits for the closed tracker's tasks to finish, giving up after
+/// `grace_period`. Returns false, after logging how many tasks were still
+/// running, when the grace period elapsed first.
+pub async fn wait_for_shutdown(tracker: &TaskTracker, grace_period: Duration) -> bool {
+    if tokio::time::timeout(grace_period, tracker.wait())
+        .await
+        .is_ok()
+    {
+        return true;
+    }
+    tracing::warn!(
+        "Shutdown grace period of {:?} elapsed with {} tasks still running",
+        grace_period,
+        tracker.len()
+    );
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, routing::get, Router};
+    use http::{
+        header::{
+            ACCESS_CONTROL_ALLOW_ORIGIN, CACHE_CONTROL, CONTENT_SECURITY_POLICY, CONTENT_TYPE,
+            LINK, LOCATION, ORIGIN, RETRY_AFTER, SET_COOKIE, WWW_AUTHENTICATE,
+            X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+        },
+        Request, StatusCode,
+    };
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{UnixListener, UnixStream},
+        sync::Notify,
+    };
+    use tokio_util::sync::CancellationToken;
+    use tower::ServiceExt;
+
+    use super::{build_router, cors_origin, serve_unix, wait_for_shutdown};
+    use crate::{
+        config::{version, DEFAULT_CONTENT_SECURITY_POLICY},
+        http::context::tests::web_context,
+        i18n::tests::LogBuffer,
+        model::DEFAULT_RESERVED_SUFFIXES,
+        resolve::parse_servers,
+        webhostmeta::{Link, WebHostMeta},
+    };
+
+    pub(crate) const TEST_CONFIG: &str = r#"external_base = "hopper.test""#;
+
+    #[tokio::test]
+    async fn test_serve_unix() {
+        let path = std::env::temp_dir().join(format!("hopper-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path).unwrap();
+        let started = Arc::new(Notify::new());
+        let finished = Arc::new(AtomicBool::new(false));
+        let app = Router::new().route("/", get(|| async { "hopper" })).route(
+            "/slow",
+            get({
+                let started = Arc::clone(&started);
+                let finished = Arc::clone(&finished);
+                || async move {
+                    started.notify_one();
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    finished.store(true, Ordering::SeqCst);
+                    "slow"
+                }
+            }),
+        );
+        let token = CancellationToken::new();
+        let server = tokio::spawn(serve_unix(listener, app, token.clone()));
+
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("hopper"));
+
+        // A request in flight at cancellation is completed before returning.
+        let mut stream = UnixStream::connect(&path).await.unwrap();
+        stream
+            .write_all(b"GET /slow HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        started.notified().await;
+        token.cancel();
+        server.await.unwrap().unwrap();
+        assert!(finished.load(Ordering::SeqCst));
+        std::fs::remove_file(&path).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("slow"));
+    }
+
+    #[tokio::test]
+    async fn test_security_headers() {
+        let app = build_router(web_context(TEST_CONFIG));
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        // The configured policy is sent with the request's nonce added.
+        let policy = response
+            .headers()
+            .get(CONTENT_SECURITY_POLICY)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        let (before, after) = policy.split_once(" 'nonce-").unwrap();
+        let (_, after) = after.split_once('\'').unwrap();
+        assert_eq!(
+            format!("{}{}", before, after),
+            DEFAULT_CONTENT_SECURITY_POLICY
+        );
+        assert_eq!(
+            response.headers().get(X_CONTENT_TYPE_OPTIONS).unwrap(),
+            "nosniff"
+        );
+        assert_eq!(response.headers().get(X_FRAME_OPTIONS).unwrap(), "DENY");
+
+        let response = app
+            .oneshot(
+                Request::get("/site.webmanifest")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(response.headers().get(CONTENT_SECURITY_POLICY).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_robots() {
+        let app = build_router(web_context(TEST_CONFIG));
+
+        let response = app
+            .oneshot(Request::get("/robots.txt").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "text/plain; charset=utf-8"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("Disallow: /?"));
+    }
+
+    #[tokio::test]
+    async fn test_version() {
+        let app = build_router(web_context(TEST_CONFIG));
+
+        let response = app
+            .oneshot(Request::get("/version").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["version"], version().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_request_id() {
+        let app = build_router(web_context(TEST_CONFIG));
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/version").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(response.headers().get("x-request-id").is_some());
+
+        let response = app
+            .oneshot(
+                Request::get("/version")
+                    .header("x-request-id", "hopper-test-request")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers().get("x-request-id").unwrap(),
+            "hopper-test-request"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let app = build_router(web_context(
+            r#"
+external_base = "hopper.test"
+rate_limit_requests = 2
+rate_limit_window = 60
+"#,
+        ));
+
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(Request::get("/?aturi=invalid").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/?aturi=invalid").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after = response.headers().get(RETRY_AFTER).unwrap();
+        assert!(retry_after.to_str().unwrap().parse::<u64>().unwrap() > 0);
+
+        // Reverse lookups share the client's bucket.
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/reverse?url=invalid")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let response = app
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_host_meta() {
+        let app = build_router(web_context(TEST_CONFIG));
+
+        let response = app
+            .oneshot(
+                Request::get("/.well-known/host-meta.json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let webhostmeta: WebHostMeta = serde_json::from_slice(&body).unwrap();
+        assert!(webhostmeta.links.contains(&Link::new(
+            "https://bsky.app/profile/{identity}/post/{rkey}",
+            Some("app.bsky.feed.post"),
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_qr() {
+        let web_context = web_context(TEST_CONFIG);
+        web_context.seed_webhostmeta_cache().await;
+        let app = build_router(web_context);
+
+        let response = app
+            .oneshot(
+                Request::get("/qr?aturi=at://ngerakines.me&server=bsky.app")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), "image/png");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.starts_with(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    #[tokio::test]
+    async fn test_index_unsupported_lists_servers() {
+        let web_context = web_context(&format!(
+            "{}\nmax_error_servers = 2\nhost_allowlist = [\"bsky.app\", \"frontpage.fyi\", \"whtwnd.com\"]",
+            TEST_CONFIG
+        ));
+        web_context.seed_webhostmeta_cache().await;
+        let app = build_router(web_context);
+
+        let response = app
+            .oneshot(
+                Request::get(
+                    "/?aturi=at://alice.example/com.example.unknown/abc&server=whtwnd.com",
+                )
+                .body(Body::empty())
+                .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8_lossy(&body);
+
+        // The user supplied server comes first and isn't repeated, the
+        // disallowed default is left out, and the rest are counted.
+        let error = body
+            .split("<small id=\"aturi-error\">")
+            .nth(1)
+            .and_then(|rest| rest.split("</small>").next())
+            .unwrap()
+            .replace(['\u{2068}', '\u{2069}'], "");
+        assert_eq!(
+            error,
+            "The AT-URI is not supported by the servers tried: whtwnd.com, frontpage.fyi, and 1 more."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reverse() {
+        let web_context = web_context(TEST_CONFIG);
+        web_context.seed_webhostmeta_cache().await;
+        let app = build_router(web_context);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get(
+                    "/reverse?url=https%3A%2F%2Fbsky.app%2Fprofile%2Fngerakines.me%2Fpost%2F3l3q2kavqw22f",
+                )
+                .body(Body::empty())
+                .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["aturi"],
+            "at://ngerakines.me/app.bsky.feed.post/3l3q2kavqw22f"
+        );
+
+        for (url, status) in [
+            ("https%3A%2F%2Fbsky.app%2Fsearch", StatusCode::NOT_FOUND),
+            (
+                "http%3A%2F%2Fbsky.app%2Fprofile%2Fngerakines.me",
+                StatusCode::BAD_REQUEST,
+            ),
+            ("not%20a%20url", StatusCode::BAD_REQUEST),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::get(format!("/reverse?url={}", url))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status, "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_index_preview() {
+        let web_context = web_context(TEST_CONFIG);
+        web_context.seed_webhostmeta_cache().await;
+        let app = build_router(web_context);
+
+        let response = app
+            .oneshot(
+                Request::get("/?aturi=at://alice.example&server=bsky.app&preview=1")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(LINK).unwrap(),
+            r#"<https://bsky.app/profile/alice.example>; rel="canonical""#
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8_lossy(&body);
+        // Templates escape slashes in attribute values.
+        let destination = "https://bsky.app/profile/alice.example".replace('/', "&#x2f;");
+        assert!(body.contains(&format!(r#"href="{}""#, destination)));
+        assert!(body.contains(&format!(
+            r#"<meta property="og:url" content="{}" />"#,
+            destination
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_shutdown_abandons_hanging_task() {
+        let tracker = tokio_util::task::TaskTracker::new();
+        tracker.spawn(std::future::pending::<()>());
+        tracker.spawn(async {});
+        tracker.close();
+
+        let started = std::time::Instant::now();
+        assert!(!wait_for_shutdown(&tracker, std::time::Duration::from_millis(100)).await);
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        assert_eq!(tracker.len(), 1);
+
+        let tracker = tokio_util::task::TaskTracker::new();
+        tracker.spawn(async {});
+        tracker.close();
+        assert!(wait_for_shutdown(&tracker, std::time::Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test]
+    async fn test_sitemap() {
+        let app = build_router(web_context(TEST_CONFIG));
+
+        let response = app
+            .oneshot(Request::get("/sitemap.xml").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "application/xml"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let document = roxmltree::Document::parse(&body).unwrap();
+
+        let locs = document
+            .descendants()
+            .filter(|node| node.has_tag_name("loc"))
+            .filter_map(|node| node.text())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            locs,
+            vec![
+                "https://hopper.test/?lang=en-us",
+                "https://hopper.test/spec?lang=en-us",
+                "https://hopper.test/policy?lang=en-us",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_favicon() {
+        let app = build_router(web_context(TEST_CONFIG));
+
+        let response = app
+            .oneshot(Request::get("/favicon.ico").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "image/x-icon"
+        );
+        assert_eq!(
+            response.headers().get(CACHE_CONTROL).unwrap(),
+            "public, max-age=604800"
+        );
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, include_bytes!("../../static/favicon.ico").as_slice());
+
+        let app = build_router(web_context(&format!(
+            "{}\nfavicon_file = \"{}/static/favicon-32x32.png\"",
+            TEST_CONFIG,
+            env!("CARGO_MANIFEST_DIR")
+        )));
+        let response = app
+            .oneshot(Request::get("/favicon.ico").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(CONTENT_TYPE).unwrap(), "image/png");
+    }
+
+    #[tokio::test]
+    async fn test_trailing_slash() {
+        let app = build_router(web_context(TEST_CONFIG));
+
+        for (path, location) in [
+            ("/spec/", "/spec"),
+            ("/policy/?lang=en-us", "/policy?lang=en-us"),
+            ("//", "/"),
+            ("//example.com/", "/example.com"),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(Request::get(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::PERMANENT_REDIRECT,
+                "{}",
+                path
+            );
+            assert_eq!(response.headers().get(LOCATION).unwrap(), location);
+        }
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/spec").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app
+            .oneshot(Request::get("/static/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_ne!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    }
+
+    #[tokio::test]
+    async fn test_index_redirect_cache_control() {
+        let web_context = web_context(TEST_CONFIG);
+        web_context.seed_webhostmeta_cache().await;
+        let app = build_router(web_context);
+
+        for cache_control in ["no-store", "public, max-age=1800"] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::get("/?aturi=at://alice.example&server=bsky.app")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), StatusCode::SEE_OTHER);
+            assert_eq!(
+                response.headers().get(LOCATION).unwrap(),
+                "https://bsky.app/profile/alice.example"
+            );
+            assert_eq!(
+                response.headers().get(CACHE_CONTROL).unwrap(),
+                cache_control
+            );
+        }
+
+        let response = app
+            .oneshot(
+                Request::get("/?aturi=at://alice.example&server=bsky.app")
+                    .header("HX-Request", "true")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("HX-Redirect").unwrap(),
+            "https://bsky.app/profile/alice.example"
+        );
+        assert_eq!(
+            response.headers().get(CACHE_CONTROL).unwrap(),
+            "public, max-age=1800"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_debug_resolve() {
+        let app = build_router(web_context(TEST_CONFIG));
+        let response = app
+            .oneshot(
+                Request::get("/debug/resolve?aturi=at://alice.example")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let app = build_router(web_context(&format!(
+            "{}\nadmin_token = \"0123456789abcdef\"\nhost_allowlist = [\"allowed.example\"]",
+            TEST_CONFIG
+        )));
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/debug/resolve?aturi=at://alice.example")
+                    .header("Authorization", "Bearer wrong")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers().get(WWW_AUTHENTICATE).unwrap(), "Bearer");
+
+        let response = app
+            .oneshot(
+                Request::get("/debug/resolve?aturi=at://alice.example&server=links.example")
+                    .header("Authorization", "Bearer 0123456789abcdef")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let trace: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let servers = trace["servers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|server| server["server"].as_str().unwrap())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            servers,
+            parse_servers("links.example", 8, &DEFAULT_RESERVED_SUFFIXES)
+        );
+        // Servers outside the allowlist are reported without being fetched.
+        assert!(trace["servers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|server| server["status"] == "disallowed"));
+    }
+
+    #[tokio::test]
+    async fn test_debug_caches() {
+        let app = build_router(web_context(&format!(
+            "{}\nadmin_token = \"0123456789abcdef\"",
+            TEST_CONFIG
+        )));
+        let response = app
+            .clone()
+            .oneshot(Request::get("/debug/caches").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .oneshot(
+                Request::get("/debug/caches")
+                    .header("Authorization", "Bearer 0123456789abcdef")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let caches: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(caches["webhostmeta"]["evictions"].is_u64());
+        assert!(caches["aturi"]["evictions"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn test_admin_allowlist() {
+        // Requests without a peer address come from the unspecified address.
+        for (admin_config, status) in [
+            ("admin_allowlist = [\"0.0.0.0\"]", StatusCode::OK),
+            ("admin_allowlist = [\"10.0.0.0/8\"]", StatusCode::FORBIDDEN),
+            (
+                "admin_allowlist = [\"0.0.0.0\"]\nadmin_token = \"0123456789abcdef\"",
+                StatusCode::UNAUTHORIZED,
+            ),
+        ] {
+            let app = build_router(web_context(&format!(
+                "{}\n{}\nhost_allowlist = [\"allowed.example\"]",
+                TEST_CONFIG, admin_config
+            )));
+            let response = app
+                .oneshot(
+                    Request::get("/debug/resolve?aturi=at://alice.example")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status, "{}", admin_config);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cors_origin() {
+        let app = build_router(web_context(TEST_CONFIG));
+        let response = app
+            .oneshot(
+                Request::get("/version")
+                    .header(ORIGIN, "https://hopper.test")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "https://hopper.test"
+        );
+
+        let err = cors_origin("hopper.test\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "EXTERNAL_BASE \"hopper.test\\n\" is not a valid CORS origin"
+        );
+
+        // An invalid origin disables cross-origin requests instead of panicking.
+        let app = build_router(web_context("external_base = \"hopper.test\\n\""));
+        let response = app
+            .oneshot(
+                Request::get("/version")
+                    .header(ORIGIN, "https://hopper.test")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response
+            .headers()
+            .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cors_origins() {
+        let app = build_router(web_context(
+            r#"
+external_base = "hopper.test"
+cors_origins = ["https://hopper.test", "https://links.example"]
+"#,
+        ));
+
+        for (origin, allowed) in [
+            ("https://hopper.test", true),
+            ("https://links.example", true),
+            ("https://other.example", false),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::get("/version")
+                        .header(ORIGIN, origin)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                response
+                    .headers()
+                    .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+                    .map(|value| value.to_str().unwrap()),
+                allowed.then_some(origin),
+                "{}",
+                origin
+            );
+        }
+
+        let app = build_router(web_context(
+            r#"
+external_base = "hopper.test"
+cors_origins = ["*"]
+"#,
+        ));
+        let response = app
+            .oneshot(
+                Request::get("/version")
+                    .header(ORIGIN, "https://other.example")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+            "*"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_index_head() {
+        let web_context = web_context(TEST_CONFIG);
+        web_context.seed_webhostmeta_cache().await;
+        let app = build_router(web_context);
+
+        // Resolve once so GET and HEAD both see the cached destination.
+        app.clone()
+            .oneshot(
+                Request::get("/?aturi=at://alice.example&server=bsky.app")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        for (uri, status) in [
+            (
+                "/?aturi=at://alice.example&server=bsky.app",
+                StatusCode::SEE_OTHER,
+            ),
+            ("/?aturi=invalid", StatusCode::OK),
+            ("/", StatusCode::OK),
+        ] {
+            let get = app
+                .clone()
+                .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let head = app
+                .clone()
+                .oneshot(Request::head(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+
+            assert_eq!(head.status(), status, "{}", uri);
+            assert_eq!(head.status(), get.status(), "{}", uri);
+            for header in [LOCATION, CACHE_CONTROL, CONTENT_TYPE] {
+                assert_eq!(
+                    head.headers().get(&header),
+                    get.headers().get(&header),
+                    "{} {}",
+                    uri,
+                    header
+                );
+            }
+            let body = axum::body::to_bytes(head.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert!(body.is_empty(), "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_csp_nonce() {
+        let app = build_router(web_context(TEST_CONFIG));
+
+        let mut nonces = Vec::new();
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(Request::get("/").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let policy = response
+                .headers()
+                .get(CONTENT_SECURITY_POLICY)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string();
+            let nonce = policy
+                .split("'nonce-")
+                .nth(1)
+                .and_then(|rest| rest.split('\'').next())
+                .unwrap()
+                .to_string();
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            assert!(body.contains(&format!("nonce=\"{}\"", nonce)));
+            nonces.push(nonce);
+        }
+        assert_ne!(nonces[0], nonces[1]);
+    }
+
+    #[tokio::test]
+    async fn test_lang() {
+        let app = build_router(web_context(TEST_CONFIG));
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/lang")
+                    .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from("lang=en-US"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers().get(LOCATION).unwrap(), "/");
+        let cookie = response
+            .headers()
+            .get(SET_COOKIE)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(cookie.starts_with("lang=en-us;"), "{}", cookie);
+        assert!(cookie.contains("Path=/"), "{}", cookie);
+
+        let response = app
+            .oneshot(
+                Request::post("/lang")
+                    .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(Body::from("lang=xx"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert!(response.headers().get(SET_COOKIE).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_access_log() {
+        let logs = LogBuffer::default();
+        let _guard = tracing::subscriber::set_default(logs.subscriber());
+
+        let context = web_context(TEST_CONFIG);
+        context.seed_webhostmeta_cache().await;
+        let response = build_router(context)
+            .oneshot(
+                Request::get("/?aturi=at://alice.example&server=bsky.app")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let logs = logs.contents();
+        let line = logs
+            .lines()
+            .find(|line| line.contains("resolution completed"))
+            .unwrap_or_else(|| panic!("no access log line in {}", logs));
+        assert!(line.contains("INFO hopper::access"), "{}", line);
+        assert!(line.contains("aturi=\"at://alice.example\""), "{}", line);
+        assert!(line.contains("matched_server=\"bsky.app\""), "{}", line);
+        assert!(line.contains("destination_host=\"bsky.app\""), "{}", line);
+        assert!(line.contains("outcome=\"resolved\""), "{}", line);
+        assert!(line.contains("latency_ms="), "{}", line);
+
+        // The AT-URI can be left out of the access log.
+        let logs = LogBuffer::default();
+        let _guard = tracing::subscriber::set_default(logs.subscriber());
+        let app = build_router(web_context(&format!(
+            "{}\naccess_log_aturi = false",
+            TEST_CONFIG
+        )));
+        app.oneshot(
+            Request::get("/?aturi=at://alice.example/not%20a%20collection")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        let logs = logs.contents();
+        assert!(logs.contains("aturi=\"redacted\""), "{}", logs);
+        assert!(logs.contains("outcome=\"error\""), "{}", logs);
+        assert!(!logs.contains("at://alice.example"), "{}", logs);
+
+        // Nor does the resolution span record it.
+        let logs = LogBuffer::default();
+        let _guard = tracing::subscriber::set_default(logs.span_subscriber());
+        let context = web_context(&format!("{}\naccess_log_aturi = false", TEST_CONFIG));
+        context.seed_webhostmeta_cache().await;
+        let response = build_router(context)
+            .oneshot(
+                Request::get("/?aturi=at://alice.example&server=bsky.app")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let logs = logs.contents();
+        assert!(logs.contains("resolve_aturi"), "{}", logs);
+        assert!(!logs.contains("at://alice.example"), "{}", logs);
+    }
+}
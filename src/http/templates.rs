@@ -1,3 +1,69 @@
+use axum_template::{engine::MinijinjaError, TemplateEngine};
+use minijinja::{Environment, ErrorKind, Value};
+use unic_langid::LanguageIdentifier;
+
+use crate::http::{context::AppEngine, middleware_i18n::DEFAULT_LANGUAGE};
+
+/// Record keys longer than this are shortened by [`aturi_display`].
+const RKEY_DISPLAY_LENGTH: usize = 8;
+
+/// Formats an AT-URI for display as its identity, the last segment of its
+/// collection, and a shortened record key, e.g. `alice.example/post/3kxbvxj7…`.
+/// Anything that isn't an AT-URI is returned unchanged.
+pub(crate) fn aturi_display(value: &str) -> String {
+    let value = value.trim();
+    let Some(path) = value
+        .strip_prefix("web+")
+        .unwrap_or(value)
+        .strip_prefix("at://")
+    else {
+        return value.to_string();
+    };
+
+    let mut segments = path.splitn(3, '/');
+    let mut display = segments.next().unwrap_or_default().to_string();
+    if let Some(collection) = segments.next().filter(|s| !s.is_empty()) {
+        display.push('/');
+        display.push_str(collection.rsplit('.').next().unwrap_or(collection));
+    }
+    if let Some(rkey) = segments.next().filter(|s| !s.is_empty()) {
+        display.push('/');
+        if rkey.chars().count() > RKEY_DISPLAY_LENGTH {
+            display.extend(rkey.chars().take(RKEY_DISPLAY_LENGTH));
+            display.push('…');
+        } else {
+            display.push_str(rkey);
+        }
+    }
+    display
+}
+
+/// Registers the filters available to every template.
+pub(crate) fn add_filters(env: &mut Environment) {
+    env.add_filter("aturi_display", |value: String| aturi_display(&value));
+}
+
+/// Renders `{page}.{language}.html`, falling back to the `en-us` template
+/// when the language has no template of its own.
+pub(crate) fn render_localized(
+    engine: &AppEngine,
+    page: &str,
+    language: &LanguageIdentifier,
+    context: Value,
+) -> Result<String, MinijinjaError> {
+    let template = format!("{}.{}.html", page, language.to_string().to_lowercase());
+    match engine.render(&template, context.clone()) {
+        Err(MinijinjaError::RenderError(err)) if err.kind() == ErrorKind::TemplateNotFound => {
+            tracing::debug!(
+                template,
+                "template not found, falling back to default language"
+            );
+            engine.render(&format!("{}.{}.html", page, DEFAULT_LANGUAGE), context)
+        }
+        result => result,
+    }
+}
+
 #[cfg(feature = "reload")]
 pub mod reload_env {
     use std::path::PathBuf;
@@ -15,6 +81,7 @@ pub mod reload_env {
             env.set_lstrip_blocks(true);
             env.add_global("base", http_external.clone());
             env.add_global("version", version.clone());
+            super::add_filters(&mut env);
             env.set_loader(path_loader(&template_path));
             notifier.set_fast_reload(true);
             notifier.watch_path(&template_path, true);
@@ -33,7 +100,42 @@ pub mod embed_env {
         env.set_lstrip_blocks(true);
         env.add_global("base", http_external.clone());
         env.add_global("version", version.clone());
+        super::add_filters(&mut env);
         minijinja_embed::load_templates!(&mut env);
         env
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use minijinja::{context, Environment};
+
+    use super::{add_filters, aturi_display};
+
+    #[test]
+    fn test_aturi_display() {
+        assert_eq!(
+            aturi_display("at://did:plc:tgudj2fjm77pzkuawquqhsxm/events.smokesignal.calendar.event/3kxbvxj7blk2t"),
+            "did:plc:tgudj2fjm77pzkuawquqhsxm/event/3kxbvxj7…"
+        );
+        assert_eq!(
+            aturi_display("web+at://alice.example/app.bsky.feed.post/abc"),
+            "alice.example/post/abc"
+        );
+        assert_eq!(aturi_display("at://alice.example"), "alice.example");
+        assert_eq!(
+            aturi_display("https://example.com/"),
+            "https://example.com/"
+        );
+
+        let mut env = Environment::new();
+        add_filters(&mut env);
+        let rendered = env
+            .render_str(
+                "<code>{{ aturi|aturi_display }}</code>",
+                context! { aturi => "at://alice.example/app.bsky.feed.post/3kxbvxj7blk2t" },
+            )
+            .unwrap();
+        assert_eq!(rendered, "<code>alice.example/post/3kxbvxj7…</code>");
+    }
+}
@@ -1,22 +1,28 @@
 use anyhow::Result;
 use errors::I18nError;
-use fluent::{bundle::FluentBundle, FluentResource};
+use fluent::{bundle::FluentBundle, FluentArgs, FluentResource};
 use std::collections::HashMap;
 use unic_langid::LanguageIdentifier;
 
 pub type Bundle = FluentBundle<FluentResource, intl_memoizer::concurrent::IntlLangMemoizer>;
 
-pub struct Locales(pub(crate) HashMap<LanguageIdentifier, Bundle>);
+pub struct Locales {
+    pub(crate) bundles: HashMap<LanguageIdentifier, Bundle>,
+    pub(crate) default_locale: LanguageIdentifier,
+}
 
 impl Locales {
-    pub fn new(locales: Vec<LanguageIdentifier>) -> Self {
+    pub fn new(locales: Vec<LanguageIdentifier>, default_locale: LanguageIdentifier) -> Self {
         let mut store = HashMap::new();
         for locale in &locales {
             let bundle: FluentBundle<FluentResource, intl_memoizer::concurrent::IntlLangMemoizer> =
                 FluentBundle::new_concurrent(vec![locale.clone()]);
             store.insert(locale.clone(), bundle);
         }
-        Self(store)
+        Self {
+            bundles: store,
+            default_locale,
+        }
     }
 
     pub(crate) fn add_bundle(
@@ -25,7 +31,7 @@ impl Locales {
         content: String,
     ) -> Result<(), I18nError> {
         let bundle = self
-            .0
+            .bundles
             .get_mut(&locale)
             .ok_or(I18nError::InvalidLanguage())?;
 
@@ -39,38 +45,85 @@ impl Locales {
         Ok(())
     }
 
-    pub(crate) fn format_error(
-        &self,
-        locale: &LanguageIdentifier,
-        bare: &str,
-        partial: &str,
-    ) -> String {
-        let bundle = self.0.get(locale);
-        if bundle.is_none() {
-            return partial.to_string();
+    /// Returns the locales to consult for a message, in order: the requested
+    /// locale, its region-stripped parent, and finally the default locale.
+    pub(crate) fn fallback_chain(&self, locale: &LanguageIdentifier) -> Vec<LanguageIdentifier> {
+        let mut chain = vec![locale.clone()];
+
+        let parent = LanguageIdentifier::from_parts(locale.language, None, None, &[]);
+        if !chain.contains(&parent) {
+            chain.push(parent);
         }
 
-        let bundle = bundle.unwrap();
+        if !chain.contains(&self.default_locale) {
+            chain.push(self.default_locale.clone());
+        }
 
-        let bundle_message = bundle.get_message(bare);
+        chain
+    }
 
-        if bundle_message.is_none() {
-            return partial.to_string();
-        }
+    /// Returns the name a locale gives itself with its `language-name`
+    /// message, or the language code when it has none. Other locales aren't
+    /// consulted, so a language is never shown in another language's name.
+    pub fn language_name(&self, locale: &LanguageIdentifier) -> String {
+        self.bundles
+            .get(locale)
+            .and_then(|bundle| format_message(bundle, locale, "language-name", None))
+            .unwrap_or_else(|| locale.to_string())
+    }
 
-        let bundle_message = bundle_message.unwrap();
+    pub fn format_error(&self, locale: &LanguageIdentifier, bare: &str, partial: &str) -> String {
+        self.format_error_with(locale, bare, partial, &FluentArgs::new())
+    }
 
-        let mut errors = Vec::new();
+    /// Formats the message `bare` with `args`, falling back through
+    /// [`Locales::fallback_chain`], or returns `partial` when no locale has the
+    /// message. Numeric arguments select plural variants using the rules of
+    /// the locale the message is found in.
+    pub fn format_error_with(
+        &self,
+        locale: &LanguageIdentifier,
+        bare: &str,
+        partial: &str,
+        args: &FluentArgs,
+    ) -> String {
+        for candidate in self.fallback_chain(locale) {
+            let Some(bundle) = self.bundles.get(&candidate) else {
+                continue;
+            };
 
-        if bundle_message.value().is_none() {
-            return partial.to_string();
+            if let Some(formatted) = format_message(bundle, &candidate, bare, Some(args)) {
+                return formatted;
+            }
         }
-        let bundle_message_value = bundle_message.value().unwrap();
 
-        let formatted_pattern = bundle.format_pattern(bundle_message_value, None, &mut errors);
+        partial.to_string()
+    }
+}
 
-        formatted_pattern.to_string()
+/// Formats the message `id` from `bundle`, or returns `None` when the bundle
+/// doesn't have it. Formatting errors, such as a reference to a missing
+/// argument, are logged and the best-effort rendering is returned, so a broken
+/// translation is visible to operators without failing the request.
+fn format_message(
+    bundle: &Bundle,
+    locale: &LanguageIdentifier,
+    id: &str,
+    args: Option<&FluentArgs>,
+) -> Option<String> {
+    let pattern = bundle.get_message(id)?.value()?;
+
+    let mut errors = Vec::new();
+    let formatted = bundle.format_pattern(pattern, args, &mut errors);
+    if !errors.is_empty() {
+        tracing::warn!(
+            locale = %locale,
+            message_id = id,
+            ?errors,
+            "formatting message failed"
+        );
     }
+    Some(formatted.to_string())
 }
 
 #[cfg(feature = "embed")]
@@ -93,7 +146,8 @@ pub mod embed {
         for locale in supported_locales {
             for file in &locale_files {
                 let source_file = format!("{}/{}.ftl", locale.to_string().to_lowercase(), file);
-                let i18n_asset = I18nAssets::get(&source_file).expect("locale file not found");
+                let i18n_asset = I18nAssets::get(&source_file)
+                    .ok_or_else(|| I18nError::LanguageNotFound(locale.to_string()))?;
                 let content = std::str::from_utf8(i18n_asset.data.as_ref())
                     .expect("invalid utf-8 in locale file");
                 locales.add_bundle(locale.clone(), content.to_string())?;
@@ -109,18 +163,28 @@ pub mod reload {
 
     use super::*;
 
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
 
     pub fn populate_locale(
         supported_locales: &Vec<LanguageIdentifier>,
         locales: &mut Locales,
+    ) -> Result<(), I18nError> {
+        let i18n_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("i18n");
+        populate_locale_from(&i18n_dir, supported_locales, locales)
+    }
+
+    pub(crate) fn populate_locale_from(
+        i18n_dir: &Path,
+        supported_locales: &Vec<LanguageIdentifier>,
+        locales: &mut Locales,
     ) -> Result<(), I18nError> {
         let locale_files = vec!["errors"];
 
         for locale in supported_locales {
-            let locale_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
-                .join("i18n")
-                .join(locale.to_string().to_lowercase());
+            let locale_dir = i18n_dir.join(locale.to_string().to_lowercase());
+            if !locale_dir.is_dir() {
+                return Err(I18nError::LanguageNotFound(locale.to_string()));
+            }
             for file in &locale_files {
                 let source_file = locale_dir.join(format!("{}.ftl", file));
                 tracing::info!("Loading locale file: {:?}", source_file);
@@ -142,6 +206,9 @@ pub mod errors {
         #[error("error-i18n-invalid-language Invalid language")]
         InvalidLanguage(),
 
+        #[error("error-i18n-language-not-found Language not found: {0}")]
+        LanguageNotFound(String),
+
         #[error("error-i18n-resource-failed Language resource failed")]
         LanguageResourceFailed(Vec<fluent_syntax::parser::ParserError>),
 
@@ -149,3 +216,247 @@ pub mod errors {
         BundleLoadFailed(Vec<fluent::FluentError>),
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use fluent::FluentArgs;
+    use std::{
+        io::Write,
+        str::FromStr,
+        sync::{Arc, Mutex},
+    };
+    use unic_langid::LanguageIdentifier;
+
+    use super::Locales;
+
+    /// Collects formatted log output so tests can assert on emitted events.
+    #[derive(Clone, Default)]
+    pub(crate) struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl LogBuffer {
+        /// Returns a subscriber writing every event into this buffer.
+        pub(crate) fn subscriber(&self) -> impl tracing::Subscriber + Send + Sync {
+            let logs = self.clone();
+            tracing_subscriber::fmt()
+                .with_writer(move || logs.clone())
+                .with_ansi(false)
+                .finish()
+        }
+
+        /// Returns a subscriber that also writes each span, with its fields,
+        /// into this buffer when it closes.
+        pub(crate) fn span_subscriber(&self) -> impl tracing::Subscriber + Send + Sync {
+            let logs = self.clone();
+            tracing_subscriber::fmt()
+                .with_writer(move || logs.clone())
+                .with_ansi(false)
+                .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+                .finish()
+        }
+
+        pub(crate) fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_format_error_with_args() {
+        let en_us = LanguageIdentifier::from_str("en-us").unwrap();
+        let mut locales = Locales::new(vec![en_us.clone()], en_us.clone());
+        locales
+            .add_bundle(
+                en_us.clone(),
+                "error-web-unsupported-aturi = The AT-URI is not supported by {$server}."
+                    .to_string(),
+            )
+            .unwrap();
+
+        let mut args = FluentArgs::new();
+        args.set("server", "bsky.app");
+
+        assert_eq!(
+            locales.format_error_with(
+                &en_us,
+                "error-web-unsupported-aturi",
+                "error-web-unsupported-aturi Unsupported AT-URI",
+                &args
+            ),
+            "The AT-URI is not supported by \u{2068}bsky.app\u{2069}."
+        );
+
+        assert_eq!(
+            locales.format_error(
+                &en_us,
+                "error-web-missing",
+                "error-web-missing Missing message"
+            ),
+            "error-web-missing Missing message"
+        );
+    }
+
+    #[test]
+    fn test_format_error_fallback_chain() {
+        let en_us = LanguageIdentifier::from_str("en-us").unwrap();
+        let fr = LanguageIdentifier::from_str("fr").unwrap();
+        let fr_fr = LanguageIdentifier::from_str("fr-fr").unwrap();
+        let mut locales = Locales::new(
+            vec![en_us.clone(), fr.clone(), fr_fr.clone()],
+            en_us.clone(),
+        );
+        locales
+            .add_bundle(
+                en_us.clone(),
+                "error-web-invalid-aturi = The AT-URI is not valid.\n\
+                 error-web-unsupported-aturi = The AT-URI is not supported."
+                    .to_string(),
+            )
+            .unwrap();
+        locales
+            .add_bundle(
+                fr.clone(),
+                "error-web-unsupported-aturi = L'AT-URI n'est pas pris en charge.".to_string(),
+            )
+            .unwrap();
+        locales
+            .add_bundle(
+                fr_fr.clone(),
+                "error-i18n-invalid-language = La langue n'est pas prise en charge.".to_string(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            locales.format_error(
+                &fr_fr,
+                "error-web-unsupported-aturi",
+                "error-web-unsupported-aturi Unsupported AT-URI"
+            ),
+            "L'AT-URI n'est pas pris en charge."
+        );
+        assert_eq!(
+            locales.format_error(
+                &fr_fr,
+                "error-web-invalid-aturi",
+                "error-web-invalid-aturi Invalid AT-URI"
+            ),
+            "The AT-URI is not valid."
+        );
+    }
+
+    #[cfg(feature = "reload")]
+    #[test]
+    fn test_populate_added_locale() {
+        use super::{errors::I18nError, reload::populate_locale_from};
+
+        let i18n_dir = std::env::temp_dir().join(format!("hopper-i18n-{}", std::process::id()));
+        std::fs::create_dir_all(i18n_dir.join("fr-fr")).unwrap();
+        std::fs::write(
+            i18n_dir.join("fr-fr").join("errors.ftl"),
+            "error-web-invalid-aturi = L'AT-URI n'est pas valide.",
+        )
+        .unwrap();
+
+        let fr_fr = LanguageIdentifier::from_str("fr-fr").unwrap();
+        let de_de = LanguageIdentifier::from_str("de-de").unwrap();
+
+        let mut locales = Locales::new(vec![fr_fr.clone()], fr_fr.clone());
+        let populated = populate_locale_from(&i18n_dir, &vec![fr_fr.clone()], &mut locales);
+
+        let mut missing = Locales::new(vec![de_de.clone()], de_de.clone());
+        let missing_populated = populate_locale_from(&i18n_dir, &vec![de_de], &mut missing);
+
+        std::fs::remove_dir_all(&i18n_dir).unwrap();
+
+        assert!(populated.is_ok());
+        assert_eq!(
+            locales.format_error(
+                &fr_fr,
+                "error-web-invalid-aturi",
+                "error-web-invalid-aturi Invalid AT-URI"
+            ),
+            "L'AT-URI n'est pas valide."
+        );
+        assert!(matches!(
+            missing_populated,
+            Err(I18nError::LanguageNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_format_error_with_plural() {
+        let en_us = LanguageIdentifier::from_str("en-us").unwrap();
+        let mut locales = Locales::new(vec![en_us.clone()], en_us.clone());
+        locales
+            .add_bundle(
+                en_us.clone(),
+                "error-web-servers-tried =\n    { $count ->\n        [one] Tried one server.\n       *[other] Tried { $count } servers.\n    }"
+                    .to_string(),
+            )
+            .unwrap();
+
+        for (count, expected) in [
+            (1, "Tried one server."),
+            (3, "Tried \u{2068}3\u{2069} servers."),
+            (0, "Tried \u{2068}0\u{2069} servers."),
+        ] {
+            let mut args = FluentArgs::new();
+            args.set("count", count);
+            assert_eq!(
+                locales.format_error_with(
+                    &en_us,
+                    "error-web-servers-tried",
+                    "error-web-servers-tried Servers tried",
+                    &args
+                ),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_error_logs_errors() {
+        let en_us = LanguageIdentifier::from_str("en-us").unwrap();
+        let mut locales = Locales::new(vec![en_us.clone()], en_us.clone());
+        locales
+            .add_bundle(
+                en_us.clone(),
+                "error-web-unsupported-aturi = The AT-URI is not supported by {$server}."
+                    .to_string(),
+            )
+            .unwrap();
+
+        let logs = LogBuffer::default();
+        let formatted = tracing::subscriber::with_default(logs.subscriber(), || {
+            locales.format_error(
+                &en_us,
+                "error-web-unsupported-aturi",
+                "error-web-unsupported-aturi Unsupported AT-URI",
+            )
+        });
+
+        // The best-effort rendering is still returned.
+        assert_eq!(
+            formatted,
+            "The AT-URI is not supported by \u{2068}{$server}\u{2069}."
+        );
+
+        let logs = logs.contents();
+        assert!(logs.contains("WARN"), "{}", logs);
+        assert!(logs.contains("formatting message failed"), "{}", logs);
+        assert!(logs.contains("locale=en-US"), "{}", logs);
+        assert!(
+            logs.contains("message_id=\"error-web-unsupported-aturi\""),
+            "{}",
+            logs
+        );
+    }
+}
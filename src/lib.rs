@@ -1,7 +1,19 @@
 pub mod cache;
+#[cfg(feature = "redis")]
+pub mod cache_redis;
 pub mod config;
+pub mod did;
 pub(crate) mod errors;
+pub mod fetch;
 pub mod http;
 pub mod i18n;
+pub mod logging;
 pub(crate) mod model;
+pub mod prewarm;
+pub mod resolve;
+#[cfg(feature = "otel")]
+pub mod telemetry;
 pub mod webhostmeta;
+
+pub use model::AtUri;
+pub use resolve::{resolve, Resolution};
@@ -0,0 +1,100 @@
+use anyhow::anyhow;
+use tracing::Subscriber;
+use tracing_subscriber::{fmt::MakeWriter, registry::LookupSpan, Layer};
+
+/// How log events are written. Read from `HOPPER_LOG_FORMAT` before the rest
+/// of the configuration, so that configuration errors are logged in it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Multi-line, human readable output.
+    #[default]
+    Pretty,
+
+    /// One JSON object per event, for log aggregators.
+    Json,
+}
+
+impl TryFrom<String> for LogFormat {
+    type Error = anyhow::Error;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "" | "pretty" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!(
+                "HOPPER_LOG_FORMAT must be pretty or json, got {}",
+                value
+            )),
+        }
+    }
+}
+
+/// Returns the fmt layer for `format`, writing events to `writer`. JSON
+/// events carry their fields flattened, along with the current span and its
+/// parents.
+pub fn fmt_layer<S, W>(format: LogFormat, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    match format {
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Json => layer.json().flatten_event(true).boxed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::prelude::*;
+
+    use super::{fmt_layer, LogFormat};
+    use crate::i18n::tests::LogBuffer;
+
+    #[test]
+    fn test_log_format() {
+        assert_eq!(
+            LogFormat::try_from(String::new()).unwrap(),
+            LogFormat::Pretty
+        );
+        assert_eq!(
+            LogFormat::try_from("json".to_string()).unwrap(),
+            LogFormat::Json
+        );
+        assert!(LogFormat::try_from("xml".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let logs = LogBuffer::default();
+        let subscriber = tracing_subscriber::registry().with(fmt_layer(LogFormat::Json, {
+            let logs = logs.clone();
+            move || logs.clone()
+        }));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("resolve_aturi", aturi = "at://alice.example");
+            let _entered = span.enter();
+            tracing::info!(
+                target: "hopper::access",
+                outcome = "resolved",
+                latency_ms = 3,
+                "resolution completed"
+            );
+            tracing::warn!("second event");
+        });
+
+        let lines = logs
+            .contents()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["target"], "hopper::access");
+        assert_eq!(lines[0]["message"], "resolution completed");
+        assert_eq!(lines[0]["outcome"], "resolved");
+        assert_eq!(lines[0]["latency_ms"], 3);
+        assert_eq!(lines[0]["span"]["aturi"], "at://alice.example");
+        assert_eq!(lines[1]["level"], "WARN");
+    }
+}
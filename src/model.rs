@@ -1,11 +1,128 @@
-#[derive(Debug, Clone)]
-pub(crate) struct AtUri {
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+use urlencoding::{decode, encode};
+
+/// A validated AT-URI: an identity, optionally followed by a collection and
+/// a record key, and a fragment pointing within the record.
+///
+/// It serializes as its components along with the flat `aturi` string, and
+/// deserializes from the components, which are validated as an AT-URI.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "AtUriFields")]
+pub struct AtUri {
     pub(crate) identity: String,
     pub(crate) collection: Option<String>,
     pub(crate) rkey: Option<String>,
+    pub(crate) fragment: Option<String>,
+}
+
+impl AtUri {
+    /// The handle or DID the AT-URI belongs to. Internationalized handles are
+    /// in their punycode form.
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    pub fn collection(&self) -> Option<&str> {
+        self.collection.as_deref()
+    }
+
+    pub fn rkey(&self) -> Option<&str> {
+        self.rkey.as_deref()
+    }
+
+    /// The fragment, without the leading `#`.
+    pub fn fragment(&self) -> Option<&str> {
+        self.fragment.as_deref()
+    }
+
+    /// Returns true when the AT-URI names a single record.
+    pub fn is_record(&self) -> bool {
+        self.rkey.is_some()
+    }
+
+    /// Returns true when the AT-URI names a collection without a record.
+    pub fn is_collection(&self) -> bool {
+        self.collection.is_some() && self.rkey.is_none()
+    }
 }
 
-pub(crate) fn validate_aturi<S: Into<String>>(aturi: S) -> Option<AtUri> {
+impl fmt::Display for AtUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at://{}", self.identity)?;
+        if let Some(collection) = &self.collection {
+            write!(f, "/{}", collection)?;
+        }
+        if let Some(rkey) = &self.rkey {
+            write!(f, "/{}", rkey)?;
+        }
+        if let Some(fragment) = &self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for AtUri {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AtUri", 5)?;
+        state.serialize_field("identity", &self.identity)?;
+        state.serialize_field("collection", &self.collection)?;
+        state.serialize_field("rkey", &self.rkey)?;
+        state.serialize_field("fragment", &self.fragment)?;
+        state.serialize_field("aturi", &self.to_string())?;
+        state.end()
+    }
+}
+
+/// The components of a deserialized AT-URI, before validation. A flat
+/// `aturi` field is ignored.
+#[derive(Deserialize)]
+struct AtUriFields {
+    identity: String,
+    #[serde(default)]
+    collection: Option<String>,
+    #[serde(default)]
+    rkey: Option<String>,
+    #[serde(default)]
+    fragment: Option<String>,
+}
+
+impl TryFrom<AtUriFields> for AtUri {
+    type Error = &'static str;
+    fn try_from(fields: AtUriFields) -> Result<Self, Self::Error> {
+        if fields.collection.is_none() && fields.rkey.is_some() {
+            return Err("an AT-URI with a record key needs a collection");
+        }
+        let aturi = AtUri {
+            identity: fields.identity,
+            collection: fields.collection,
+            rkey: fields.rkey,
+            fragment: fields.fragment,
+        };
+        // Components that validate to something else, such as an unnormalized
+        // handle or one containing a separator, are rejected. Reserved suffixes
+        // are a resolution policy rather than syntax, so none apply here.
+        let reserved_suffixes: [&str; 0] = [];
+        match validate_aturi(aturi.to_string(), &HashMap::new(), &reserved_suffixes) {
+            Some(validated) if validated == aturi => Ok(aturi),
+            _ => Err("invalid AT-URI"),
+        }
+    }
+}
+
+/// Validates an AT-URI, expanding a collection segment that isn't already a
+/// valid NSID through `aliases` (e.g. `post` to `app.bsky.feed.post`).
+/// Identities whose host ends with one of `reserved_suffixes` are rejected.
+pub(crate) fn validate_aturi<S: Into<String>, R: AsRef<str>>(
+    aturi: S,
+    aliases: &HashMap<String, String>,
+    reserved_suffixes: &[R],
+) -> Option<AtUri> {
     let aturi = aturi.into();
     let aturi = aturi.trim();
 
@@ -15,16 +132,40 @@ pub(crate) fn validate_aturi<S: Into<String>>(aturi: S) -> Option<AtUri> {
         aturi
     };
 
-    let stripped = aturi.strip_prefix("at://");
-    if stripped.is_none() {
-        return None;
+    let stripped = aturi.strip_prefix("at://")?;
+
+    let (stripped, fragment) = match stripped.split_once('#') {
+        Some((path, fragment)) if is_valid_fragment(fragment) => (path, Some(fragment)),
+        Some(_) => return None,
+        None => (stripped, None),
+    };
+
+    let mut parts = stripped.split('/').collect::<Vec<&str>>();
+
+    if let Some(collection) = parts.get_mut(1) {
+        if !is_valid_nsid(collection) {
+            if let Some(nsid) = aliases.get(*collection) {
+                *collection = nsid.as_str();
+            }
+        }
     }
 
-    let stripped = stripped.unwrap();
+    // `split` always yields a first segment, but it is empty for input such
+    // as `at://` or `at:///app.bsky.feed.post/abc`.
+    let identity = parts
+        .first()
+        .copied()
+        .filter(|identity| !identity.is_empty())?;
 
-    let parts = stripped.split('/').collect::<Vec<&str>>();
+    // Internationalized handles are validated, templated and cached in their
+    // punycode form.
+    let identity = if identity.is_ascii() || identity.starts_with("did:") {
+        identity.to_string()
+    } else {
+        idna::domain_to_ascii(identity).ok()?
+    };
 
-    if !parts.is_empty() && !is_valid_identity(parts[0]) {
+    if !is_valid_identity(&identity, reserved_suffixes) {
         return None;
     }
     if parts.len() > 1 && !is_valid_nsid(parts[1]) {
@@ -33,12 +174,27 @@ pub(crate) fn validate_aturi<S: Into<String>>(aturi: S) -> Option<AtUri> {
     if parts.len() > 3 {
         return None;
     }
+    // A trailing slash after the collection would leave an empty record key,
+    // which templates would substitute as an empty path segment.
+    if parts.get(2).is_some_and(|rkey| rkey.is_empty()) {
+        return None;
+    }
 
-    return Some(AtUri {
-        identity: parts[0].to_string(),
+    Some(AtUri {
+        identity,
         collection: parts.get(1).map(|s| s.to_string()),
         rkey: parts.get(2).map(|s| s.to_string()),
-    });
+        fragment: fragment.map(str::to_string),
+    })
+}
+
+/// Returns true for a non-empty fragment of printable ASCII characters that
+/// can appear in a URL fragment unescaped, or as percent-escapes.
+fn is_valid_fragment(fragment: &str) -> bool {
+    !fragment.is_empty()
+        && fragment
+            .bytes()
+            .all(|byte| byte.is_ascii_graphic() && !b"#\"<>\\^`{|}".contains(&byte))
 }
 
 pub(crate) fn is_valid_nsid(nsid: &str) -> bool {
@@ -58,7 +214,64 @@ pub(crate) fn is_valid_nsid(nsid: &str) -> bool {
         || nsid.len() > 253)
 }
 
-pub(crate) fn is_valid_hostname(hostname: &str) -> bool {
+/// Hostname suffixes of names that don't resolve on the public internet.
+pub const DEFAULT_RESERVED_SUFFIXES: [&str; 4] = [".localhost", ".internal", ".arpa", ".local"];
+
+/// Returns true for a domain label of the atproto grammar: 1 to 63 ASCII
+/// letters, digits and hyphens, not starting or ending with a hyphen.
+fn is_strict_label(label: &str) -> bool {
+    (1..=63).contains(&label.len())
+        && label
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+}
+
+/// Returns true when `nsid` matches the atproto NSID grammar exactly: a
+/// lowercase domain authority of at least two labels, in reverse order so the
+/// first is a TLD starting with a letter, followed by a name of 1 to 63
+/// letters and digits starting with a letter.
+pub(crate) fn is_strict_nsid(nsid: &str) -> bool {
+    let Some((authority, name)) = nsid.rsplit_once('.') else {
+        return false;
+    };
+    let labels = authority.split('.').collect::<Vec<&str>>();
+    nsid.len() <= 317
+        && authority.len() <= 253
+        && labels.len() >= 2
+        && labels.iter().all(|label| {
+            is_strict_label(label) && !label.bytes().any(|byte| byte.is_ascii_uppercase())
+        })
+        && labels[0].starts_with(|c: char| c.is_ascii_alphabetic())
+        && (1..=63).contains(&name.len())
+        && name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.bytes().all(|byte| byte.is_ascii_alphanumeric())
+}
+
+/// Returns true when `handle` matches the atproto handle grammar exactly: at
+/// most 253 characters of at least two domain labels, with a TLD starting
+/// with a letter. Handles are case-insensitive, so uppercase is allowed.
+pub(crate) fn is_strict_handle(handle: &str) -> bool {
+    let labels = handle.split('.').collect::<Vec<&str>>();
+    handle.len() <= 253
+        && labels.len() >= 2
+        && labels.iter().all(|label| is_strict_label(label))
+        && labels
+            .last()
+            .is_some_and(|tld| tld.starts_with(|c: char| c.is_ascii_alphabetic()))
+}
+
+/// Returns true when `aturi`, already accepted by [`validate_aturi`], also
+/// meets the atproto handle and NSID grammar exactly.
+pub(crate) fn is_strict_aturi(aturi: &AtUri) -> bool {
+    (aturi.identity.starts_with("did:") || is_strict_handle(&aturi.identity))
+        && aturi.collection.as_deref().is_none_or(is_strict_nsid)
+}
+
+/// Returns true when `hostname` is syntactically valid and doesn't end with
+/// one of `reserved_suffixes`.
+pub(crate) fn is_valid_hostname<S: AsRef<str>>(hostname: &str, reserved_suffixes: &[S]) -> bool {
     fn is_valid_char(byte: u8) -> bool {
         byte.is_ascii_lowercase()
             || byte.is_ascii_uppercase()
@@ -66,10 +279,9 @@ pub(crate) fn is_valid_hostname(hostname: &str) -> bool {
             || byte == b'-'
             || byte == b'.'
     }
-    !(hostname.ends_with(".localhost")
-        || hostname.ends_with(".internal")
-        || hostname.ends_with(".arpa")
-        || hostname.ends_with(".local")
+    !(reserved_suffixes
+        .iter()
+        .any(|suffix| hostname.ends_with(suffix.as_ref()))
         || hostname.bytes().any(|byte| !is_valid_char(byte))
         || hostname.split('.').any(|label| {
             label.is_empty() || label.len() > 63 || label.starts_with('-') || label.ends_with('-')
@@ -84,7 +296,16 @@ enum InputType {
     Web(String),
 }
 
-pub(crate) fn is_valid_identity(identity: &str) -> bool {
+/// Returns true for an IPv4 address or a bracketed IPv6 address.
+pub(crate) fn is_ip_literal(value: &str) -> bool {
+    value.parse::<Ipv4Addr>().is_ok()
+        || value
+            .strip_prefix('[')
+            .and_then(|value| value.strip_suffix(']'))
+            .is_some_and(|value| value.parse::<Ipv6Addr>().is_ok())
+}
+
+pub(crate) fn is_valid_identity<S: AsRef<str>>(identity: &str, reserved_suffixes: &[S]) -> bool {
     let identity = if identity.starts_with("did:web:") {
         InputType::Web(identity.to_string())
     } else if identity.starts_with("did:plc:") {
@@ -94,7 +315,15 @@ pub(crate) fn is_valid_identity(identity: &str) -> bool {
     };
 
     match identity {
-        InputType::Handle(handle) => is_valid_hostname(&handle) && handle.chars().any(|c| c == '.'),
+        // The TLD of a handle can't be all digits, which would make it
+        // ambiguous with an IP address, and IP literals are never handles.
+        InputType::Handle(handle) => {
+            !is_ip_literal(&handle)
+                && is_valid_hostname(&handle, reserved_suffixes)
+                && handle
+                    .rsplit_once('.')
+                    .is_some_and(|(_, tld)| !tld.bytes().all(|byte| byte.is_ascii_digit()))
+        }
         InputType::Plc(did) => did
             .strip_prefix("did:plc:")
             .is_some_and(|remaining| remaining.len() == 24),
@@ -106,9 +335,641 @@ pub(crate) fn is_valid_identity(identity: &str) -> bool {
             parts.is_some_and(|inner_parts| {
                 !inner_parts.is_empty()
                     && inner_parts.first().is_some_and(|hostname| {
-                        is_valid_hostname(hostname) && hostname.chars().any(|c| c == '.')
+                        is_valid_hostname(hostname, reserved_suffixes)
+                            && hostname.chars().any(|c| c == '.')
                     })
             })
         }
     }
 }
+
+/// Encodes an identity for a path placeholder. The colons that separate the
+/// method-specific segments of a did:web become path separators, following
+/// did:web path semantics, so `did:web:example.com:user:alice` becomes
+/// `did%3Aweb%3Aexample.com/user/alice`. Segments are decoded before encoding
+/// so a `%3A` port separator isn't encoded twice. Cache keys use the raw
+/// identity.
+fn identity_path(identity: &str) -> String {
+    let Some(method_specific_id) = identity.strip_prefix("did:web:") else {
+        return encode(identity).into_owned();
+    };
+    let segments = method_specific_id
+        .split(':')
+        .map(|segment| {
+            let segment = decode(segment).map_or(segment.into(), |decoded| decoded);
+            encode(&segment).into_owned()
+        })
+        .collect::<Vec<String>>();
+    format!("{}{}", encode("did:web:"), segments.join("/"))
+}
+
+/// Reverses [`identity_path`], returning the identity an `{identity}` value
+/// was substituted from. did:web segments keep their escaping, as a `%3A`
+/// port separator is part of the DID.
+pub(crate) fn identity_from_path(path: &str) -> Option<String> {
+    if let Some(segments) = path.strip_prefix(encode("did:web:").as_ref()) {
+        return Some(format!("did:web:{}", segments.replace('/', ":")));
+    }
+    decode(path).ok().map(|identity| identity.into_owned())
+}
+
+/// Substitutes the components of `aturi` into a link template whose
+/// collection property is `collection_prop`, or returns why the template
+/// doesn't apply to the AT-URI.
+///
+/// A link without a collection property, or with the collection `identity`,
+/// applies to identity-only AT-URIs. A template with a collection property
+/// but without `{rkey}` is a collection listing, which doesn't apply to
+/// records.
+///
+/// The identity has three placeholders, differing in how it is escaped:
+///
+/// - `{identity}` is escaped as a path, with did:web segments as separators.
+/// - `{identity_raw}` is inserted as it appears in the AT-URI.
+/// - `{identity_query}` is escaped as a query parameter value.
+pub(crate) fn apply_template(
+    template: &str,
+    aturi: &AtUri,
+    collection_prop: Option<&str>,
+) -> Result<String, &'static str> {
+    if aturi.collection.as_deref().unwrap_or("identity") != collection_prop.unwrap_or("identity") {
+        return Err("collection mismatch");
+    }
+
+    // A template referencing a component the AT-URI doesn't have would
+    // produce a URL with literal braces, so it doesn't match.
+    if (template.contains("{collection}") && aturi.collection.is_none())
+        || (template.contains("{rkey}") && aturi.rkey.is_none())
+    {
+        return Err("template needs a missing component");
+    }
+
+    if aturi.is_record() && collection_prop.is_some() && !template.contains("{rkey}") {
+        return Err("collection listing template for a record");
+    }
+
+    // Substituted values are encoded as path segments. Encoding also escapes
+    // braces, so a value can't inject another placeholder. Validated
+    // identities never contain braces, so the raw form can't either.
+    let mut result = template
+        .replace("{identity}", &identity_path(&aturi.identity))
+        .replace("{identity_raw}", &aturi.identity)
+        .replace(
+            "{identity_query}",
+            &url::form_urlencoded::byte_serialize(aturi.identity.as_bytes()).collect::<String>(),
+        );
+    if let Some(collection) = &aturi.collection {
+        result = result.replace("{collection}", &encode(collection));
+    }
+    if let Some(rkey) = &aturi.rkey {
+        result = result.replace("{rkey}", &encode(rkey));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use proptest::strategy::Strategy;
+
+    use super::{
+        apply_template, identity_path, is_ip_literal, is_strict_handle, is_strict_nsid,
+        is_valid_hostname, is_valid_identity, is_valid_nsid, validate_aturi, AtUri,
+        DEFAULT_RESERVED_SUFFIXES,
+    };
+
+    #[test]
+    fn test_collection_aliases() {
+        let aliases = HashMap::from([
+            ("post".to_string(), "app.bsky.feed.post".to_string()),
+            (
+                "app.bsky.feed.like".to_string(),
+                "app.bsky.feed.post".to_string(),
+            ),
+        ]);
+
+        let aturi = validate_aturi(
+            "at://alice.test/post/abc",
+            &aliases,
+            &DEFAULT_RESERVED_SUFFIXES,
+        )
+        .unwrap();
+        assert_eq!(aturi.collection.as_deref(), Some("app.bsky.feed.post"));
+        assert_eq!(aturi.rkey.as_deref(), Some("abc"));
+
+        // Valid NSIDs are never expanded, even when they collide with an alias.
+        let aturi = validate_aturi(
+            "at://alice.test/app.bsky.feed.like/abc",
+            &aliases,
+            &DEFAULT_RESERVED_SUFFIXES,
+        )
+        .unwrap();
+        assert_eq!(aturi.collection.as_deref(), Some("app.bsky.feed.like"));
+
+        assert!(validate_aturi(
+            "at://alice.test/event/abc",
+            &aliases,
+            &DEFAULT_RESERVED_SUFFIXES
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_apply_template() {
+        let aturi = validate_aturi(
+            "at://alice.example/app.bsky.feed.post/a b",
+            &HashMap::new(),
+            &DEFAULT_RESERVED_SUFFIXES,
+        );
+        let aturi = aturi.unwrap();
+        assert_eq!(
+            apply_template(
+                "https://example.com/{identity}/{collection}/{rkey}",
+                &aturi,
+                Some("app.bsky.feed.post")
+            ),
+            Ok("https://example.com/alice.example/app.bsky.feed.post/a%20b".to_string())
+        );
+        assert_eq!(
+            apply_template(
+                "https://example.com/{identity}/{rkey}",
+                &aturi,
+                Some("app.bsky.feed.like")
+            ),
+            Err("collection mismatch")
+        );
+        assert_eq!(
+            apply_template("https://example.com/{identity}", &aturi, None),
+            Err("collection mismatch")
+        );
+        assert_eq!(
+            apply_template(
+                "https://example.com/{identity}/{collection}",
+                &aturi,
+                Some("app.bsky.feed.post")
+            ),
+            Err("collection listing template for a record")
+        );
+
+        let aturi = validate_aturi(
+            "at://alice.example",
+            &HashMap::new(),
+            &DEFAULT_RESERVED_SUFFIXES,
+        )
+        .unwrap();
+        assert_eq!(
+            apply_template("https://example.com/{identity}", &aturi, None),
+            Ok("https://example.com/alice.example".to_string())
+        );
+        assert_eq!(
+            apply_template("https://example.com/{identity}/{rkey}", &aturi, None),
+            Err("template needs a missing component")
+        );
+    }
+
+    #[test]
+    fn test_is_valid_hostname_reserved_suffixes() {
+        assert!(is_valid_hostname("bsky.app", &DEFAULT_RESERVED_SUFFIXES));
+        assert!(!is_valid_hostname(
+            "hopper.internal",
+            &DEFAULT_RESERVED_SUFFIXES
+        ));
+        assert!(!is_valid_hostname(
+            "printer.local",
+            &DEFAULT_RESERVED_SUFFIXES
+        ));
+
+        // Operators can allow .internal and block other suffixes.
+        let reserved_suffixes = vec![".local".to_string(), ".corp".to_string()];
+        assert!(is_valid_hostname("hopper.internal", &reserved_suffixes));
+        assert!(!is_valid_hostname("printer.local", &reserved_suffixes));
+        assert!(!is_valid_hostname("wiki.corp", &reserved_suffixes));
+
+        let reserved_suffixes: [&str; 0] = [];
+        assert!(is_valid_hostname("printer.local", &reserved_suffixes));
+    }
+
+    #[test]
+    fn test_is_valid_identity_numeric_tld() {
+        assert!(is_valid_identity("alice.test", &DEFAULT_RESERVED_SUFFIXES));
+        assert!(is_valid_identity("123.example", &DEFAULT_RESERVED_SUFFIXES));
+        assert!(!is_valid_identity("1.2.3.4", &DEFAULT_RESERVED_SUFFIXES));
+        assert!(!is_valid_identity("foo.123", &DEFAULT_RESERVED_SUFFIXES));
+        assert!(!is_valid_identity("alice", &DEFAULT_RESERVED_SUFFIXES));
+    }
+
+    #[test]
+    fn test_is_valid_identity_ip_literal() {
+        assert!(is_ip_literal("127.0.0.1"));
+        assert!(is_ip_literal("[::1]"));
+        assert!(is_ip_literal("[2001:db8::1]"));
+        assert!(!is_ip_literal("2001:db8::1"));
+        assert!(!is_ip_literal("alice.example"));
+
+        assert!(!is_valid_identity("127.0.0.1", &DEFAULT_RESERVED_SUFFIXES));
+        assert!(!is_valid_identity(
+            "[2001:db8::1]",
+            &DEFAULT_RESERVED_SUFFIXES
+        ));
+        assert!(is_valid_identity(
+            "alice.example",
+            &DEFAULT_RESERVED_SUFFIXES
+        ));
+    }
+
+    #[test]
+    fn test_validate_aturi_idn() {
+        let aturi = validate_aturi(
+            "at://café.example/app.bsky.feed.post/abc",
+            &HashMap::new(),
+            &DEFAULT_RESERVED_SUFFIXES,
+        );
+        assert_eq!(aturi.unwrap().identity, "xn--caf-dma.example");
+
+        let aturi = validate_aturi(
+            "at://xn--caf-dma.example",
+            &HashMap::new(),
+            &DEFAULT_RESERVED_SUFFIXES,
+        );
+        assert_eq!(aturi.unwrap().identity, "xn--caf-dma.example");
+
+        assert!(validate_aturi(
+            "at://a\u{200d}b.example",
+            &HashMap::new(),
+            &DEFAULT_RESERVED_SUFFIXES
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_aturi_accessors() {
+        let record = validate_aturi(
+            "at://alice.example/app.bsky.feed.post/abc",
+            &HashMap::new(),
+            &DEFAULT_RESERVED_SUFFIXES,
+        )
+        .unwrap();
+        assert_eq!(record.identity(), "alice.example");
+        assert_eq!(record.collection(), Some("app.bsky.feed.post"));
+        assert_eq!(record.rkey(), Some("abc"));
+        assert!(record.is_record());
+        assert!(!record.is_collection());
+
+        let collection = validate_aturi(
+            "at://alice.example/app.bsky.feed.post",
+            &HashMap::new(),
+            &DEFAULT_RESERVED_SUFFIXES,
+        )
+        .unwrap();
+        assert!(collection.is_collection());
+        assert!(!collection.is_record());
+
+        let identity = validate_aturi(
+            "at://alice.example",
+            &HashMap::new(),
+            &DEFAULT_RESERVED_SUFFIXES,
+        )
+        .unwrap();
+        assert!(!identity.is_collection());
+        assert!(!identity.is_record());
+
+        // Equal AT-URIs can be used as map keys.
+        let aliases = HashMap::from([("post".to_string(), "app.bsky.feed.post".to_string())]);
+        let aliased = validate_aturi(
+            "at://alice.example/post/abc",
+            &aliases,
+            &DEFAULT_RESERVED_SUFFIXES,
+        )
+        .unwrap();
+        let expanded = validate_aturi(
+            "at://alice.example/app.bsky.feed.post/abc",
+            &aliases,
+            &DEFAULT_RESERVED_SUFFIXES,
+        )
+        .unwrap();
+        assert_eq!(aliased, expanded);
+        let counts = HashMap::from([(aliased, 1)]);
+        assert_eq!(counts.get(&expanded), Some(&1));
+        assert_ne!(record, identity);
+    }
+
+    #[test]
+    fn test_identity_path() {
+        assert_eq!(identity_path("alice.example"), "alice.example");
+        assert_eq!(
+            identity_path("did:plc:tgudj2fjm77pzkuawquqhsxm"),
+            "did%3Aplc%3Atgudj2fjm77pzkuawquqhsxm"
+        );
+        assert_eq!(
+            identity_path("did:web:example.com"),
+            "did%3Aweb%3Aexample.com"
+        );
+        assert_eq!(
+            identity_path("did:web:localhost%3A3000:user"),
+            "did%3Aweb%3Alocalhost%3A3000/user"
+        );
+
+        let aturi = validate_aturi(
+            "at://did:web:example.com:user:alice",
+            &HashMap::new(),
+            &DEFAULT_RESERVED_SUFFIXES,
+        );
+        let aturi = aturi.unwrap();
+        // The cached identity keeps its raw form.
+        assert_eq!(aturi.identity, "did:web:example.com:user:alice");
+        assert_eq!(
+            apply_template("https://example.com/profile/{identity}", &aturi, None),
+            Ok("https://example.com/profile/did%3Aweb%3Aexample.com/user/alice".to_string())
+        );
+    }
+
+    #[test]
+    fn test_apply_template_identity_variants() {
+        let aturi = validate_aturi(
+            "at://did:web:example.com:user:alice",
+            &HashMap::new(),
+            &DEFAULT_RESERVED_SUFFIXES,
+        );
+        let aturi = aturi.unwrap();
+        assert_eq!(
+            apply_template("https://example.com/{identity}", &aturi, None),
+            Ok("https://example.com/did%3Aweb%3Aexample.com/user/alice".to_string())
+        );
+        assert_eq!(
+            apply_template("https://example.com/{identity_raw}", &aturi, None),
+            Ok("https://example.com/did:web:example.com:user:alice".to_string())
+        );
+        assert_eq!(
+            apply_template("https://example.com/?actor={identity_query}", &aturi, None),
+            Ok("https://example.com/?actor=did%3Aweb%3Aexample.com%3Auser%3Aalice".to_string())
+        );
+
+        let aturi = validate_aturi(
+            "at://alice.example",
+            &HashMap::new(),
+            &DEFAULT_RESERVED_SUFFIXES,
+        )
+        .unwrap();
+        assert_eq!(
+            apply_template(
+                "https://example.com/{identity_raw}?q={identity_query}",
+                &aturi,
+                None
+            ),
+            Ok("https://example.com/alice.example?q=alice.example".to_string())
+        );
+    }
+
+    #[test]
+    fn test_aturi_serde() {
+        let aturi = validate_aturi(
+            "at://did:plc:ewvi7nxzyoun6zhxrhs64oiz/app.bsky.feed.post/3l3q2kavqw22f",
+            &HashMap::new(),
+            &DEFAULT_RESERVED_SUFFIXES,
+        )
+        .unwrap();
+        let value = serde_json::to_value(&aturi).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "identity": "did:plc:ewvi7nxzyoun6zhxrhs64oiz",
+                "collection": "app.bsky.feed.post",
+                "rkey": "3l3q2kavqw22f",
+                "fragment": null,
+                "aturi": "at://did:plc:ewvi7nxzyoun6zhxrhs64oiz/app.bsky.feed.post/3l3q2kavqw22f",
+            })
+        );
+        assert_eq!(serde_json::from_value::<AtUri>(value).unwrap(), aturi);
+
+        let identity = serde_json::from_str::<AtUri>(r#"{"identity": "alice.test"}"#).unwrap();
+        assert_eq!(identity.to_string(), "at://alice.test");
+        assert_eq!(
+            serde_json::to_value(&identity).unwrap()["collection"],
+            serde_json::Value::Null
+        );
+
+        for invalid in [
+            r#"{"identity": ""}"#,
+            r#"{"identity": "alice.test/app.bsky.feed.post"}"#,
+            r#"{"identity": "alice.test", "rkey": "abc"}"#,
+            r#"{"identity": "alice.test", "collection": "not an nsid"}"#,
+        ] {
+            assert!(
+                serde_json::from_str::<AtUri>(invalid).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_aturi_empty_identity() {
+        let aliases = HashMap::new();
+        assert_eq!(
+            validate_aturi(
+                "at:///app.bsky.feed.post/abc",
+                &aliases,
+                &DEFAULT_RESERVED_SUFFIXES
+            ),
+            None
+        );
+        assert_eq!(
+            validate_aturi("at://", &aliases, &DEFAULT_RESERVED_SUFFIXES),
+            None
+        );
+        assert_eq!(
+            validate_aturi("at:///", &aliases, &DEFAULT_RESERVED_SUFFIXES),
+            None
+        );
+        assert_eq!(
+            validate_aturi("web+at://", &aliases, &DEFAULT_RESERVED_SUFFIXES),
+            None
+        );
+        assert_eq!(
+            validate_aturi(
+                "at://alice.test/app.bsky.feed.post/",
+                &aliases,
+                &DEFAULT_RESERVED_SUFFIXES
+            ),
+            None
+        );
+
+        let aturi =
+            validate_aturi("at://alice.test", &aliases, &DEFAULT_RESERVED_SUFFIXES).unwrap();
+        assert_eq!(aturi.identity(), "alice.test");
+        assert_eq!(aturi.collection(), None);
+        assert_eq!(aturi.rkey(), None);
+    }
+
+    #[test]
+    fn test_validate_aturi_fragment() {
+        let aliases = HashMap::new();
+        let aturi = validate_aturi(
+            "at://alice.test/app.bsky.feed.post/abc#main",
+            &aliases,
+            &DEFAULT_RESERVED_SUFFIXES,
+        )
+        .unwrap();
+        assert_eq!(aturi.rkey(), Some("abc"));
+        assert_eq!(aturi.fragment(), Some("main"));
+        assert_eq!(
+            aturi.to_string(),
+            "at://alice.test/app.bsky.feed.post/abc#main"
+        );
+
+        let aturi = validate_aturi(
+            "at://alice.test#/displayName",
+            &aliases,
+            &DEFAULT_RESERVED_SUFFIXES,
+        )
+        .unwrap();
+        assert_eq!(aturi.identity(), "alice.test");
+        assert_eq!(aturi.fragment(), Some("/displayName"));
+
+        assert_eq!(
+            validate_aturi(
+                "at://alice.test/app.bsky.feed.post/abc#",
+                &aliases,
+                &DEFAULT_RESERVED_SUFFIXES
+            ),
+            None
+        );
+        assert_eq!(
+            validate_aturi(
+                "at://alice.test/app.bsky.feed.post/abc#a#b",
+                &aliases,
+                &DEFAULT_RESERVED_SUFFIXES
+            ),
+            None
+        );
+        assert_eq!(
+            validate_aturi(
+                "at://alice.test/app.bsky.feed.post/abc#a b",
+                &aliases,
+                &DEFAULT_RESERVED_SUFFIXES
+            ),
+            None
+        );
+        assert_eq!(
+            validate_aturi("at://#main", &aliases, &DEFAULT_RESERVED_SUFFIXES),
+            None
+        );
+    }
+
+    proptest::proptest! {
+        /// Any input either fails validation or yields an AT-URI whose
+        /// `Display` form validates back to the same AT-URI.
+        #[test]
+        fn test_validate_aturi_roundtrip(
+            input in proptest::prop_oneof![
+                proptest::collection::vec(proptest::num::u8::ANY, 0..64)
+                    .prop_map(|bytes| String::from_utf8_lossy(&bytes).into_owned()),
+                "\\PC{0,64}",
+                " ?(web\\+)?at://[a-zA-Z0-9.:%#/_~é -]{0,48}",
+                "at://(did:(plc|web):)?[a-z0-9.%:-]{1,24}(/[a-zA-Z0-9.-]{0,24}(/[a-zA-Z0-9._~:-]{0,16})?)?(#[ -~]{0,8})?",
+            ]
+        ) {
+            if let Some(aturi) = validate_aturi(input.as_str(), &HashMap::new(), &DEFAULT_RESERVED_SUFFIXES) {
+                let reparsed = validate_aturi(aturi.to_string(), &HashMap::new(), &DEFAULT_RESERVED_SUFFIXES);
+                proptest::prop_assert_eq!(reparsed, Some(aturi));
+            }
+        }
+    }
+
+    /// Cases from the atproto interop test files for NSID syntax.
+    #[test]
+    fn test_is_strict_nsid() {
+        let valid = [
+            "com.example.fooBar",
+            "net.users.bob.ping",
+            "a-0.b-1.c",
+            "a.b.c",
+            "com.example.fooBarV2",
+            "cn.8.lex.stuff",
+            "org.4chan.lex.getThing",
+            "onion.expyuzz4wqqyqhjn.spec.getThing",
+            "app.bsky.feed.post",
+            "com.atproto.repo.createRecord",
+        ];
+        for nsid in valid {
+            assert!(is_strict_nsid(nsid), "{}", nsid);
+            assert!(is_valid_nsid(nsid), "{}", nsid);
+        }
+
+        let invalid = [
+            "com.exa💩ple.thing",
+            "com.example",
+            "com.example.3",
+            "com.example.3foo",
+            "com.example.foo-bar",
+            "com.example.foo.*",
+            "com.-example.foo",
+            "com.example-.foo",
+            "com..example.foo",
+            ".com.example.foo",
+            "com.example.foo.",
+            "1com.example.foo",
+        ];
+        for nsid in invalid {
+            assert!(!is_strict_nsid(nsid), "{}", nsid);
+        }
+        assert!(!is_strict_nsid(&format!("com.{}.foo", "a".repeat(64))));
+
+        // The lenient check accepts what the grammar doesn't, such as an
+        // uppercase authority or a hyphenated name.
+        for nsid in [
+            "COM.EXAMPLE.fooBar",
+            "com.example.foo-bar",
+            "1com.example.foo",
+        ] {
+            assert!(is_valid_nsid(nsid), "{}", nsid);
+            assert!(!is_strict_nsid(nsid), "{}", nsid);
+        }
+    }
+
+    /// Cases from the atproto interop test files for handle syntax.
+    #[test]
+    fn test_is_strict_handle() {
+        let long_label = format!("{}.test", "a".repeat(63));
+        let valid = [
+            "A.ISI.EDU",
+            "XX.LCS.MIT.EDU",
+            "john.test",
+            "jan.test",
+            "john2.test",
+            "john-john.test",
+            "xn--ls8h.test",
+            "8.cn",
+            "a.co",
+            "example.t",
+            "12345.test",
+            "laptop.local",
+            long_label.as_str(),
+        ];
+        for handle in valid {
+            assert!(is_strict_handle(handle), "{}", handle);
+        }
+
+        let too_long_label = format!("{}.test", "a".repeat(64));
+        let too_long = format!("{}.test", vec!["a".repeat(63); 4].join("."));
+        let invalid = [
+            "jo@hn.test",
+            "💩.test",
+            "john..test",
+            "xn--bcher-.tld",
+            "john.0",
+            "cn.8",
+            "www.masełkowski.pl.com",
+            "org",
+            "name.org.",
+            ".john.test",
+            "-john.test",
+            too_long_label.as_str(),
+            too_long.as_str(),
+        ];
+        for handle in invalid {
+            assert!(!is_strict_handle(handle), "{}", handle);
+        }
+    }
+}
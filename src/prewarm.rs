@@ -0,0 +1,155 @@
+use futures_util::{stream, StreamExt};
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    cache::{webhostmeta_cached, Caches},
+    config::Config,
+    fetch::HttpFetcher,
+    resolve::{parse_servers, resolve},
+};
+
+/// How many prewarm entries are resolved at the same time.
+pub const PREWARM_CONCURRENCY: usize = 8;
+
+/// Prewarming is abandoned after this long so a slow server cannot delay
+/// startup indefinitely.
+pub const PREWARM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Resolves the configured prewarm entries into the caches. Hostnames have
+/// their host-meta fetched, and AT-URIs are resolved against the default
+/// servers, as requests without a `server` parameter are. Failures are logged
+/// and otherwise ignored.
+pub async fn prewarm<F: HttpFetcher + ?Sized>(http_client: &F, caches: &Caches, config: &Config) {
+    let entries = config.prewarm.as_ref();
+    if entries.is_empty() {
+        return;
+    }
+
+    let (aturis, hostnames): (Vec<&String>, Vec<&String>) =
+        entries.iter().partition(|entry| entry.starts_with("at://"));
+
+    let servers = &parse_servers(
+        "",
+        *config.max_servers.as_ref(),
+        config.reserved_suffixes.as_ref(),
+    );
+
+    tracing::info!(
+        "Prewarming {} hostnames and {} AT-URIs",
+        hostnames.len(),
+        aturis.len()
+    );
+
+    let completed = &AtomicUsize::new(0);
+    let run = async {
+        stream::iter(hostnames)
+            .for_each_concurrent(PREWARM_CONCURRENCY, |hostname| async move {
+                if !config.is_host_allowed(hostname) {
+                    tracing::warn!(hostname, "prewarm skipped disallowed host");
+                    return;
+                }
+                if let Err(err) = webhostmeta_cached(
+                    caches.webhostmeta.as_ref(),
+                    http_client,
+                    config.resolver_base.as_ref(),
+                    config.host_meta_path.as_ref(),
+                    hostname,
+                )
+                .await
+                {
+                    tracing::warn!(hostname, error = ?err, "prewarm host-meta failed");
+                }
+                completed.fetch_add(1, Ordering::Relaxed);
+            })
+            .await;
+
+        stream::iter(aturis)
+            .for_each_concurrent(PREWARM_CONCURRENCY, |aturi| async move {
+                if let Err(err) = resolve(http_client, caches, config, servers, aturi).await {
+                    tracing::warn!(aturi, error = ?err, "prewarm resolution failed");
+                }
+                completed.fetch_add(1, Ordering::Relaxed);
+            })
+            .await;
+    };
+
+    if tokio::time::timeout(PREWARM_TIMEOUT, run).await.is_err() {
+        tracing::warn!("Prewarm timed out after {:?}", PREWARM_TIMEOUT);
+    }
+
+    tracing::info!(
+        "Prewarmed {} of {} entries",
+        completed.load(Ordering::Relaxed),
+        entries.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::prewarm;
+    use crate::{
+        cache::Caches,
+        config::Config,
+        fetch::tests::MockFetcher,
+        resolve::{parse_servers, resolve},
+    };
+
+    #[tokio::test]
+    async fn test_prewarm() {
+        let path = std::env::temp_dir().join(format!("hopper-prewarm-{}.txt", std::process::id()));
+        std::fs::write(
+            &path,
+            "# hot set\nlinks.example\n\nat://alice.example/example.links.entry/abc\n",
+        )
+        .unwrap();
+        let config = Config::from_toml(&format!(
+            "external_base = \"hopper.test\"\nprewarm_file = \"{}\"",
+            path.display()
+        ));
+        std::fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+        assert_eq!(config.prewarm.as_ref().len(), 2);
+
+        let host_meta = r#"{
+  "links": [
+    {
+      "rel": "http://hopper.at/rel/link",
+      "template": "https://bsky.app/{identity}/{rkey}",
+      "properties": { "http://hopper.at/ns/collection": "example.links.entry" }
+    }
+  ]
+}"#;
+        let http_fetcher = MockFetcher::new([
+            ("https://links.example/.well-known/host-meta.json", "{}"),
+            ("https://bsky.app/.well-known/host-meta.json", host_meta),
+        ]);
+        let caches = Caches::new();
+
+        prewarm(&http_fetcher, &caches, &config).await;
+
+        assert!(caches.webhostmeta.get("links.example").await.is_some());
+        assert!(caches.webhostmeta.get("bsky.app").await.is_some());
+
+        // The AT-URI is answered from the cache without fetching again.
+        let requests = http_fetcher.requests.lock().unwrap().len();
+        let resolution = resolve(
+            &http_fetcher,
+            &caches,
+            &config,
+            &parse_servers(
+                "",
+                *config.max_servers.as_ref(),
+                config.reserved_suffixes.as_ref(),
+            ),
+            "at://alice.example/example.links.entry/abc",
+        )
+        .await
+        .unwrap();
+        assert_eq!(resolution.destination, "https://bsky.app/alice.example/abc");
+        assert_eq!(resolution.matched_server, None);
+        assert_eq!(http_fetcher.requests.lock().unwrap().len(), requests);
+    }
+}
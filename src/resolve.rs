@@ -0,0 +1,854 @@
+use anyhow::{anyhow, Result};
+use futures_util::future::join_all;
+use ordermap::OrderSet;
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::{
+    cache::{aturi_cached, webhostmeta_cached, Caches},
+    config::Config,
+    fetch::HttpFetcher,
+    model::{is_strict_aturi, is_valid_hostname, validate_aturi, AtUri},
+    webhostmeta::{query, NS_COLLECTION},
+};
+
+pub(crate) const ERROR_INVALID_AT_URI: &str = "error-web-invalid-aturi Invalid AT-URI";
+pub(crate) const ERROR_INVALID_DESTINATION: &str =
+    "error-web-invalid-destination Invalid destination";
+pub(crate) const ERROR_INVALID_URL: &str = "error-web-invalid-url Invalid URL";
+pub(crate) const ERROR_UNSUPPORTED_URL: &str = "error-web-unsupported-url Unsupported URL";
+
+/// Servers tried after any supplied by the user.
+pub const DEFAULT_SERVERS: [&str; 4] = [
+    "smokesignal.events",
+    "frontpage.fyi",
+    "whtwnd.com",
+    "bsky.app",
+];
+
+/// Where an AT-URI resolved to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resolution {
+    pub destination: String,
+
+    /// The server whose host-meta produced the destination. This is `None`
+    /// when the destination was served from the cache.
+    pub matched_server: Option<String>,
+
+    /// The collection of the matched link, or of the AT-URI when the
+    /// destination was served from the cache.
+    pub collection: Option<String>,
+
+    /// The relation of the matched link.
+    pub rel: Option<String>,
+
+    /// The template the destination was produced from.
+    pub template: Option<String>,
+}
+
+impl Resolution {
+    /// Returns true when the destination was served from the cache.
+    pub fn is_cached(&self) -> bool {
+        self.matched_server.is_none()
+    }
+}
+
+/// Validates `input` as an AT-URI. Input longer than the configured maximum is
+/// rejected before it is parsed.
+fn validate_input(config: &Config, input: &str) -> Result<AtUri> {
+    if input.len() > *config.max_input_length.as_ref() {
+        return Err(anyhow!(ERROR_INVALID_AT_URI));
+    }
+    let aturi = validate_aturi(
+        input,
+        config.collection_aliases.as_ref(),
+        config.reserved_suffixes.as_ref(),
+    )
+    .ok_or(anyhow!(ERROR_INVALID_AT_URI))?;
+    if *config.strict_validation.as_ref() && !is_strict_aturi(&aturi) {
+        return Err(anyhow!(ERROR_INVALID_AT_URI));
+    }
+    Ok(aturi)
+}
+
+/// Validates `input` as an AT-URI and resolves it against `servers`, in
+/// order of preference.
+pub async fn resolve<F: HttpFetcher + ?Sized>(
+    http_client: &F,
+    caches: &Caches,
+    config: &Config,
+    servers: &[String],
+    input: &str,
+) -> Result<Resolution> {
+    let aturi = validate_input(config, input)?;
+
+    let resolution = aturi_cached(
+        http_client,
+        caches.webhostmeta.as_ref(),
+        caches.aturi.as_ref(),
+        config,
+        servers,
+        input,
+        &aturi,
+    )
+    .await?;
+
+    let resolution = with_fragment(config, &aturi, resolution);
+    Ok(with_append_query(config, resolution))
+}
+
+/// Adds the configured `append_query` parameters to the destination. A
+/// parameter the destination already has keeps its value.
+fn with_append_query(config: &Config, mut resolution: Resolution) -> Resolution {
+    let append_query = config.append_query.as_ref();
+    if append_query.is_empty() {
+        return resolution;
+    }
+    let Ok(mut url) = url::Url::parse(&resolution.destination) else {
+        return resolution;
+    };
+    let existing = url
+        .query_pairs()
+        .map(|(key, _)| key.into_owned())
+        .collect::<HashSet<String>>();
+    let missing = append_query
+        .iter()
+        .filter(|(key, _)| !existing.contains(key))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        url.query_pairs_mut().extend_pairs(missing);
+        resolution.destination = url.into();
+    }
+    resolution
+}
+
+/// Carries the fragment of `aturi` into the destination when
+/// `propagate_fragment` is enabled. Cached destinations never include it, as
+/// the cache key leaves the fragment out.
+fn with_fragment(config: &Config, aturi: &AtUri, mut resolution: Resolution) -> Resolution {
+    let Some(fragment) = aturi.fragment() else {
+        return resolution;
+    };
+    if !*config.propagate_fragment.as_ref() {
+        return resolution;
+    }
+    if let Ok(mut url) = url::Url::parse(&resolution.destination) {
+        url.set_fragment(Some(fragment));
+        resolution.destination = url.into();
+    }
+    resolution
+}
+
+/// How an AT-URI was resolved against each server, for diagnosing links that
+/// don't resolve as expected.
+#[derive(Debug, Serialize)]
+pub struct ResolutionTrace {
+    pub aturi: String,
+    pub destination: Option<String>,
+    pub servers: Vec<ServerTrace>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServerTrace {
+    pub server: String,
+
+    /// One of `disallowed`, `error`, `timeout`, or `fetched`.
+    pub status: &'static str,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    pub links: Vec<LinkTrace>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LinkTrace {
+    pub rel: String,
+    pub template: Option<String>,
+    pub collection: Option<String>,
+
+    /// The destination when the link matched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+
+    /// Why the link didn't match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'static str>,
+}
+
+/// Resolves `input` against every server without using or filling the
+/// caches, recording what each host-meta fetch returned and whether each of
+/// its links matched. The destination is the one `resolve` would pick.
+pub async fn trace<F: HttpFetcher + ?Sized>(
+    http_client: &F,
+    config: &Config,
+    servers: &[String],
+    input: &str,
+) -> Result<ResolutionTrace> {
+    let aturi = validate_input(config, input)?;
+    let rels = config.link_rels.as_ref();
+
+    let servers = join_all(servers.iter().map(|server| async {
+        let mut server_trace = ServerTrace {
+            server: server.clone(),
+            status: "fetched",
+            error: None,
+            links: vec![],
+        };
+        if !config.is_host_allowed(server) {
+            server_trace.status = "disallowed";
+            return server_trace;
+        }
+
+        let fetched = tokio::time::timeout(
+            *config.server_timeout.as_ref(),
+            query(
+                http_client,
+                config.resolver_base.as_ref(),
+                config.host_meta_path.as_ref(),
+                server,
+            ),
+        )
+        .await;
+        let webhostmeta = match fetched {
+            Ok(Ok(webhostmeta)) => webhostmeta,
+            Ok(Err(err)) => {
+                server_trace.status = "error";
+                server_trace.error = Some(format!("{:#}", err));
+                return server_trace;
+            }
+            Err(_) => {
+                server_trace.status = "timeout";
+                return server_trace;
+            }
+        };
+
+        server_trace.links = webhostmeta
+            .links
+            .iter()
+            .map(|link| {
+                let matched = link.match_uri(server, &aturi, rels);
+                LinkTrace {
+                    rel: link.rel.clone(),
+                    template: link.template.clone(),
+                    collection: link.properties.get(NS_COLLECTION).cloned(),
+                    destination: matched.as_ref().ok().map(|r| r.destination.clone()),
+                    reason: matched.err(),
+                }
+            })
+            .collect();
+        server_trace
+    }))
+    .await;
+
+    let destination = servers
+        .iter()
+        .flat_map(|server| server.links.iter())
+        .find_map(|link| link.destination.clone());
+
+    Ok(ResolutionTrace {
+        aturi: input.to_string(),
+        destination,
+        servers,
+    })
+}
+
+/// Finds the AT-URI that resolves to `input`, a URL on a server whose
+/// host-meta links can be read in reverse. Only the server the URL is on is
+/// consulted.
+pub async fn reverse<F: HttpFetcher + ?Sized>(
+    http_client: &F,
+    caches: &Caches,
+    config: &Config,
+    input: &str,
+) -> Result<String> {
+    if input.len() > *config.max_input_length.as_ref() {
+        return Err(anyhow!(ERROR_INVALID_URL));
+    }
+    let url = url::Url::parse(input.trim()).map_err(|_| anyhow!(ERROR_INVALID_URL))?;
+    let server = url
+        .host_str()
+        .filter(|host| is_valid_hostname(host, config.reserved_suffixes.as_ref()))
+        .filter(|_| url.scheme() == "https" && url.port().is_none())
+        .ok_or(anyhow!(ERROR_INVALID_URL))?;
+    if !config.is_host_allowed(server) {
+        return Err(anyhow!(ERROR_UNSUPPORTED_URL));
+    }
+
+    let webhostmeta = tokio::time::timeout(
+        *config.server_timeout.as_ref(),
+        webhostmeta_cached(
+            caches.webhostmeta.as_ref(),
+            http_client,
+            config.resolver_base.as_ref(),
+            config.host_meta_path.as_ref(),
+            server,
+        ),
+    )
+    .await;
+    let webhostmeta = match webhostmeta {
+        Ok(Ok(webhostmeta)) => webhostmeta,
+        Ok(Err(err)) => {
+            tracing::debug!(server, error = ?err, "host-meta unavailable for reverse");
+            return Err(anyhow!(ERROR_UNSUPPORTED_URL));
+        }
+        Err(_) => {
+            tracing::debug!(server, "host-meta timed out for reverse");
+            return Err(anyhow!(ERROR_UNSUPPORTED_URL));
+        }
+    };
+
+    webhostmeta
+        .reverse_uri(
+            server,
+            url.as_str(),
+            config.link_rels.as_ref(),
+            config.reserved_suffixes.as_ref(),
+        )
+        .ok_or(anyhow!(ERROR_UNSUPPORTED_URL))
+}
+
+/// Parses a comma separated list of servers, keeping at most `max_servers`
+/// valid hostnames outside `reserved_suffixes`, followed by the default
+/// servers.
+pub fn parse_servers<S: AsRef<str>>(
+    value: &str,
+    max_servers: usize,
+    reserved_suffixes: &[S],
+) -> Vec<String> {
+    let mut values = value
+        .split(',')
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty() && is_valid_hostname(s, reserved_suffixes))
+        .collect::<OrderSet<String>>();
+    values.truncate(max_servers);
+
+    values.extend(DEFAULT_SERVERS.map(String::from));
+
+    Vec::from_iter(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::get, Json, Router};
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+
+    use super::{
+        parse_servers, resolve, trace, Resolution, DEFAULT_SERVERS, ERROR_INVALID_AT_URI,
+        ERROR_INVALID_DESTINATION,
+    };
+    use crate::{
+        cache::{new_resolve_aturi_cache, Caches, ResolveWebHostMetaResult},
+        config::Config,
+        fetch::tests::MockFetcher,
+        model::DEFAULT_RESERVED_SUFFIXES,
+        webhostmeta::{Link, WebHostMeta, REL_LINK},
+    };
+
+    #[test]
+    fn test_parse_servers() {
+        assert_eq!(
+            parse_servers("", 8, &DEFAULT_RESERVED_SUFFIXES),
+            DEFAULT_SERVERS
+        );
+
+        assert_eq!(
+            parse_servers(
+                "a.example,B.example, b.EXAMPLE ,a.example",
+                8,
+                &DEFAULT_RESERVED_SUFFIXES
+            ),
+            ["a.example", "b.example"]
+                .into_iter()
+                .chain(DEFAULT_SERVERS)
+                .collect::<Vec<&str>>()
+        );
+
+        assert_eq!(
+            parse_servers(
+                "a.example,bad_host!,-b.example,c.example",
+                8,
+                &DEFAULT_RESERVED_SUFFIXES
+            ),
+            ["a.example", "c.example"]
+                .into_iter()
+                .chain(DEFAULT_SERVERS)
+                .collect::<Vec<&str>>()
+        );
+    }
+
+    #[test]
+    fn test_parse_servers_cap() {
+        let value = (0..100)
+            .map(|i| format!("host{}.example", i))
+            .collect::<Vec<String>>()
+            .join(",");
+
+        let servers = parse_servers(&value, 2, &DEFAULT_RESERVED_SUFFIXES);
+        assert_eq!(
+            servers,
+            ["host0.example", "host1.example"]
+                .into_iter()
+                .chain(DEFAULT_SERVERS)
+                .collect::<Vec<&str>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve() {
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+        let caches = Caches::new();
+        caches
+            .webhostmeta
+            .insert(
+                "links.example".to_string(),
+                ResolveWebHostMetaResult::Found(Arc::new(WebHostMeta::new(vec![Link::new(
+                    "https://links.example/{identity}/{rkey}",
+                    Some("example.links.entry"),
+                )]))),
+            )
+            .await;
+        let servers = vec!["links.example".to_string()];
+        let http_client = reqwest::Client::new();
+
+        let resolution = resolve(
+            &http_client,
+            &caches,
+            &config,
+            &servers,
+            "at://alice.example/example.links.entry/abc",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            resolution,
+            Resolution {
+                destination: "https://links.example/alice.example/abc".to_string(),
+                matched_server: Some("links.example".to_string()),
+                collection: Some("example.links.entry".to_string()),
+                rel: Some(REL_LINK.to_string()),
+                template: Some("https://links.example/{identity}/{rkey}".to_string()),
+            }
+        );
+
+        // Later resolutions are served from the cache without provenance.
+        let resolution = resolve(
+            &http_client,
+            &caches,
+            &config,
+            &servers,
+            "at://alice.example/example.links.entry/abc",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            resolution,
+            Resolution {
+                destination: "https://links.example/alice.example/abc".to_string(),
+                matched_server: None,
+                collection: Some("example.links.entry".to_string()),
+                rel: None,
+                template: None,
+            }
+        );
+
+        assert!(
+            resolve(&http_client, &caches, &config, &servers, "alice.example")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_resolver_base() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = Router::new().route(
+            "/.well-known/host-meta.json",
+            get(|| async {
+                Json(WebHostMeta::new(vec![Link::new(
+                    "https://links.example/{identity}",
+                    None,
+                )]))
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config = Config::from_toml(&format!(
+            "external_base = \"hopper.test\"\nresolver_base = \"http://{}\"",
+            address
+        ))
+        .unwrap();
+
+        let resolution = resolve(
+            &reqwest::Client::new(),
+            &Caches::new(),
+            &config,
+            &["links.example".to_string()],
+            "at://alice.example",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            resolution.destination,
+            "https://links.example/alice.example"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_host_meta_path() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = Router::new().route(
+            "/hopper/links.json",
+            get(|| async {
+                Json(WebHostMeta::new(vec![Link::new(
+                    "https://links.example/{identity}",
+                    None,
+                )]))
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config = Config::from_toml(&format!(
+            "external_base = \"hopper.test\"\nresolver_base = \"http://{}\"\nhost_meta_path = \"/hopper/links.json\"",
+            address
+        ))
+        .unwrap();
+
+        let resolution = resolve(
+            &reqwest::Client::new(),
+            &Caches::new(),
+            &config,
+            &["links.example".to_string()],
+            "at://alice.example",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            resolution.destination,
+            "https://links.example/alice.example"
+        );
+
+        assert!(Config::from_toml(
+            "external_base = \"hopper.test\"\nhost_meta_path = \"hopper/links.json\""
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_trace() {
+        let http_fetcher = MockFetcher::new([(
+            "https://links.example/.well-known/host-meta.json",
+            r#"{
+  "links": [
+    { "rel": "http://hopper.at/rel/link", "template": "https://links.example/{identity}" },
+    {
+      "rel": "http://hopper.at/rel/link",
+      "template": "https://links.example/{identity}/{rkey}",
+      "properties": { "http://hopper.at/ns/collection": "example.links.entry" }
+    }
+  ]
+}"#,
+        )]);
+        let config = Config::from_toml(
+            r#"
+external_base = "hopper.test"
+host_denylist = ["blocked.example"]
+"#,
+        )
+        .unwrap();
+        let servers = vec![
+            "blocked.example".to_string(),
+            "missing.example".to_string(),
+            "links.example".to_string(),
+        ];
+
+        let trace = trace(
+            &http_fetcher,
+            &config,
+            &servers,
+            "at://alice.example/example.links.entry/abc",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            trace.destination.as_deref(),
+            Some("https://links.example/alice.example/abc")
+        );
+        assert_eq!(
+            trace
+                .servers
+                .iter()
+                .map(|server| (server.server.as_str(), server.status))
+                .collect::<Vec<_>>(),
+            vec![
+                ("blocked.example", "disallowed"),
+                ("missing.example", "error"),
+                ("links.example", "fetched"),
+            ]
+        );
+        let links = &trace.servers[2].links;
+        assert_eq!(links[0].reason, Some("collection mismatch"));
+        assert_eq!(
+            links[1].destination.as_deref(),
+            Some("https://links.example/alice.example/abc")
+        );
+
+        // Disallowed servers are not fetched.
+        assert_eq!(http_fetcher.requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_max_input_length() {
+        let config = Config::from_toml(
+            r#"
+external_base = "hopper.test"
+max_input_length = 64
+"#,
+        )
+        .unwrap();
+        let aturi_cache = new_resolve_aturi_cache();
+        let caches = Caches {
+            aturi: Arc::new(aturi_cache.clone()),
+            ..Caches::new()
+        };
+        let http_fetcher = MockFetcher::new([]);
+
+        let input = format!("at://{}.example", "a".repeat(64));
+        let err = resolve(&http_fetcher, &caches, &config, &[], &input)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), ERROR_INVALID_AT_URI);
+        assert!(http_fetcher.requests.lock().unwrap().is_empty());
+        assert_eq!(aturi_cache.entry_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_empty_identity() {
+        let config = Config::from_toml("external_base = \"hopper.test\"").unwrap();
+        let caches = Caches::new();
+        let http_fetcher = MockFetcher::new([]);
+
+        for input in ["at:///app.bsky.feed.post/abc", "at://"] {
+            let err = resolve(&http_fetcher, &caches, &config, &[], input)
+                .await
+                .unwrap_err();
+            assert_eq!(err.to_string(), ERROR_INVALID_AT_URI, "{}", input);
+        }
+        assert!(http_fetcher.requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_fragment() {
+        let caches = Caches::new();
+        caches
+            .webhostmeta
+            .insert(
+                "links.example".to_string(),
+                ResolveWebHostMetaResult::Found(Arc::new(WebHostMeta::new(vec![Link::new(
+                    "https://links.example/{identity}/{rkey}",
+                    Some("example.links.entry"),
+                )]))),
+            )
+            .await;
+        let servers = vec!["links.example".to_string()];
+        let http_fetcher = MockFetcher::new([]);
+        let input = "at://alice.example/example.links.entry/abc#main";
+
+        // The fragment is dropped by default.
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+        let resolution = resolve(&http_fetcher, &caches, &config, &servers, input)
+            .await
+            .unwrap();
+        assert_eq!(
+            resolution.destination,
+            "https://links.example/alice.example/abc"
+        );
+
+        // The cached destination is shared with the fragment-less AT-URI and
+        // gets the fragment when it is propagated.
+        let config = Config::from_toml(
+            r#"
+external_base = "hopper.test"
+propagate_fragment = true
+"#,
+        )
+        .unwrap();
+        let resolution = resolve(&http_fetcher, &caches, &config, &servers, input)
+            .await
+            .unwrap();
+        assert_eq!(resolution.matched_server, None);
+        assert_eq!(
+            resolution.destination,
+            "https://links.example/alice.example/abc#main"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_append_query() {
+        let config = Config::from_toml(
+            r#"
+external_base = "hopper.test"
+
+[append_query]
+id = "replaced"
+utm_medium = "redirect"
+utm_source = "hopper & co"
+"#,
+        )
+        .unwrap();
+        let caches = Caches::new();
+        caches
+            .webhostmeta
+            .insert(
+                "links.example".to_string(),
+                ResolveWebHostMetaResult::Found(Arc::new(WebHostMeta::new(vec![Link::new(
+                    "https://links.example/entry?by={identity_query}&id={rkey}",
+                    Some("example.links.entry"),
+                )]))),
+            )
+            .await;
+        let servers = vec!["links.example".to_string()];
+        let http_fetcher = MockFetcher::new([]);
+
+        // Existing parameters are kept, and the rest are appended encoded.
+        for _ in 0..2 {
+            let resolution = resolve(
+                &http_fetcher,
+                &caches,
+                &config,
+                &servers,
+                "at://alice.example/example.links.entry/abc",
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                resolution.destination,
+                "https://links.example/entry?by=alice.example&id=abc&utm_medium=redirect&utm_source=hopper+%26+co"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_strict_validation() {
+        let caches = Caches::new();
+        let http_fetcher = MockFetcher::new([]);
+        let input = "at://alice.example/com.example.foo-bar/abc";
+
+        // Lenient validation accepts the hyphenated name, so resolution goes
+        // on to find no server supporting it.
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+        let err = resolve(&http_fetcher, &caches, &config, &[], input)
+            .await
+            .unwrap_err();
+        assert_ne!(err.to_string(), ERROR_INVALID_AT_URI);
+
+        let config = Config::from_toml(
+            r#"
+external_base = "hopper.test"
+strict_validation = true
+"#,
+        )
+        .unwrap();
+        let err = resolve(&http_fetcher, &caches, &config, &[], input)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), ERROR_INVALID_AT_URI);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_reserved_suffixes() {
+        let caches = Caches::new();
+        caches
+            .webhostmeta
+            .insert(
+                "links.example".to_string(),
+                ResolveWebHostMetaResult::Found(Arc::new(WebHostMeta::new(vec![Link::new(
+                    "https://links.example/{identity}/{rkey}",
+                    Some("example.links.entry"),
+                )]))),
+            )
+            .await;
+        let servers = vec!["links.example".to_string()];
+        let http_fetcher = MockFetcher::new([]);
+        let input = "at://alice.internal/example.links.entry/abc";
+
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+        let err = resolve(&http_fetcher, &caches, &config, &servers, input)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), ERROR_INVALID_AT_URI);
+
+        // Handles under a suffix the operator no longer reserves resolve.
+        let config = Config::from_toml(
+            r#"
+external_base = "hopper.test"
+reserved_suffixes = [".local"]
+"#,
+        )
+        .unwrap();
+        let resolution = resolve(&http_fetcher, &caches, &config, &servers, input)
+            .await
+            .unwrap();
+        assert_eq!(
+            resolution.destination,
+            "https://links.example/alice.internal/abc"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resolve_insecure_destination() {
+        let caches = Caches::new();
+        caches
+            .webhostmeta
+            .insert(
+                "links.example".to_string(),
+                ResolveWebHostMetaResult::Found(Arc::new(WebHostMeta::new(vec![
+                    Link::new(
+                        "http://links.example/{identity}/{rkey}",
+                        Some("example.links.entry"),
+                    ),
+                    Link::new(
+                        "https://links.example/{identity}/{rkey}",
+                        Some("example.links.note"),
+                    ),
+                ]))),
+            )
+            .await;
+        let servers = vec!["links.example".to_string()];
+        let http_fetcher = MockFetcher::new([]);
+        let config = Config::from_toml(r#"external_base = "hopper.test""#).unwrap();
+
+        let err = resolve(
+            &http_fetcher,
+            &caches,
+            &config,
+            &servers,
+            "at://alice.example/example.links.entry/abc",
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.to_string(), ERROR_INVALID_DESTINATION);
+
+        // An rkey can't move the destination off the server.
+        let resolution = resolve(
+            &http_fetcher,
+            &caches,
+            &config,
+            &servers,
+            "at://alice.example/example.links.note/@evil.com",
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            resolution.destination,
+            "https://links.example/alice.example/%40evil.com"
+        );
+        let url = url::Url::parse(&resolution.destination).unwrap();
+        assert_eq!(url.host_str(), Some("links.example"));
+        assert_eq!(url.username(), "");
+    }
+}
@@ -0,0 +1,115 @@
+use anyhow::Result;
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_sdk::{
+    runtime,
+    trace::{Tracer, TracerProvider},
+    Resource,
+};
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// The standard OTLP variable naming the collector that spans are sent to.
+pub const OTEL_EXPORTER_OTLP_ENDPOINT: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Returns a provider that exports spans over OTLP/HTTP, or `None` when no
+/// collector endpoint is configured.
+pub fn tracer_provider() -> Result<Option<TracerProvider>> {
+    if std::env::var(OTEL_EXPORTER_OTLP_ENDPOINT).map_or(true, |endpoint| endpoint.is_empty()) {
+        return Ok(None);
+    }
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()?;
+
+    Ok(Some(
+        TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_resource(Resource::new(vec![KeyValue::new("service.name", "hopper")]))
+            .build(),
+    ))
+}
+
+/// Returns a layer that records tracing spans with the provider's tracer.
+pub fn layer<S>(provider: &TracerProvider) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer("hopper"))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::future::BoxFuture;
+    use opentelemetry::Value;
+    use opentelemetry_sdk::{
+        export::trace::{ExportResult, SpanData, SpanExporter},
+        trace::TracerProvider,
+    };
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+    use tracing_subscriber::prelude::*;
+
+    use super::layer;
+    use crate::{
+        cache::{aturi_cached, new_resolve_aturi_cache, new_resolve_webhostmeta_cache},
+        config::Config,
+        model::{validate_aturi, DEFAULT_RESERVED_SUFFIXES},
+    };
+
+    #[derive(Clone, Debug, Default)]
+    struct InMemoryExporter(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanExporter for InMemoryExporter {
+        fn export(&mut self, batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+            self.0.lock().unwrap().extend(batch);
+            Box::pin(std::future::ready(Ok(())))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_aturi_span() {
+        let exporter = InMemoryExporter::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let _guard = tracing_subscriber::registry()
+            .with(layer(&provider))
+            .set_default();
+
+        let config = Config::from_toml(
+            r#"
+external_base = "hopper.test"
+host_denylist = ["disallowed.example"]
+"#,
+        )
+        .unwrap();
+        let webfinger_cache = new_resolve_webhostmeta_cache();
+        let aturi_input = "at://ngerakines.me";
+        let aturi =
+            validate_aturi(aturi_input, &HashMap::new(), &DEFAULT_RESERVED_SUFFIXES).unwrap();
+        let destination = aturi_cached(
+            &reqwest::Client::new(),
+            &webfinger_cache,
+            &new_resolve_aturi_cache(),
+            &config,
+            &["disallowed.example".to_string()],
+            aturi_input,
+            &aturi,
+        )
+        .await;
+        assert!(destination.is_err());
+
+        let spans = exporter.0.lock().unwrap();
+        let span = spans
+            .iter()
+            .find(|span| span.name == "resolve_aturi")
+            .unwrap();
+        assert!(span.attributes.iter().any(|attribute| {
+            attribute.key.as_str() == "outcome" && attribute.value == Value::from("not_found")
+        }));
+    }
+}
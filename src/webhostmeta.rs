@@ -1,41 +1,244 @@
 use anyhow::{Context, Result};
-use serde::Deserialize;
-use std::collections::HashMap;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use urlencoding::decode;
 
-use crate::model::AtUri;
+use crate::{
+    fetch::HttpFetcher,
+    model::{apply_template, identity_from_path, validate_aturi, AtUri},
+    resolve::Resolution,
+};
 
 pub const REL_LINK: &str = "http://hopper.at/rel/link";
+
+/// Where host-meta documents are fetched from unless `HOPPER_HOST_META_PATH`
+/// says otherwise.
+pub const DEFAULT_HOST_META_PATH: &str = "/.well-known/host-meta.json";
 pub const NS_COLLECTION: &str = "http://hopper.at/ns/collection";
+pub const NS_DID_METHOD: &str = "http://hopper.at/ns/did-method";
+
+/// Link properties in this namespace are constraints on the AT-URI. Other
+/// properties are informational and ignored when matching.
+const NS_PREFIX: &str = "http://hopper.at/ns/";
+
+/// The versioned link relation from the published spec, accepted alongside
+/// `REL_LINK` by default.
+pub const REL_SPEC_LINK: &str = "https://hopper.at/spec/schema/1.0/link";
+
+/// Why a link that applies to an AT-URI was refused for where its
+/// destination points.
+const REASON_NOT_ON_SERVER: &str = "template not on server";
+const REASON_INVALID_URL: &str = "destination is not a valid URL";
+const REASON_ESCAPED_SERVER: &str = "template escaped server";
+
+/// Why no link of a host-meta document resolved an AT-URI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum NoMatch {
+    /// No link applies to the AT-URI.
+    Unsupported,
+
+    /// A link applies, but its destination isn't an https URL on the server.
+    InsecureDestination,
+}
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct Link {
     pub(crate) rel: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) template: Option<String>,
 
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub(crate) properties: HashMap<String, String>,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct WebHostMeta {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub(crate) properties: HashMap<String, String>,
 
     #[serde(default)]
     pub(crate) links: Vec<Link>,
 }
 
-pub(crate) async fn query(http_client: &reqwest::Client, hostname: &str) -> Result<WebHostMeta> {
-    let url = format!("https://{}/.well-known/host-meta.json", hostname,);
+/// Host-meta documents for well-known servers, used in place of fetching
+/// them and published as hopper's own host-meta.
+pub fn seeded() -> Vec<(String, WebHostMeta)> {
+    vec![
+        (
+            "bsky.app".to_string(),
+            WebHostMeta::new(vec![
+                Link::new("https://bsky.app/profile/{identity}", None),
+                Link::new(
+                    "https://bsky.app/profile/{identity}/post/{rkey}",
+                    Some("app.bsky.feed.post"),
+                ),
+            ]),
+        ),
+        (
+            "frontpage.fyi".to_string(),
+            WebHostMeta::new(vec![Link::new(
+                "https://frontpage.fyi/post/{identity}/{rkey}",
+                Some("fyi.unravel.frontpage.post"),
+            )]),
+        ),
+        (
+            "whtwnd.com".to_string(),
+            WebHostMeta::new(vec![Link::new(
+                "https://whtwnd.com/{identity}/{rkey}",
+                Some("com.whtwnd.blog.entry"),
+            )]),
+        ),
+    ]
+}
 
-    http_client
-        .get(url)
-        .send()
-        .await
-        .context("web host meta get failed")?
-        .json()
+/// Attributes of an AT-URI that link property constraints are matched
+/// against. A constraint on an attribute the AT-URI doesn't have fails.
+fn aturi_attributes(aturi: &AtUri) -> HashMap<&'static str, String> {
+    let mut attributes = HashMap::new();
+    if let Some(did_method) = aturi
+        .identity
+        .strip_prefix("did:")
+        .and_then(|did| did.split(':').next())
+    {
+        attributes.insert(NS_DID_METHOD, did_method.to_string());
+    }
+    attributes
+}
+
+/// Servers that negotiate content types should answer with JRD rather than
+/// an HTML page.
+const ACCEPT_JRD: &str = "application/jrd+json, application/json";
+
+/// Fetches the host-meta document of `hostname` from `host_meta_path` under
+/// `resolver_base`, a base URL where `{host}` is replaced with the hostname.
+/// When the document can't be fetched, an `lrdd` `Link` header on the origin
+/// root is followed instead, as RFC 6415 allows.
+pub(crate) async fn query<F: HttpFetcher + ?Sized>(
+    http_fetcher: &F,
+    resolver_base: &str,
+    host_meta_path: &str,
+    hostname: &str,
+) -> Result<WebHostMeta> {
+    let base = resolver_base.replace("{host}", hostname);
+    let url = format!("{}{}", base, host_meta_path);
+
+    let err = match fetch_descriptor(http_fetcher, &url).await {
+        Ok(webhostmeta) => return Ok(webhostmeta),
+        Err(err) => err,
+    };
+
+    let root = format!("{}/", base);
+    let Some(descriptor) = discover_lrdd(http_fetcher, &root).await else {
+        return Err(err);
+    };
+    tracing::debug!(hostname, descriptor, "following lrdd link");
+    fetch_descriptor(http_fetcher, &descriptor).await
+}
+
+async fn fetch_descriptor<F: HttpFetcher + ?Sized>(
+    http_fetcher: &F,
+    url: &str,
+) -> Result<WebHostMeta> {
+    let body = http_fetcher
+        .get_text(url, ACCEPT_JRD)
         .await
-        .context("web host meta parse failed")
+        .context("web host meta get failed")?;
+    serde_json::from_str(&body).context("web host meta parse failed")
+}
+
+/// Returns the target of the first `lrdd` link advertised by `root`. Only
+/// targets on the same origin are followed, so a server can't point hopper
+/// at another host.
+async fn discover_lrdd<F: HttpFetcher + ?Sized>(http_fetcher: &F, root: &str) -> Option<String> {
+    let root = url::Url::parse(root).ok()?;
+    let links = http_fetcher.get_links(root.as_str()).await.ok()?;
+    links
+        .iter()
+        .flat_map(|value| parse_link_header(value))
+        .filter(|(_, rels)| rels.iter().any(|rel| rel.eq_ignore_ascii_case("lrdd")))
+        .filter_map(|(target, _)| root.join(&target).ok())
+        .find(|target| target.origin() == root.origin())
+        .map(String::from)
+}
+
+/// Parses a `Link` header value into its targets and their relation types.
+fn parse_link_header(value: &str) -> Vec<(String, Vec<String>)> {
+    let mut links = Vec::new();
+    let mut rest = value.trim();
+    while let Some(after_open) = rest.strip_prefix('<') {
+        let Some((target, after_target)) = after_open.split_once('>') else {
+            break;
+        };
+        let (params, next) = match after_target.find(',') {
+            Some(index) => (&after_target[..index], &after_target[index + 1..]),
+            None => (after_target, ""),
+        };
+        let rels = params
+            .split(';')
+            .filter_map(|param| param.split_once('='))
+            .filter(|(name, _)| name.trim().eq_ignore_ascii_case("rel"))
+            .flat_map(|(_, value)| {
+                value
+                    .trim()
+                    .trim_matches('"')
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        links.push((target.trim().to_string(), rels));
+        rest = next.trim();
+    }
+    links
+}
+
+/// Template placeholders and patterns matching the values substituted for
+/// them. An `{identity}` may span did:web path segments, so it matches
+/// lazily.
+const PLACEHOLDER_PATTERNS: [(&str, &str); 5] = [
+    ("identity", "[^?#]+?"),
+    ("identity_raw", "[^/?#]+"),
+    ("identity_query", "[^&#]+"),
+    ("collection", "[^/?#]+"),
+    ("rkey", "[^/?#]+"),
+];
+
+/// Compiles a link template into an anchored regex with a named group for
+/// the first occurrence of each placeholder. Repeated placeholders only match
+/// the same pattern, so a match still has to be checked by applying the
+/// template again.
+fn template_regex(template: &str) -> Option<Regex> {
+    let mut pattern = String::from("^");
+    let mut captured = HashSet::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        pattern.push_str(&regex::escape(&rest[..start]));
+        rest = &rest[start..];
+        let placeholder = rest[1..].split_once('}').and_then(|(name, after)| {
+            PLACEHOLDER_PATTERNS
+                .iter()
+                .find(|(placeholder, _)| *placeholder == name)
+                .map(|placeholder| (placeholder, after))
+        });
+        match placeholder {
+            Some(((name, value_pattern), after)) => {
+                if captured.insert(*name) {
+                    pattern.push_str(&format!("(?<{}>{})", name, value_pattern));
+                } else {
+                    pattern.push_str(&format!("(?:{})", value_pattern));
+                }
+                rest = after;
+            }
+            None => {
+                pattern.push_str(&regex::escape("{"));
+                rest = &rest[1..];
+            }
+        }
+    }
+    pattern.push_str(&regex::escape(rest));
+    pattern.push('$');
+    Regex::new(&pattern).ok()
 }
 
 impl Link {
@@ -49,55 +252,199 @@ impl Link {
             properties,
         }
     }
-}
 
-impl WebHostMeta {
-    pub fn new(links: Vec<Link>) -> Self {
-        Self {
-            properties: Default::default(),
-            links,
+    /// Resolves `aturi` with this link, or returns why the link doesn't
+    /// match it.
+    pub(crate) fn match_uri(
+        &self,
+        server: &str,
+        aturi: &AtUri,
+        rels: &[String],
+    ) -> Result<Resolution, &'static str> {
+        if !rels.contains(&self.rel) {
+            return Err("rel not accepted");
         }
-    }
 
-    pub(crate) fn match_uri(&self, server: &str, aturi: &AtUri) -> Option<String> {
-        let prefix = format!("https://{}/", server);
-        for link in &self.links {
-            if link.rel != REL_LINK {
-                continue;
-            }
+        let Some(template) = self.template.as_ref() else {
+            return Err("no template");
+        };
 
-            if link.template.is_none() {
-                continue;
-            }
+        let attributes = aturi_attributes(aturi);
+        let constraints_met = self
+            .properties
+            .iter()
+            .filter(|(key, _)| key.starts_with(NS_PREFIX) && key.as_str() != NS_COLLECTION)
+            .all(|(key, value)| attributes.get(key.as_str()) == Some(value));
+        if !constraints_met {
+            return Err("property constraint not met");
+        }
 
-            let template = link.template.as_ref().unwrap();
+        let result = apply_template(
+            template,
+            aturi,
+            self.properties.get(NS_COLLECTION).map(String::as_str),
+        )?;
 
-            if !template.starts_with(prefix.as_str()) {
-                continue;
-            }
+        // The link applies to the AT-URI from here on, so the reasons below
+        // are all insecure destinations.
+        if !template.starts_with(&format!("https://{}/", server)) {
+            return Err(REASON_NOT_ON_SERVER);
+        }
+
+        // The destination is sent as a Location header, so it must already be
+        // a valid absolute URL. A result the parser would have to normalize,
+        // such as one with spaces, is rejected rather than rewritten.
+        let Ok(url) = url::Url::parse(&result) else {
+            tracing::debug!(server, destination = result, "destination is not a URL");
+            return Err(REASON_INVALID_URL);
+        };
+        if url.as_str() != result {
+            tracing::debug!(server, destination = result, "destination is not a URL");
+            return Err(REASON_INVALID_URL);
+        }
+
+        // The substituted values must not change where the link points.
+        let points_at_server =
+            url.scheme() == "https" && url.host_str() == Some(server) && url.port().is_none();
+        if !points_at_server {
+            tracing::debug!(server, destination = result, "template escaped server");
+            return Err(REASON_ESCAPED_SERVER);
+        }
+
+        Ok(Resolution {
+            destination: result,
+            matched_server: Some(server.to_string()),
+            collection: self.properties.get(NS_COLLECTION).cloned(),
+            rel: Some(self.rel.clone()),
+            template: Some(template.clone()),
+        })
+    }
 
-            let matching_collection = aturi.collection.clone().unwrap_or("identity".to_string());
-            let compare_collection = link
+    /// Reads `url` back into the AT-URI this link would resolve to it, by
+    /// matching the URL against the template and substituting the extracted
+    /// components. The AT-URI is only returned if resolving it with this
+    /// link gives `url` again, and its identity is valid with
+    /// `reserved_suffixes`.
+    pub(crate) fn reverse_uri<S: AsRef<str>>(
+        &self,
+        server: &str,
+        url: &str,
+        rels: &[String],
+        reserved_suffixes: &[S],
+    ) -> Option<String> {
+        if !rels.contains(&self.rel) {
+            return None;
+        }
+        let template = self.template.as_ref()?;
+        let captures = template_regex(template)?.captures(url)?;
+        let capture = |name| captures.name(name).map(|value| value.as_str());
+
+        let identity = if let Some(identity) = capture("identity") {
+            identity_from_path(identity)?
+        } else if let Some(identity) = capture("identity_raw") {
+            identity.to_string()
+        } else {
+            decode(&capture("identity_query")?.replace('+', " "))
+                .ok()?
+                .into_owned()
+        };
+        let collection = match capture("collection") {
+            Some(collection) => Some(decode(collection).ok()?.into_owned()),
+            None => self
                 .properties
                 .get(NS_COLLECTION)
-                .map(|value| value.to_string())
-                .unwrap_or("identity".to_string());
+                .filter(|collection| collection.as_str() != "identity")
+                .cloned(),
+        };
+        let rkey = match capture("rkey") {
+            Some(rkey) => Some(decode(rkey).ok()?.into_owned()),
+            None => None,
+        };
 
-            if compare_collection != matching_collection {
-                continue;
-            }
+        let aturi = match (collection, rkey) {
+            (None, None) => format!("at://{}", identity),
+            (Some(collection), None) => format!("at://{}/{}", identity, collection),
+            (Some(collection), Some(rkey)) => format!("at://{}/{}/{}", identity, collection, rkey),
+            (None, Some(_)) => return None,
+        };
+        let resolution = self
+            .match_uri(
+                server,
+                &validate_aturi(aturi.as_str(), &HashMap::new(), reserved_suffixes)?,
+                rels,
+            )
+            .ok()?;
+        (resolution.destination == url).then_some(aturi)
+    }
+}
 
-            let mut result = template.replace("{identity}", &aturi.identity);
-            if let Some(collection) = &aturi.collection {
-                result = result.replace("{collection}", collection);
-            }
-            if let Some(nsid) = &aturi.rkey {
-                result = result.replace("{rkey}", nsid);
-            }
+impl WebHostMeta {
+    pub fn new(links: Vec<Link>) -> Self {
+        Self {
+            properties: Default::default(),
+            links,
+        }
+    }
 
-            return Some(result);
+    /// Estimates the memory held by the document, in bytes, from the length of
+    /// its relations, templates, and properties.
+    pub(crate) fn estimated_size(&self) -> usize {
+        let properties_size = |properties: &HashMap<String, String>| {
+            properties
+                .iter()
+                .map(|(key, value)| key.len() + value.len())
+                .sum::<usize>()
+        };
+        properties_size(&self.properties)
+            + self
+                .links
+                .iter()
+                .map(|link| {
+                    link.rel.len()
+                        + link.template.as_ref().map_or(0, String::len)
+                        + properties_size(&link.properties)
+                })
+                .sum::<usize>()
+    }
+
+    /// Resolves `aturi` with the first link of the document that matches it.
+    /// When none does, a link that applied but pointed off the server is
+    /// reported over the AT-URI being unsupported.
+    pub(crate) fn match_uri(
+        &self,
+        server: &str,
+        aturi: &AtUri,
+        rels: &[String],
+    ) -> Result<Resolution, NoMatch> {
+        let mut no_match = NoMatch::Unsupported;
+        for link in &self.links {
+            match link.match_uri(server, aturi, rels) {
+                Ok(resolution) => return Ok(resolution),
+                Err(REASON_NOT_ON_SERVER | REASON_INVALID_URL | REASON_ESCAPED_SERVER) => {
+                    no_match = NoMatch::InsecureDestination;
+                }
+                Err(_) => {}
+            }
         }
-        None
+        Err(no_match)
+    }
+
+    /// Returns the AT-URI that a link of the document resolves to `url`.
+    /// When several links do, as a profile template can also match a post
+    /// URL with a did:web identity, the AT-URI with the most components wins,
+    /// then the earliest link.
+    pub(crate) fn reverse_uri<S: AsRef<str>>(
+        &self,
+        server: &str,
+        url: &str,
+        rels: &[String],
+        reserved_suffixes: &[S],
+    ) -> Option<String> {
+        self.links
+            .iter()
+            .filter_map(|link| link.reverse_uri(server, url, rels, reserved_suffixes))
+            .rev()
+            .max_by_key(|aturi| aturi.matches('/').count())
     }
 }
 
@@ -105,7 +452,15 @@ impl WebHostMeta {
 mod tests {
     use std::collections::HashMap;
 
-    use super::{Link, WebHostMeta};
+    use crate::{
+        fetch::tests::MockFetcher,
+        model::{validate_aturi, AtUri, DEFAULT_RESERVED_SUFFIXES},
+    };
+
+    use super::{
+        parse_link_header, query, seeded, Link, NoMatch, WebHostMeta, DEFAULT_HOST_META_PATH,
+        NS_DID_METHOD, REL_LINK, REL_SPEC_LINK,
+    };
 
     #[test]
     fn test_deserialize() {
@@ -131,6 +486,7 @@ mod tests {
 
     #[test]
     fn test_match_uri() {
+        let rels = vec![REL_LINK.to_string()];
         let hostname = "smokesignal.events".to_string();
         let web_finger1 = WebHostMeta {
             links: vec![Link {
@@ -145,27 +501,496 @@ mod tests {
         };
 
         assert_eq!(
-            web_finger1.match_uri(
-                &hostname,
-                &crate::model::AtUri {
-                    identity: "ngerakines.me".to_string(),
-                    collection: None,
-                    rkey: None,
-                }
+            web_finger1
+                .match_uri(
+                    &hostname,
+                    &crate::model::AtUri {
+                        identity: "ngerakines.me".to_string(),
+                        collection: None,
+                        rkey: None,
+                        fragment: None,
+                    },
+                    &rels
+                )
+                .map(|resolution| resolution.destination),
+            Ok("https://smokesignal.events/ngerakines.me".into())
+        );
+
+        assert_eq!(
+            web_finger1
+                .match_uri(
+                    &hostname,
+                    &crate::model::AtUri {
+                        identity: "smokesignal.events".to_string(),
+                        collection: Some("event".into()),
+                        rkey: Some("s0xnr5kqnp".into()),
+                        fragment: None,
+                    },
+                    &rels
+                )
+                .map(|resolution| resolution.destination),
+            Err(NoMatch::Unsupported),
+        );
+    }
+
+    #[test]
+    fn test_match_uri_missing_rkey() {
+        let rels = vec![REL_LINK.to_string()];
+        let hostname = "bsky.app".to_string();
+        let web_host_meta = WebHostMeta::new(vec![Link::new(
+            "https://bsky.app/profile/{identity}/post/{rkey}",
+            Some("app.bsky.feed.post"),
+        )]);
+
+        assert_eq!(
+            web_host_meta
+                .match_uri(
+                    &hostname,
+                    &crate::model::AtUri {
+                        identity: "ngerakines.me".to_string(),
+                        collection: Some("app.bsky.feed.post".into()),
+                        rkey: None,
+                        fragment: None,
+                    },
+                    &rels
+                )
+                .map(|resolution| resolution.destination),
+            Err(NoMatch::Unsupported)
+        );
+
+        assert_eq!(
+            web_host_meta
+                .match_uri(
+                    &hostname,
+                    &crate::model::AtUri {
+                        identity: "ngerakines.me".to_string(),
+                        collection: Some("app.bsky.feed.post".into()),
+                        rkey: Some("3l3qo2vuowo2b".into()),
+                        fragment: None,
+                    },
+                    &rels
+                )
+                .map(|resolution| resolution.destination),
+            Ok("https://bsky.app/profile/ngerakines.me/post/3l3qo2vuowo2b".into())
+        );
+    }
+
+    #[test]
+    fn test_match_uri_encodes_values() {
+        let rels = vec![REL_LINK.to_string()];
+        let hostname = "smokesignal.events".to_string();
+        let web_host_meta = WebHostMeta::new(vec![Link::new(
+            "https://smokesignal.events/{identity}/{rkey}",
+            Some("events.smokesignal.calendar.event"),
+        )]);
+
+        assert_eq!(
+            web_host_meta
+                .match_uri(
+                    &hostname,
+                    &crate::model::AtUri {
+                        identity: "did:web:example.com".to_string(),
+                        collection: Some("events.smokesignal.calendar.event".into()),
+                        rkey: Some("3l3q~o2vu".into()),
+                        fragment: None,
+                    },
+                    &rels
+                )
+                .map(|resolution| resolution.destination),
+            Ok("https://smokesignal.events/did%3Aweb%3Aexample.com/3l3q~o2vu".into())
+        );
+    }
+
+    #[test]
+    fn test_match_uri_alternate_rel() {
+        let hostname = "smokesignal.events".to_string();
+        let web_host_meta = WebHostMeta {
+            links: vec![Link {
+                rel: REL_SPEC_LINK.to_string(),
+                template: Some("https://smokesignal.events/{identity}".to_string()),
+                properties: Default::default(),
+            }],
+            properties: Default::default(),
+        };
+        let aturi = crate::model::AtUri {
+            identity: "ngerakines.me".to_string(),
+            collection: None,
+            rkey: None,
+            fragment: None,
+        };
+
+        assert_eq!(
+            web_host_meta
+                .match_uri(&hostname, &aturi, &[REL_LINK.to_string()])
+                .map(|resolution| resolution.destination),
+            Err(NoMatch::Unsupported)
+        );
+        assert_eq!(
+            web_host_meta
+                .match_uri(
+                    &hostname,
+                    &aturi,
+                    &[REL_LINK.to_string(), REL_SPEC_LINK.to_string()]
+                )
+                .map(|resolution| resolution.destination),
+            Ok("https://smokesignal.events/ngerakines.me".into())
+        );
+    }
+
+    #[test]
+    fn test_match_uri_did_method() {
+        let hostname = "plc.example".to_string();
+        let rels = vec![REL_LINK.to_string()];
+        let mut link = Link::new("https://plc.example/{identity}", None);
+        link.properties
+            .insert(NS_DID_METHOD.to_string(), "plc".to_string());
+        let web_host_meta = WebHostMeta::new(vec![link]);
+
+        let aturi = |identity: &str| crate::model::AtUri {
+            identity: identity.to_string(),
+            collection: None,
+            rkey: None,
+            fragment: None,
+        };
+
+        assert_eq!(
+            web_host_meta
+                .match_uri(&hostname, &aturi("did:plc:cbkjy5n7bk3ax2wplmtjofq2"), &rels)
+                .map(|resolution| resolution.destination),
+            Ok("https://plc.example/did%3Aplc%3Acbkjy5n7bk3ax2wplmtjofq2".into())
+        );
+        assert_eq!(
+            web_host_meta
+                .match_uri(&hostname, &aturi("did:web:example.com"), &rels)
+                .map(|resolution| resolution.destination),
+            Err(NoMatch::Unsupported)
+        );
+        assert_eq!(
+            web_host_meta
+                .match_uri(&hostname, &aturi("ngerakines.me"), &rels)
+                .map(|resolution| resolution.destination),
+            Err(NoMatch::Unsupported)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_accept_header() {
+        let http_fetcher = MockFetcher::new([(
+            "https://smokesignal.events/.well-known/host-meta.json",
+            r#"{"links": []}"#,
+        )]);
+
+        let webhostmeta = query(
+            &http_fetcher,
+            "https://{host}",
+            DEFAULT_HOST_META_PATH,
+            "smokesignal.events",
+        )
+        .await
+        .unwrap();
+        assert!(webhostmeta.links.is_empty());
+        assert_eq!(
+            *http_fetcher.requests.lock().unwrap(),
+            vec![(
+                "https://smokesignal.events/.well-known/host-meta.json".to_string(),
+                "application/jrd+json, application/json".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_match_uri_https_only() {
+        let hostname = "smokesignal.events".to_string();
+        let rels = vec![REL_LINK.to_string()];
+        let aturi = crate::model::AtUri {
+            identity: "ngerakines.me".to_string(),
+            collection: Some("events.smokesignal.calendar.event".into()),
+            rkey: Some("@evil.com".into()),
+            fragment: None,
+        };
+
+        let web_host_meta = WebHostMeta::new(vec![Link::new(
+            "http://smokesignal.events/{identity}/{rkey}",
+            Some("events.smokesignal.calendar.event"),
+        )]);
+        assert_eq!(
+            web_host_meta
+                .match_uri(&hostname, &aturi, &rels)
+                .map(|resolution| resolution.destination),
+            Err(NoMatch::InsecureDestination)
+        );
+
+        // The rkey is encoded, so it can't become the userinfo of the URL.
+        let web_host_meta = WebHostMeta::new(vec![Link::new(
+            "https://smokesignal.events/{identity}/{rkey}",
+            Some("events.smokesignal.calendar.event"),
+        )]);
+        assert_eq!(
+            web_host_meta
+                .match_uri(&hostname, &aturi, &rels)
+                .map(|resolution| resolution.destination),
+            Ok("https://smokesignal.events/ngerakines.me/%40evil.com".into())
+        );
+    }
+
+    #[test]
+    fn test_match_uri_collection_listing() {
+        // The listing template is listed first so that order alone doesn't
+        // pick the right one.
+        let webhostmeta = WebHostMeta::new(vec![
+            Link::new(
+                "https://example.com/{identity}/{collection}",
+                Some("app.bsky.feed.post"),
+            ),
+            Link::new(
+                "https://example.com/{identity}/{collection}/{rkey}",
+                Some("app.bsky.feed.post"),
             ),
-            Some("https://smokesignal.events/ngerakines.me".into())
+        ]);
+        let rels = vec![REL_LINK.to_string()];
+
+        let aturi = AtUri {
+            identity: "alice.test".to_string(),
+            collection: Some("app.bsky.feed.post".into()),
+            rkey: None,
+            fragment: None,
+        };
+        assert_eq!(
+            webhostmeta
+                .match_uri("example.com", &aturi, &rels)
+                .map(|resolution| resolution.destination),
+            Ok("https://example.com/alice.test/app.bsky.feed.post".to_string())
         );
 
+        let aturi = AtUri {
+            rkey: Some("abc".into()),
+            fragment: None,
+            ..aturi
+        };
         assert_eq!(
-            web_finger1.match_uri(
-                &hostname,
-                &crate::model::AtUri {
-                    identity: "smokesignal.events".to_string(),
-                    collection: Some("event".into()),
-                    rkey: Some("s0xnr5kqnp".into()),
-                }
+            webhostmeta
+                .match_uri("example.com", &aturi, &rels)
+                .map(|resolution| resolution.destination),
+            Ok("https://example.com/alice.test/app.bsky.feed.post/abc".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_link_header() {
+        assert_eq!(
+            parse_link_header(
+                r#"</meta.json>; rel="lrdd"; type="application/json", <https://links.example/>; rel="canonical alternate""#
+            ),
+            vec![
+                ("/meta.json".to_string(), vec!["lrdd".to_string()]),
+                (
+                    "https://links.example/".to_string(),
+                    vec!["canonical".to_string(), "alternate".to_string()]
+                ),
+            ]
+        );
+        assert!(parse_link_header("not a link").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_query_lrdd() {
+        let descriptor = r#"{"links": [{"rel": "http://hopper.at/rel/link", "template": "https://links.example/{rkey}"}]}"#;
+        let http_fetcher = MockFetcher::new([("https://links.example/meta/host.json", descriptor)])
+            .with_link(
+                "https://links.example/",
+                r#"<https://links.example/meta/host.json>; rel="lrdd"; type="application/json""#,
+            );
+
+        let webhostmeta = query(
+            &http_fetcher,
+            "https://{host}",
+            DEFAULT_HOST_META_PATH,
+            "links.example",
+        )
+        .await
+        .unwrap();
+        assert_eq!(webhostmeta.links.len(), 1);
+
+        // The well-known document stays primary.
+        let http_fetcher = MockFetcher::new([
+            ("https://links.example/.well-known/host-meta.json", "{}"),
+            ("https://links.example/meta/host.json", descriptor),
+        ])
+        .with_link("https://links.example/", "</meta/host.json>; rel=lrdd");
+        let webhostmeta = query(
+            &http_fetcher,
+            "https://{host}",
+            DEFAULT_HOST_META_PATH,
+            "links.example",
+        )
+        .await
+        .unwrap();
+        assert!(webhostmeta.links.is_empty());
+
+        // Descriptors on other hosts are not followed.
+        let http_fetcher = MockFetcher::new([("https://other.example/host.json", descriptor)])
+            .with_link(
+                "https://links.example/",
+                r#"<https://other.example/host.json>; rel="lrdd""#,
+            );
+        assert!(query(
+            &http_fetcher,
+            "https://{host}",
+            DEFAULT_HOST_META_PATH,
+            "links.example"
+        )
+        .await
+        .is_err());
+        assert_eq!(http_fetcher.requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_match_uri_invalid_destination() {
+        let rels = vec![REL_LINK.to_string()];
+        let aturi = AtUri {
+            identity: "alice.example".to_string(),
+            collection: Some("example.links.entry".to_string()),
+            rkey: Some("abc".to_string()),
+            fragment: None,
+        };
+
+        let link = Link::new(
+            "https://links.example/entries of/{rkey}",
+            Some("example.links.entry"),
+        );
+        assert_eq!(
+            link.match_uri("links.example", &aturi, &rels)
+                .map(|resolution| resolution.destination),
+            Err("destination is not a valid URL")
+        );
+
+        // A malformed link is skipped in favor of a later valid one.
+        let web_host_meta = WebHostMeta::new(vec![
+            link,
+            Link::new(
+                "https://links.example/entries/{rkey}",
+                Some("example.links.entry"),
+            ),
+        ]);
+        assert_eq!(
+            web_host_meta
+                .match_uri("links.example", &aturi, &rels)
+                .map(|resolution| resolution.destination),
+            Ok("https://links.example/entries/abc".to_string())
+        );
+    }
+
+    #[test]
+    fn test_reverse_uri_seeded() {
+        let rels = vec![REL_LINK.to_string()];
+        let seeded = seeded().into_iter().collect::<HashMap<_, _>>();
+        let aturis = [
+            ("bsky.app", "at://alice.example"),
+            ("bsky.app", "at://did:plc:ewvi7nxzyoun6zhxrhs64oiz"),
+            (
+                "bsky.app",
+                "at://alice.example/app.bsky.feed.post/3l3q2kavqw22f",
+            ),
+            (
+                "bsky.app",
+                "at://did:web:example.com:user:alice/app.bsky.feed.post/abc",
+            ),
+            (
+                "frontpage.fyi",
+                "at://alice.example/fyi.unravel.frontpage.post/3l3q~o2vu",
+            ),
+            (
+                "whtwnd.com",
+                "at://did:web:example.com:blog/com.whtwnd.blog.entry/abc",
+            ),
+        ];
+        for (server, aturi) in aturis {
+            let web_host_meta = &seeded[server];
+            let destination = web_host_meta
+                .match_uri(
+                    server,
+                    &validate_aturi(aturi, &HashMap::new(), &DEFAULT_RESERVED_SUFFIXES).unwrap(),
+                    &rels,
+                )
+                .unwrap()
+                .destination;
+            assert_eq!(
+                web_host_meta.reverse_uri(server, &destination, &rels, &DEFAULT_RESERVED_SUFFIXES),
+                Some(aturi.to_string()),
+                "{}",
+                destination
+            );
+        }
+
+        // URLs the templates don't produce aren't reversed.
+        let bsky = &seeded["bsky.app"];
+        for url in [
+            "https://bsky.app/",
+            "https://bsky.app/profile/alice.example/post",
+            "https://bsky.app/profile/alice.example/post/abc?ref=share",
+            "https://bsky.app/profile/alice.example/feed/abc",
+            "https://bsky.app/profile/not%20a%20handle",
+        ] {
+            assert_eq!(
+                bsky.reverse_uri("bsky.app", url, &rels, &DEFAULT_RESERVED_SUFFIXES),
+                None,
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn test_reverse_uri_placeholders() {
+        let rels = vec![REL_LINK.to_string()];
+
+        let link = Link::new(
+            "https://links.example/{collection}?by={identity_query}&id={rkey}",
+            Some("example.links.entry"),
+        );
+        assert_eq!(
+            link.reverse_uri(
+                "links.example",
+                "https://links.example/example.links.entry?by=did%3Aplc%3Aewvi7nxzyoun6zhxrhs64oiz&id=xyz",
+                &rels,
+                &DEFAULT_RESERVED_SUFFIXES
+            ),
+            Some("at://did:plc:ewvi7nxzyoun6zhxrhs64oiz/example.links.entry/xyz".to_string())
+        );
+
+        // A repeated placeholder has to agree with its first occurrence.
+        let link = Link::new(
+            "https://links.example/{identity_raw}/{rkey}#{identity_raw}",
+            Some("example.links.entry"),
+        );
+        assert_eq!(
+            link.reverse_uri(
+                "links.example",
+                "https://links.example/alice.example/abc#alice.example",
+                &rels,
+                &DEFAULT_RESERVED_SUFFIXES
+            ),
+            Some("at://alice.example/example.links.entry/abc".to_string())
+        );
+        assert_eq!(
+            link.reverse_uri(
+                "links.example",
+                "https://links.example/alice.example/abc#bob.example",
+                &rels,
+                &DEFAULT_RESERVED_SUFFIXES
+            ),
+            None
+        );
+
+        // Links with other relations aren't reversed.
+        assert_eq!(
+            link.reverse_uri(
+                "links.example",
+                "https://links.example/alice.example/abc#alice.example",
+                &[REL_SPEC_LINK.to_string()],
+                &DEFAULT_RESERVED_SUFFIXES
             ),
-            None,
+            None
         );
     }
 }